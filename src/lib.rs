@@ -0,0 +1,9306 @@
+/*!
+ * Hecto 编辑器 - 一个基于终端的文本编辑器
+ * 
+ * 编辑器的全部功能在这个库中实现，`main.rs` 只负责解析命令行参数并启动 `Editor`。
+ * 行的编辑操作（`Row`）、位置和选择（`Position`、`Selection`）以及语法规则的查找
+ * 公开给外部使用，不需要终端即可测试
+ * 
+ * # 主要功能
+ * - 基本的文本编辑（插入、删除、复制、粘贴）
+ * - 文件操作（打开、保存）
+ * - 搜索和替换（支持实时预览）
+ * - 语法高亮（按扩展名支持 Rust、Python、C、JavaScript）
+ * - 文本选择（支持鼠标和键盘）
+ * - 系统剪贴板集成
+ * - 用户配置（`~/.config/hecto/config.toml`，见 `Config`）
+ * - 启动时用 `文件:行` 或 `文件:行:列` 直接跳转到指定位置
+ * - 每次运行中第一次覆盖文件前创建备份（配置文件中设置 `backup = true`，备份名默认为文件名加 `~`）
+ * - 记住每个文件的光标位置，再次打开时回到上次离开的地方（可以在配置文件中用 `remember_positions` 关闭）
+
+ * # 快捷键
+ * - Ctrl-Q：退出
+ * - Ctrl-S：保存
+ * - Ctrl-Alt-S：强制保存（即使没有修改）
+ * - Alt-A：另存为
+ * - Ctrl-O：打开其它文件（替换当前缓冲区）
+ * - Ctrl-N：新建空文档（替换当前缓冲区）
+ * - Ctrl-R：从磁盘重新加载当前文件，放弃未保存的修改（需确认）
+ * - Ctrl-P：命令行，支持以下命令：
+ *   - `w` 保存，`q` 退出，`wq` / `x` 保存并退出，`q!` 放弃修改并退出
+ *   - `e <文件>` 打开文件，`goto <行号>`（或直接输入行号）跳转到指定行
+ *   - `set tabwidth=<N>` 设置制表符宽度，`set filetype=<类型>` 设置文件类型，`noh` 清除搜索高亮
+ *   - `fold` 折叠（与 Alt-F 相同），`unfold` 展开光标所在行的折叠
+ *   - `s/查找/替换/` 替换光标之后的下一处匹配，加 `g` 后缀时替换全部匹配
+ * - Ctrl-F：搜索
+ * - F3 / Shift-F3：查找上一次搜索文本的下一处/上一处匹配
+ * - Ctrl-E：替换光标之后的下一处匹配
+ * - Ctrl-Alt-E：替换全部匹配
+ * - Ctrl-C：复制
+ * - Ctrl-X：剪切
+ * - Ctrl-V：粘贴
+ * - Ctrl-A：全选
+ * - Ctrl-D：复制当前行（或选中的多行）
+ * - Alt-Up / Alt-Down：上移/下移当前行（或选中的多行）
+ * - Ctrl-/：注释/取消注释当前行（或选中的多行）
+ * - Tab / Shift-Tab：缩进/取消缩进选中的多行
+ * - Alt-S：切换 Tab 键插入空格（软制表符）或制表符，默认插入制表符
+ * - Ctrl-Z：撤销
+ * - Ctrl-Y：重做
+ * - Insert：切换插入/覆盖模式
+ * - Alt-D / Alt-U：向下/向上滚动半页
+ * - Ctrl-B：切换十六进制视图（只读；文件没有未保存的修改时显示磁盘上的原始字节）
+ * - Alt-I：查看光标处字符的 Unicode 信息
+ * - Alt-T：手动设置文件类型（也可以在配置文件中用 `filetype.<文件名模式> = "<类型>"` 按文件名指定）
+ * - Ctrl-L：显示/隐藏行号
+ * - Ctrl-G：跳转到指定行
+ * - Ctrl-]：跳转到与光标旁括号匹配的括号
+ * - Ctrl-Left / Ctrl-Right：按单词移动光标（同时按 Shift 扩展选择）
+ * - Alt-0 … Alt-9：输入重复次数，作用于下一个移动命令（如 Alt-5 Down 向下移动五行）
+ * - Backspace / Ctrl-H：删除选中文本或光标前的字符。许多终端按 Backspace 时发送与 Ctrl-H 相同的字节，
+ *   crossterm 会把它报告为 Ctrl-H，因此两者执行相同的操作，无论终端发送哪一种都能正常删除
+ * - Delete：删除选中文本或光标处的字符，在行尾时与下一行合并
+ * - Ctrl-Backspace / Ctrl-Delete：删除光标前/后的一个单词
+ * - Ctrl-K：删除光标到行尾的文本，光标在行尾时与下一行合并
+ * - Ctrl-Alt-K：删除当前行（可以用重复次数删除多行）
+ * - Ctrl-U：插入最近删除的文本。Ctrl-K、删除单词和删除整行删除的文本记入删除环，
+ *   连续的删除合并为一条，与系统剪贴板互不影响
+ * - Alt-W：切换自动换行
+ * - Alt-N：切换到下一个缓冲区
+ * - Alt-B：按编号选择缓冲区
+ * - Alt-R：切换只读模式（也可以用 `--readonly` 参数以只读模式打开）
+ * - Alt-V：切换矩形（列）选择，也可以按住 Alt 用鼠标拖动
+ * - Alt-C：切换配色方案（深色/浅色，默认值可在配置文件中用 `theme` 设置）
+ * - Alt-L：切换空白字符显示（制表符显示为 `→`，行尾空格显示为 `·`，行末显示 `¬`）
+ * - Alt-F：折叠选中的多行，没有选择时折叠光标所在的缩进块；光标在折叠行上时展开。
+ *   折叠后只显示首行和被隐藏的行数，光标移动时整体跳过
+ * - Alt-G：切换缩进参考线（在行首缩进中每一级缩进的位置显示 `│`，也可以在配置文件中用
+ *   `show_indent_guides` 设置）
+ * - Alt-E：切换行尾空白高亮（行尾的空格和制表符以红色背景显示，光标所在行的光标位于行末时不显示，
+ *   也可以在配置文件中用 `highlight_trailing_whitespace` 设置）
+ * - Alt-K：切换拼写检查（注释和字符串中不在词表里的英文单词加下划线，纯文本文件检查全部内容；
+ *   也可以在配置文件中用 `spell_check` 开启，用 `dictionary` 指定词表文件，默认为 `/usr/share/dict/words`）
+ * - Alt-X：切换虚拟空间（光标可以移到行尾之后的空白处，在那里输入时先用空格把行补齐到光标处；
+ *   自动换行时不生效，也可以在配置文件中用 `virtual_space` 开启）
+ * - Alt-H：切换搜索结束后是否继续高亮所有匹配（也可以在配置文件中用 `highlight_search` 设置），
+ *   Esc 或 `noh` 命令清除高亮
+ * 
+ * 以上带 Ctrl / Alt 的快捷键都可以在配置文件中用 `bind.<按键> = "<命令>"` 重新绑定（见 `Config`），
+ * 例如在 Backspace 不会发送 Ctrl-H 的终端上用 `bind.ctrl-h = "replace"` 恢复以前的替换快捷键
+ * 
+ * 在配置文件中设置 `modal_editing = true` 后启用类似 vi 的模式编辑，启动时处于普通模式：
+ * - h / j / k / l：左/下/上/右移动，w / b 按单词移动，0 / $ 移到行首/行尾
+ * - 数字：输入下一个命令的重复次数
+ * - i / a / A / o：进入插入模式（在光标处、光标后、行尾、下方新行），Esc 返回普通模式
+ * - x：删除光标处的字符，dd：删除当前行，u：撤销
+ * - n / N：查找上一次搜索文本的下一处/上一处匹配
+ * - :：命令行，与 Ctrl-P 相同
+ * - 带 Ctrl / Alt 的组合键和方向键等功能键与非模式编辑时相同
+ */
+
+use std::io::{self, stdout, Write};
+use std::time::{Duration, Instant};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyModifiers, KeyEventKind},
+    terminal::{self, ClearType},
+    cursor,
+    style,
+    queue,
+    style::Print,
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use std::fs;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread;
+use clipboard::{ClipboardProvider, ClipboardContext};
+
+const VERSION: &str = "0.1.0";
+const QUIT_TIMES: u8 = 3;  // 退出确认次数，防止意外退出
+const PAGE_OVERLAP: usize = 2;  // 翻页时保留的上下文行数
+const BINARY_SNIFF_LEN: usize = 8192;  // 检测二进制内容时检查的字节数
+const LARGE_FILE_MB: u64 = 64;  // 打开超过此大小（MB）的文件前请求确认的默认阈值
+const HEX_BYTES_PER_LINE: usize = 16;  // 十六进制视图每行显示的字节数
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);  // 进度指示的刷新间隔
+const UNDO_LIMIT: usize = 1000;  // 撤销栈保留的最大操作组数
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);  // 普通状态消息的默认显示时长
+const ERROR_MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);  // 错误消息的默认显示时长
+const TAB_WIDTH: usize = 4;  // 制表符的默认显示宽度
+const MATCH_COUNT_LIMIT: usize = 10_000;  // 搜索时统计匹配数的上限
+const KILL_RING_SIZE: usize = 16;  // 删除环保留的最大记录数
+const BRACKET_SCAN_LINES: usize = 5000;  // 查找匹配括号时最多扫描的行数
+const RENDER_CACHE_LIMIT: usize = 16;  // 每行最多缓存的渲染结果数（自动换行时每个显示段一项）
+const POSITION_HISTORY_SIZE: usize = 200;  // 记住光标位置的最大文件数
+const DICTIONARY_PATH: &str = "/usr/share/dict/words";  // 拼写检查默认使用的系统词表
+
+/// 状态消息结构体，用于显示编辑器底部的状态信息
+/// 
+/// 普通消息和错误消息分别在配置的 `message_timeout` 和 `error_message_timeout` 后自动消失
+/// （默认为 `MESSAGE_TIMEOUT` 和 `ERROR_MESSAGE_TIMEOUT`）；常驻消息（如输入提示）由当前模式持有，模式结束时被清除。
+/// 每次刷新屏幕时由 `draw_message_bar` 统一按 `is_visible_at` 判断是否过期。
+struct StatusMessage {
+    text: String,
+    time: Instant,  // 消息创建时间，用于计算显示持续时间
+    error: bool,  // 是否为错误消息，错误消息显示得更久
+    persistent: bool,  // 是否为常驻消息
+}
+
+impl StatusMessage {
+    fn from(message: String) -> Self {
+        Self {
+            time: Instant::now(),
+            text: message,
+            error: false,
+            persistent: false,
+        }
+    }
+
+    /// 创建错误消息，比普通消息显示得更久
+    fn error(message: String) -> Self {
+        Self {
+            error: true,
+            ..Self::from(message)
+        }
+    }
+
+    /// 创建常驻消息，在所属模式结束前不会超时
+    fn persistent(message: String) -> Self {
+        Self {
+            persistent: true,
+            ..Self::from(message)
+        }
+    }
+
+    /// 消息在给定时刻是否仍应显示，时间由调用者提供以便验证超时
+    /// 
+    /// # 参数
+    /// * `now` - 判断的时刻
+    /// * `timeout` - 普通消息的显示时长
+    /// * `error_timeout` - 错误消息的显示时长
+    fn is_visible_at(&self, now: Instant, timeout: Duration, error_timeout: Duration) -> bool {
+        let timeout = if self.error { error_timeout } else { timeout };
+        self.persistent || now.saturating_duration_since(self.time) < timeout
+    }
+}
+
+/// 长时间操作的进度指示器
+/// 
+/// 定期直接在消息栏绘制完成百分比，不触发整屏刷新，
+/// 避免加载大文件或全文替换时编辑器看起来像卡住了
+struct Progress {
+    label: &'static str,  // 操作名称
+    last_draw: Instant,   // 上次绘制时间，用于限制刷新频率
+}
+
+impl Progress {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            last_draw: Instant::now(),
+        }
+    }
+
+    /// 距上次绘制超过 `PROGRESS_INTERVAL` 时更新消息栏
+    /// 
+    /// 进度只是提示，绘制失败时忽略，不影响正在进行的操作。
+    /// 写终端可能阻塞，调用时不要持有文档的锁
+    /// 
+    /// # 参数
+    /// * `out` - 绘制的目标，通常为 `stdout()`
+    /// * `done` - 已完成的工作量
+    /// * `total` - 总工作量
+    /// * `message_row` - 消息栏所在的终端行
+    fn update(&mut self, out: &mut impl Write, done: usize, total: usize, message_row: usize) {
+        if self.last_draw.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.last_draw = Instant::now();
+        let percent = (done.min(total) * 100).checked_div(total).unwrap_or(100);
+        queue!(
+            out,
+            cursor::MoveTo(0, message_row as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            Print(format!("{}… {}%", self.label, percent))
+        )
+        .and_then(|()| out.flush())
+        .unwrap_or(());
+    }
+}
+
+/// 表示编辑器中的位置信息（光标或偏移）
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: usize,  // 列位置
+    pub y: usize,  // 行位置
+}
+
+/// 文本选择区域的状态
+/// 
+/// # 功能特点
+/// - 支持跨行选择
+/// - 支持从任意方向选择（向前或向后）
+/// - 自动规范化选择范围
+/// - 支持空选择状态
+/// 
+/// # 字段说明
+/// - `start`: 选择的起始位置
+/// - `end`: 选择的结束位置
+/// - `block`: 是否为矩形选择，矩形的左右边界取起止位置所在的显示列
+/// 
+/// # 使用说明
+/// - 使用 `new()` 创建新的选择，初始时起始和结束位置相同
+/// - 使用 `normalized()` 获取规范化的选择范围（确保 start 在 end 之前）
+/// - 使用 `Editor::selection_span()` 获取某一行被选中的范围
+/// - 使用 `is_empty()` 检查是否有实际选择的内容
+/// 
+/// # 示例
+/// ```rust
+/// # use hecto::{Position, Selection};
+/// let mut sel = Selection::new(Position { x: 0, y: 0 });
+/// sel.end = Position { x: 10, y: 0 };  // 选择第一行的前10个字符
+/// assert!(!sel.is_empty());
+/// ```
+#[derive(Clone, Copy)]
+pub struct Selection {
+    pub start: Position,  // 选择起始位置
+    pub end: Position,    // 选择结束位置
+    pub block: bool,      // 是否为矩形选择
+}
+
+impl Selection {
+    pub fn new(start: Position) -> Self {
+        Self {
+            start,
+            end: start,
+            block: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.x == self.end.x && self.start.y == self.end.y
+    }
+
+    // 获取规范化的选择范围（确保 start 在 end 之前）
+    pub fn normalized(&self) -> (Position, Position) {
+        if self.start.y < self.end.y || (self.start.y == self.end.y && self.start.x <= self.end.x) {
+            (self.start, self.end)
+        } else {
+            (self.end, self.start)
+        }
+    }
+}
+
+/// 语法高亮的类型枚举
+/// 
+/// # 功能特点
+/// - 支持多种代码元素的高亮
+/// - 每种类型对应不同的颜色
+/// - 使用 ANSI 颜色代码
+/// - 支持 256 色模式
+/// 
+/// # 变体说明
+/// - `Normal`: 普通文本
+/// - `Number`: 数字字面量
+/// - `String`: 字符串字面量
+/// - `CharLiteral`: 字符字面量
+/// - `Comment`: 注释
+/// - `PrimaryKeywords`: 主要关键字
+/// - `SecondaryKeywords`: 次要关键字
+/// 
+/// # 使用说明
+/// - 通过当前配色方案的 `Theme::color()` 获取对应的 ANSI 颜色代码
+/// - 在渲染时自动应用颜色
+/// - 支持实时语法高亮更新
+/// 
+/// # 示例
+/// ```ignore
+/// let highlight = HighlightType::String;
+/// let color_code = DARK_THEME.color(highlight);  // 返回 46（绿色）
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum HighlightType {
+    Normal,
+    Number,             // 数字
+    String,             // 字符串
+    CharLiteral,        // 字符字面量
+    Comment,            // 注释
+    PrimaryKeywords,    // 主要关键字
+    SecondaryKeywords,  // 次要关键字
+}
+
+/// 语法高亮的配色方案，为每种 `HighlightType` 指定 256 色模式的颜色代码
+/// 
+/// # 字段说明
+/// - `name`: 配色方案名称，用于配置文件和状态栏提示
+/// - 其余字段: 对应高亮类型的前景色
+struct Theme {
+    name: &'static str,       // 配色方案名称
+    normal: u8,               // 普通文本
+    number: u8,               // 数字
+    string: u8,               // 字符串
+    char_literal: u8,         // 字符字面量
+    comment: u8,              // 注释
+    primary_keywords: u8,     // 主要关键字
+    secondary_keywords: u8,   // 次要关键字
+}
+
+impl Theme {
+    /// 获取高亮类型在此配色方案中的颜色代码
+    fn color(&self, highlight: HighlightType) -> u8 {
+        match highlight {
+            HighlightType::Normal => self.normal,
+            HighlightType::Number => self.number,
+            HighlightType::String => self.string,
+            HighlightType::CharLiteral => self.char_literal,
+            HighlightType::Comment => self.comment,
+            HighlightType::PrimaryKeywords => self.primary_keywords,
+            HighlightType::SecondaryKeywords => self.secondary_keywords,
+        }
+    }
+}
+
+/// 默认的深色配色方案，适合深色背景的终端
+static DARK_THEME: Theme = Theme {
+    name: "dark",
+    normal: 255,              // 白色
+    number: 196,              // 红色
+    string: 46,               // 绿色
+    char_literal: 51,         // 青色
+    comment: 242,             // 深灰色
+    primary_keywords: 226,    // 黄色
+    secondary_keywords: 201,  // 洋红色
+};
+
+/// 浅色配色方案，适合浅色背景的终端
+static LIGHT_THEME: Theme = Theme {
+    name: "light",
+    normal: 232,              // 近黑色
+    number: 124,              // 深红色
+    string: 28,               // 深绿色
+    char_literal: 30,         // 深青色
+    comment: 245,             // 灰色
+    primary_keywords: 130,    // 棕色
+    secondary_keywords: 90,   // 深洋红色
+};
+
+/// 所有内置的配色方案，Alt-C 按此顺序循环切换
+static THEMES: [&Theme; 2] = [&DARK_THEME, &LIGHT_THEME];
+
+/// 按名称查找配色方案（不区分大小写）
+fn find_theme(name: &str) -> Option<&'static Theme> {
+    THEMES.iter().copied().find(|theme| theme.name.eq_ignore_ascii_case(name))
+}
+
+/// 文件类型对应的语法高亮规则
+/// 
+/// # 字段说明
+/// - `name`: 文件类型名称，显示在状态栏
+/// - `aliases`: 手动设置文件类型时可用的其他名称
+/// - `extensions`: 按扩展名自动选择时匹配的扩展名
+/// - `primary_keywords` / `secondary_keywords`: 主要和次要关键字
+/// - `line_comment`: 行注释的起始标记
+/// - `block_comment`: 块注释的起始和结束标记
+/// - `string_quotes`: 字符串的引号字符
+/// - `char_quote`: 字符字面量的引号字符
+/// - `highlight_numbers`: 是否高亮数字
+/// 
+/// # 使用说明
+/// - 通过 `find_syntax()` 按名称、`syntax_for_filename()` 按文件名查找内置的语法规则
+/// - 编辑器持有当前生效的规则，行在更新高亮时参考它
+pub struct Syntax {
+    name: &'static str,                               // 文件类型名称
+    aliases: &'static [&'static str],                 // 其他可用名称
+    extensions: &'static [&'static str],              // 匹配的扩展名
+    primary_keywords: &'static [&'static str],        // 主要关键字
+    secondary_keywords: &'static [&'static str],      // 次要关键字
+    line_comment: Option<&'static str>,               // 行注释标记
+    block_comment: Option<(&'static str, &'static str)>,  // 块注释标记
+    string_quotes: &'static [char],                   // 字符串引号
+    char_quote: Option<char>,                         // 字符字面量引号
+    highlight_numbers: bool,                          // 是否高亮数字
+}
+
+impl Syntax {
+    /// 文件类型名称，如 `Rust`
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 检查单词是否为主要关键字
+    fn is_primary_keyword(&self, word: &str) -> bool {
+        self.primary_keywords.contains(&word)
+    }
+
+    /// 检查单词是否为次要关键字
+    fn is_secondary_keyword(&self, word: &str) -> bool {
+        self.secondary_keywords.contains(&word)
+    }
+
+    /// 是否没有注释和字符串规则（如纯文本），拼写检查时整行都是正文
+    fn is_prose(&self) -> bool {
+        self.line_comment.is_none() && self.block_comment.is_none() && self.string_quotes.is_empty()
+    }
+}
+
+/// Rust 语法规则
+static RUST_SYNTAX: Syntax = Syntax {
+    name: "rust",
+    aliases: &["rs"],
+    extensions: &["rs"],
+    primary_keywords: &[
+        "if", "else", "fn", "for", "while", "match", "const", "static", "struct", "enum",
+        "impl", "trait", "type", "mod", "pub", "use", "extern", "crate",
+    ],
+    secondary_keywords: &[
+        "let", "mut", "ref", "return", "self", "Self", "where", "async", "await", "move",
+        "dyn", "box", "in", "as", "break", "continue", "loop",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    string_quotes: &['"'],
+    char_quote: Some('\''),
+    highlight_numbers: true,
+};
+
+/// Python 语法规则
+static PYTHON_SYNTAX: Syntax = Syntax {
+    name: "python",
+    aliases: &["py"],
+    extensions: &["py", "pyw"],
+    primary_keywords: &[
+        "def", "class", "if", "elif", "else", "for", "while", "try", "except", "finally",
+        "with", "import", "from", "lambda", "global", "nonlocal", "assert", "del", "pass",
+    ],
+    secondary_keywords: &[
+        "return", "yield", "raise", "break", "continue", "in", "is", "not", "and", "or", "as",
+        "async", "await", "self", "None", "True", "False",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    string_quotes: &['"', '\''],
+    char_quote: None,
+    highlight_numbers: true,
+};
+
+/// C 语法规则
+static C_SYNTAX: Syntax = Syntax {
+    name: "c",
+    aliases: &["h"],
+    extensions: &["c", "h"],
+    primary_keywords: &[
+        "if", "else", "for", "while", "do", "switch", "case", "default", "struct", "union",
+        "enum", "typedef", "static", "extern", "const", "volatile", "inline", "register",
+        "sizeof", "goto",
+    ],
+    secondary_keywords: &[
+        "int", "char", "short", "long", "float", "double", "void", "signed", "unsigned",
+        "return", "break", "continue", "NULL",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    string_quotes: &['"'],
+    char_quote: Some('\''),
+    highlight_numbers: true,
+};
+
+/// JavaScript 语法规则
+static JAVASCRIPT_SYNTAX: Syntax = Syntax {
+    name: "javascript",
+    aliases: &["js"],
+    extensions: &["js", "mjs", "cjs"],
+    primary_keywords: &[
+        "function", "if", "else", "for", "while", "do", "switch", "case", "default", "class",
+        "extends", "const", "let", "var", "import", "export", "from", "try", "catch",
+        "finally", "new", "delete", "typeof", "instanceof",
+    ],
+    secondary_keywords: &[
+        "return", "break", "continue", "throw", "yield", "async", "await", "this", "super",
+        "in", "of", "null", "undefined", "true", "false",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    string_quotes: &['"', '\'', '`'],
+    char_quote: None,
+    highlight_numbers: true,
+};
+
+/// 纯文本，不进行语法高亮
+static PLAIN_SYNTAX: Syntax = Syntax {
+    name: "text",
+    aliases: &["plain", "txt", "none"],
+    extensions: &["txt"],
+    primary_keywords: &[],
+    secondary_keywords: &[],
+    line_comment: None,
+    block_comment: None,
+    string_quotes: &[],
+    char_quote: None,
+    highlight_numbers: false,
+};
+
+/// 所有内置的语法规则
+static SYNTAXES: [&Syntax; 5] = [
+    &RUST_SYNTAX,
+    &PYTHON_SYNTAX,
+    &C_SYNTAX,
+    &JAVASCRIPT_SYNTAX,
+    &PLAIN_SYNTAX,
+];
+
+/// 根据文件名选择语法规则
+/// 
+/// 先按顺序查找配置文件中 `filetype.<模式>` 的映射，没有匹配时按扩展名选择，
+/// 未知扩展名使用纯文本
+/// 
+/// # 参数
+/// * `filename` - 文件路径
+/// * `filetypes` - 文件名模式到语法规则的映射，见 `glob_match`
+pub fn syntax_for_filename(filename: &str, filetypes: &[(String, &'static Syntax)]) -> &'static Syntax {
+    if let Some(&(_, syntax)) = filetypes.iter().find(|(pattern, _)| glob_match(pattern, filename)) {
+        return syntax;
+    }
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    extension
+        .and_then(|extension| {
+            SYNTAXES
+                .iter()
+                .copied()
+                .find(|syntax| syntax.extensions.contains(&extension.as_str()))
+        })
+        .unwrap_or(&PLAIN_SYNTAX)
+}
+
+/// 判断文件名是否匹配模式，`*` 匹配任意多个字符，`?` 匹配一个字符
+/// 
+/// 模式不含 `/` 时只与路径的最后一部分比较，如 `*.rs.in` 或 `Makefile`
+/// 
+/// # 参数
+/// * `pattern` - 文件名模式
+/// * `filename` - 文件路径
+fn glob_match(pattern: &str, filename: &str) -> bool {
+    let name = if pattern.contains('/') {
+        filename
+    } else {
+        filename.rsplit(['/', '\\']).next().unwrap_or(filename)
+    };
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // 回溯到最近一个 `*`，让它多匹配一个字符
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 按名称或别名查找语法规则（不区分大小写）
+/// 
+/// # 参数
+/// * `name` - 文件类型名称
+pub fn find_syntax(name: &str) -> Option<&'static Syntax> {
+    let name = name.trim().to_lowercase();
+    SYNTAXES
+        .iter()
+        .copied()
+        .find(|syntax| syntax.name == name || syntax.aliases.contains(&name.as_str()))
+}
+
+/// 影响一行渲染结果的全部状态，用作 `Row::render_cache` 的键
+/// 
+/// 选择和括号高亮只记录落在本行的部分，因此光标移动不会使其它行的缓存失效
+#[derive(Clone, PartialEq)]
+struct RenderKey {
+    start: usize,                      // 第一个渲染的字素位置
+    end: usize,                        // 渲染结束的字素位置
+    width: usize,                      // 可用的显示列数
+    search: Option<String>,            // 当前的搜索文本
+    selection: Option<(usize, usize)>, // 本行被选中的字素范围
+    brackets: [Option<usize>; 2],      // 本行需要高亮的括号位置
+    theme: &'static str,               // 配色方案名称
+    tab_width: usize,                  // 制表符的显示宽度
+    show_whitespace: bool,             // 是否显示空白字符
+    show_indent_guides: bool,          // 是否显示缩进参考线
+    trailing_whitespace: bool,         // 本行是否高亮行尾空白
+    spell_check: bool,                 // 是否标出拼写错误
+}
+
+/// 表示编辑器中的一行文本
+/// 
+/// # 功能特点
+/// - 支持 Unicode 字符（包括 CJK）
+/// - 实时语法高亮
+/// - 高效的文本操作（插入、删除、分割）
+/// - 智能制表符处理
+/// 
+/// # 字段说明
+/// - `string`: 行的实际内容，存储为 UTF-8 字符串
+/// - `highlighting`: 每个字符的语法高亮类型
+/// - `len`: 行的长度（按字素计算，支持组合字符）
+/// - `display_len`: 行的显示长度（考虑 CJK 等宽字符）
+/// - `starts_in_comment`: 行首是否位于上一行未结束的块注释中
+/// - `open_comment_at_end`: 行尾是否仍处于未结束的块注释中
+/// - `render_cache`: `Editor::render_row` 的渲染结果缓存，内容或高亮变化时清空
+/// - `folded`: 折叠时隐藏的后续行数。拆分或合并这一行会展开折叠
+/// 
+/// # 性能考虑
+/// - 使用 String 而不是 Vec<char> 以节省内存
+/// - 缓存字符计数以避免重复计算
+/// - 延迟语法高亮更新
+/// - 缓存渲染结果，未修改的行在重绘时不重新渲染
+/// 
+/// # 示例
+/// ```rust
+/// # use hecto::{syntax_for_filename, Row};
+/// let mut row = Row::new("let x = 42;".to_string(), syntax_for_filename("main.rs", &[]));
+/// row.insert(8, '1');  // 变成 "let x = 142;"
+/// assert_eq!(row.as_str(), "let x = 142;");
+/// row.delete(8);       // 恢复为 "let x = 42;"
+/// assert_eq!(row.as_str(), "let x = 42;");
+/// ```
+pub struct Row {
+    string: String,                    // 行的实际内容
+    highlighting: Vec<HighlightType>,  // 每个字符的高亮类型
+    len: usize,                        // 行的长度（按字素计算）
+    display_len: usize,                // 行的显示长度（考虑 CJK 字符宽度）
+    starts_in_comment: bool,           // 行首是否在块注释中
+    open_comment_at_end: bool,         // 行尾块注释是否未结束
+    render_cache: Mutex<Vec<(RenderKey, String)>>,  // 按渲染参数缓存的渲染结果
+    folded: Option<usize>,             // 折叠时隐藏的后续行数
+}
+
+impl Row {
+    /// 创建新的行实例
+    /// 
+    /// # 参数
+    /// * `string` - 行的文本内容
+    /// * `syntax` - 用于高亮的语法规则
+    pub fn new(string: String, syntax: &Syntax) -> Self {
+        let len = string.graphemes(true).count();
+        let display_len = UnicodeWidthStr::width(&string[..]);
+        let mut row = Self {
+            string,
+            highlighting: Vec::new(),
+            len,
+            display_len,
+            starts_in_comment: false,
+            open_comment_at_end: false,
+            render_cache: Mutex::new(Vec::new()),
+            folded: None,
+        };
+        row.update_syntax(syntax);
+        row
+    }
+
+    /// 行的文本内容
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    /// 行的长度（按字素计算）
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 行是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 替换行的内容并重新计算长度
+    /// 
+    /// 长度总是从新内容算出，而不是增量维护：插入组合字符或拼接两行时，
+    /// 相邻的字符可能合并成一个字素
+    fn set_string(&mut self, string: String) {
+        self.invalidate_render();
+        self.len = string.graphemes(true).count();
+        self.display_len = UnicodeWidthStr::width(&string[..]);
+        self.string = string;
+    }
+
+    /// 更新行的语法高亮
+    /// 
+    /// 分析行内容并为每个字符设置适当的高亮类型。
+    /// 行首状态取自 `starts_in_comment`，结束时更新 `open_comment_at_end`，
+    /// 由 `update_syntax_from` 负责在相邻行之间传递
+    /// 
+    /// # 参数
+    /// * `syntax` - 当前生效的语法规则
+    fn update_syntax(&mut self, syntax: &Syntax) {
+        self.invalidate_render();
+        self.highlighting = Vec::new();
+        let chars: Vec<char> = self.string.chars().collect();
+        let mut i = 0;
+        let mut in_string: Option<char> = None;
+        let mut in_comment = self.starts_in_comment && syntax.block_comment.is_some();
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if let Some((start, end)) = syntax.block_comment {
+                if in_comment {
+                    if starts_with_at(&chars, i, end) {
+                        let len = end.chars().count();
+                        self.push_highlight(HighlightType::Comment, len);
+                        i += len;
+                        in_comment = false;
+                    } else {
+                        self.highlighting.push(HighlightType::Comment);
+                        i += 1;
+                    }
+                    continue;
+                }
+
+                if in_string.is_none() && starts_with_at(&chars, i, start) {
+                    let len = start.chars().count();
+                    self.push_highlight(HighlightType::Comment, len);
+                    i += len;
+                    in_comment = true;
+                    continue;
+                }
+            }
+
+            if let Some(quote) = in_string {
+                self.highlighting.push(HighlightType::String);
+                if c == '\\' && i + 1 < chars.len() {
+                    // 转义字符
+                    self.highlighting.push(HighlightType::String);
+                    i += 2;
+                    continue;
+                }
+                if c == quote {
+                    in_string = None;
+                }
+                i += 1;
+                continue;
+            }
+
+            if syntax.string_quotes.contains(&c) {
+                self.highlighting.push(HighlightType::String);
+                in_string = Some(c);
+                i += 1;
+                continue;
+            }
+
+            if syntax.char_quote == Some(c) {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != c {
+                    j += 1;
+                }
+                let end = j.min(chars.len() - 1);
+                self.push_highlight(HighlightType::CharLiteral, end - i + 1);
+                i = end + 1;
+                continue;
+            }
+
+            if syntax.highlight_numbers && c.is_ascii_digit() {
+                self.highlighting.push(HighlightType::Number);
+                i += 1;
+                continue;
+            }
+
+            if let Some(line_comment) = syntax.line_comment {
+                if starts_with_at(&chars, i, line_comment) {
+                    self.push_highlight(HighlightType::Comment, chars.len() - i);
+                    break;
+                }
+            }
+
+            // 关键字高亮
+            if let Some(word) = self.get_word_at(i, &chars) {
+                if syntax.is_primary_keyword(&word) {
+                    self.push_highlight(HighlightType::PrimaryKeywords, word.len());
+                    i += word.len();
+                    continue;
+                } else if syntax.is_secondary_keyword(&word) {
+                    self.push_highlight(HighlightType::SecondaryKeywords, word.len());
+                    i += word.len();
+                    continue;
+                }
+            }
+
+            self.highlighting.push(HighlightType::Normal);
+            i += 1;
+        }
+        self.open_comment_at_end = in_comment;
+    }
+
+    /// 为接下来的 `count` 个字符设置同一种高亮类型
+    fn push_highlight(&mut self, highlight: HighlightType, count: usize) {
+        self.highlighting.extend(std::iter::repeat_n(highlight, count));
+    }
+
+    /// 获取指定位置的单词
+    /// 
+    /// # 参数
+    /// * `start` - 开始位置
+    /// * `chars` - 字符数组
+    /// 
+    /// # 返回值
+    /// 返回以start位置开始的完整单词，如果start位置不是单词开始则返回None
+    fn get_word_at(&self, start: usize, chars: &[char]) -> Option<String> {
+        let is_word_char = |c: &char| c.is_alphanumeric() || *c == '_';
+        // 单词必须以字母开头，且前一个字符（如果存在）必须是词边界
+        if !chars.get(start)?.is_alphabetic() || (start > 0 && is_word_char(&chars[start - 1])) {
+            return None;
+        }
+        // 单词一直延伸到第一个非单词字符，因此结束处必然是词边界
+        let len = chars[start..].iter().take_while(|c| is_word_char(c)).count();
+        Some(chars[start..start + len].iter().collect())
+    }
+
+    /// 清空渲染缓存，行内容或高亮改变时调用
+    fn invalidate_render(&mut self) {
+        self.render_cache.get_mut().unwrap().clear();
+    }
+
+    /// 在指定位置插入字符
+    /// 
+    /// # 参数
+    /// * `at` - 插入位置
+    /// * `c` - 要插入的字符
+    pub fn insert(&mut self, at: usize, c: char) {
+        self.invalidate_render();
+        let mut result: String = String::new();
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if index == at {
+                result.push(c);
+            }
+            result.push_str(grapheme);
+        }
+        if at >= self.len {
+            result.push(c);
+        }
+        self.set_string(result);
+    }
+
+    /// 在行尾补空格，使行至少有 `len` 个字素（在虚拟空间中输入时使用）
+    /// 
+    /// # 参数
+    /// * `len` - 补齐后的最小长度
+    /// * `syntax` - 重新高亮时使用的语法规则
+    fn pad_to(&mut self, len: usize, syntax: &Syntax) {
+        if len > self.len {
+            self.replace_range(self.len, 0, &" ".repeat(len - self.len), syntax);
+        }
+    }
+
+    /// 用字符替换指定位置的字素（覆盖模式）
+    /// 
+    /// 位置在行尾或之后时追加字符
+    /// 
+    /// # 参数
+    /// * `at` - 替换位置
+    /// * `c` - 新字符
+    fn replace_at(&mut self, at: usize, c: char) {
+        if at >= self.len {
+            self.insert(at, c);
+            return;
+        }
+        self.invalidate_render();
+        let mut result: String = String::new();
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if index == at {
+                result.push(c);
+            } else {
+                result.push_str(grapheme);
+            }
+        }
+        self.set_string(result);
+    }
+
+    /// 删除指定位置的字符
+    /// 
+    /// # 参数
+    /// * `at` - 要删除的字符位置
+    pub fn delete(&mut self, at: usize) {
+        if at >= self.len {
+            return;
+        }
+        self.invalidate_render();
+        let mut result: String = String::new();
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if index != at {
+                result.push_str(grapheme);
+            }
+        }
+        self.set_string(result);
+    }
+
+    /// 将另一行的内容追加到当前行
+    /// 
+    /// # 参数
+    /// * `new` - 要追加的行
+    /// * `syntax` - 用于高亮的语法规则
+    pub fn append(&mut self, new: &Self, syntax: &Syntax) {
+        self.folded = None;
+        self.set_string(format!("{}{}", self.string, new.string));
+        // 添加立即更新语法高亮
+        self.update_syntax(syntax);
+    }
+
+    /// 在指定位置分割行
+    /// 
+    /// # 参数
+    /// * `at` - 分割位置
+    /// * `syntax` - 用于高亮的语法规则
+    /// 
+    /// # 返回值
+    /// 返回分割后的新行（at位置之后的内容）
+    pub fn split(&mut self, at: usize, syntax: &Syntax) -> Self {
+        self.folded = None;
+        let mut row: String = String::new();
+        let mut splitted_row: String = String::new();
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if index < at {
+                row.push_str(grapheme);
+            } else {
+                splitted_row.push_str(grapheme);
+            }
+        }
+        self.set_string(row);
+        // 添加立即更新语法高亮
+        self.update_syntax(syntax);
+        Self::new(splitted_row, syntax)
+    }
+
+    /// 在行中搜索文本
+    /// 
+    /// # 参数
+    /// * `query` - 要搜索的文本
+    /// * `at` - 开始搜索的位置
+    /// 
+    /// # 返回值
+    /// 返回找到的位置，如果未找到则返回None
+    pub fn search(&self, query: &str, at: usize) -> Option<usize> {
+        if at > self.len {
+            return None;
+        }
+        let substring: String = self.string[..].graphemes(true).skip(at).collect();
+        let matching = substring.find(query);
+        // find 返回的是字节偏移，需要换算成字素位置
+        matching.map(|match_index| at + substring[..match_index].graphemes(true).count())
+    }
+
+    /// 在行中向前搜索文本
+    /// 
+    /// # 参数
+    /// * `query` - 要搜索的文本
+    /// * `before` - 匹配必须在这个位置之前开始
+    /// 
+    /// # 返回值
+    /// 返回离 `before` 最近的匹配位置，如果未找到则返回None
+    pub fn rsearch(&self, query: &str, before: usize) -> Option<usize> {
+        let starts: Vec<usize> = self.string.grapheme_indices(true).take(before).map(|(i, _)| i).collect();
+        starts.iter().rposition(|&start| self.string[start..].starts_with(query))
+    }
+
+    /// 行中所有匹配的开始位置（字素索引），相互重叠的匹配也分别计入
+    /// 
+    /// 与用 `search` 从上一个匹配的下一个字素继续查找时经过的匹配一致
+    /// 
+    /// # 参数
+    /// * `query` - 要搜索的文本
+    pub fn match_positions<'a>(&'a self, query: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.string
+            .grapheme_indices(true)
+            .enumerate()
+            .filter(move |(_, (start, _))| self.string[*start..].starts_with(query))
+            .map(|(index, _)| index)
+    }
+
+    /// 用新文本替换从指定位置开始的若干个字素
+    /// 
+    /// # 参数
+    /// * `at` - 开始位置（字素索引）
+    /// * `count` - 被替换的字素个数
+    /// * `replacement` - 替换成的文本
+    /// * `syntax` - 重新高亮时使用的语法规则
+    pub fn replace_range(&mut self, at: usize, count: usize, replacement: &str, syntax: &Syntax) {
+        let mut graphemes: Vec<&str> = self.string[..].graphemes(true).collect();
+        let start = at.min(graphemes.len());
+        let end = (at + count).min(graphemes.len());
+        graphemes.splice(start..end, replacement.graphemes(true));
+        self.set_string(graphemes.concat());
+        self.update_syntax(syntax);
+    }
+}
+
+/// 重新高亮从 `start` 开始的 `count` 行，并把块注释状态向下传递
+/// 
+/// 被修改的行总会重新高亮；之后的行只在行首状态改变时重新高亮，
+/// 直到某一行的行首状态不再变化为止
+/// 
+/// # 参数
+/// * `rows` - 文档的所有行
+/// * `start` - 第一个被修改的行
+/// * `count` - 被修改的行数
+/// * `syntax` - 当前生效的语法规则
+fn update_syntax_from(rows: &mut [Row], start: usize, count: usize, syntax: &Syntax) {
+    for y in start..rows.len() {
+        let carry = y > 0 && rows[y - 1].open_comment_at_end;
+        if y >= start + count && rows[y].starts_in_comment == carry {
+            break;
+        }
+        rows[y].starts_in_comment = carry;
+        rows[y].update_syntax(syntax);
+    }
+}
+
+/// 第 `y` 行之后的下一个可见行
+/// 
+/// 折叠的行跳过它隐藏的所有行。结果最大为 `rows.len()`，即文档末尾之后的虚拟行；
+/// `y` 已经是虚拟行时返回 `y + 1`，由调用者限制范围
+fn next_visible_row(rows: &[Row], y: usize) -> usize {
+    match rows.get(y) {
+        Some(row) => y.saturating_add(1).saturating_add(row.folded.unwrap_or(0)).min(rows.len()),
+        None => y.saturating_add(1),
+    }
+}
+
+/// 显示第 `y` 行的可见行
+/// 
+/// `y` 被折叠隐藏时为覆盖它的最外层折叠的首行，否则为 `y` 本身
+fn visible_row(rows: &[Row], y: usize) -> usize {
+    let mut visible = y;
+    for header in (0..y.min(rows.len())).rev() {
+        if rows[header].folded.is_some_and(|hidden| header + hidden >= visible) {
+            visible = header;
+        }
+    }
+    visible
+}
+
+/// 从第 `y` 行出发向下（或向上）移动 `count` 个可见行
+/// 
+/// 向下最多到文档末尾之后的虚拟行，向上最多到第一行
+fn step_visible_rows(rows: &[Row], y: usize, count: usize, down: bool) -> usize {
+    let mut y = y;
+    for _ in 0..count {
+        if down {
+            if y >= rows.len() {
+                break;
+            }
+            y = next_visible_row(rows, y);
+        } else {
+            if y == 0 {
+                break;
+            }
+            y = visible_row(rows, y - 1);
+        }
+    }
+    y
+}
+
+/// 后台进行的全文语法高亮
+/// 
+/// 线程只处理文档内容的快照，不持有文档的锁；完成后把重新高亮的行
+/// 通过通道送回，由主线程在文档仍处于快照时的版本时一次性替换
+struct SyntaxJob {
+    version: u64,                      // 快照对应的修改版本号
+    receiver: mpsc::Receiver<Vec<Row>>, // 接收高亮结果的通道
+}
+
+/// 检查字符数组从指定位置开始是否与模式匹配
+/// 
+/// # 参数
+/// * `chars` - 字符数组
+/// * `at` - 开始位置
+/// * `pattern` - 要匹配的模式
+fn starts_with_at(chars: &[char], at: usize, pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .all(|(offset, p)| chars.get(at + offset) == Some(&p))
+}
+
+/// 搜索状态，用于跟踪搜索和替换操作
+/// 
+/// # 功能特点
+/// - 支持双向搜索（向前和向后）
+/// - 记住上次匹配位置
+/// - 支持替换操作
+/// - 支持实时搜索预览
+/// 
+/// # 字段说明
+/// - `last_match`: 上一个匹配位置，用于继续搜索
+/// - `direction`: 搜索方向（1 向前，-1 向后）
+/// - `replace_text`: 替换文本，仅在替换模式下使用
+/// - `match_counts`: 每行的匹配数缓存，对应的查询文本和文档版本改变时重新统计
+/// - `match_status`: 当前匹配的序号（未统计到时为 None）、匹配总数以及总数是否达到上限
+/// - `last_query`: 上一次确认的搜索文本，退出搜索提示后仍保留，供 n / N（F3 / Shift-F3）使用
+/// 
+/// # 使用说明
+/// - 使用 `Default::default()` 创建新的搜索状态
+/// - 搜索方向可以通过键盘方向键动态改变
+/// - 替换文本在确认替换前可以预览
+/// 
+/// # 示例
+/// ```ignore
+/// let mut state = SearchState::default();
+/// state.direction = 1;  // 向前搜索
+/// state.replace_text = Some("new".to_string());  // 设置替换文本
+/// ```
+#[derive(Clone, Default)]
+struct SearchState {
+    last_match: Option<Position>,     // 上一个匹配位置
+    direction: i32,                   // 搜索方向：1 向前，-1 向后
+    replace_text: Option<String>,     // 替换文本
+    match_counts: Option<(String, u64, Vec<usize>)>,  // 查询文本、文档版本和每行的匹配数
+    match_status: Option<(Option<usize>, usize, bool)>,  // 当前序号、总数、是否达到上限
+    last_query: Option<String>,       // 上一次完成的搜索文本，供 n / N 继续查找
+}
+
+/// 按单词移动光标时对字素的分类
+#[derive(Clone, Copy, PartialEq)]
+enum WordClass {
+    Word,         // 字母数字和 `_`
+    Punctuation,  // 其它可见字符
+    Blank,        // 空白
+}
+
+impl WordClass {
+    fn of(grapheme: &str) -> WordClass {
+        if grapheme.chars().all(char::is_whitespace) {
+            WordClass::Blank
+        } else if grapheme.chars().any(|c| c.is_alphanumeric() || c == '_') {
+            WordClass::Word
+        } else {
+            WordClass::Punctuation
+        }
+    }
+}
+
+/// 模式编辑（类似 vi）的当前模式
+#[derive(Clone, Copy, PartialEq)]
+enum ViMode {
+    Normal,  // 普通模式，字母键作为命令
+    Insert,  // 插入模式，按键输入文本
+}
+
+impl ViMode {
+    /// 状态栏中显示的模式名称
+    fn name(self) -> &'static str {
+        match self {
+            ViMode::Normal => "NORMAL",
+            ViMode::Insert => "INSERT",
+        }
+    }
+}
+
+/// 可撤销操作的种类
+/// 
+/// 用于合并连续的输入以及在撤销时提示被撤销的操作
+#[derive(Clone, Copy, PartialEq)]
+enum EditKind {
+    InsertChar,       // 输入字符
+    DeleteChar,       // 删除字符
+    Newline,          // 插入换行（分割行）
+    JoinLines,        // 合并行
+    DeleteSelection,  // 删除选中文本
+    Paste,            // 粘贴
+    Replace,          // 替换
+    DuplicateLines,   // 复制行
+    MoveLines,        // 移动行
+    ToggleComment,    // 切换注释
+    Indent,           // 缩进/取消缩进
+    DeleteWord,       // 删除单词
+    DeleteLines,      // 删除整行
+    KillLine,         // 删除到行尾
+    TrimWhitespace,   // 保存时删除行尾空白
+}
+
+impl EditKind {
+    fn description(self) -> &'static str {
+        match self {
+            EditKind::InsertChar => "输入",
+            EditKind::DeleteChar => "删除",
+            EditKind::Newline => "换行",
+            EditKind::JoinLines => "合并行",
+            EditKind::DeleteSelection => "删除选中文本",
+            EditKind::Paste => "粘贴",
+            EditKind::Replace => "替换",
+            EditKind::DuplicateLines => "复制行",
+            EditKind::MoveLines => "移动行",
+            EditKind::ToggleComment => "切换注释",
+            EditKind::Indent => "缩进",
+            EditKind::DeleteWord => "删除单词",
+            EditKind::DeleteLines => "删除行",
+            EditKind::KillLine => "删除到行尾",
+            EditKind::TrimWhitespace => "删除行尾空白",
+        }
+    }
+}
+
+/// 一次修改的记录：把从第 `y` 行开始的 `before` 行替换成了 `after` 行
+/// 
+/// 所有编辑操作（包括分割、合并行和多行删除）都可以表示为行区间的替换，
+/// 撤销时反向替换即可
+struct Edit {
+    y: usize,             // 修改区域的起始行
+    before: Vec<String>,  // 修改前的行内容
+    after: Vec<String>,   // 修改后的行内容
+}
+
+impl Edit {
+    /// 重新应用修改
+    fn apply(&self, rows: &mut Vec<Row>, syntax: &Syntax) {
+        Self::splice(rows, self.y, self.before.len(), &self.after, syntax);
+    }
+
+    /// 撤销修改
+    fn revert(&self, rows: &mut Vec<Row>, syntax: &Syntax) {
+        Self::splice(rows, self.y, self.after.len(), &self.before, syntax);
+    }
+
+    /// 用 `lines` 替换从第 `y` 行开始的 `count` 行
+    /// 
+    /// 行数不变时各行保留原来的折叠状态；行数改变说明有行被拆分或合并，区间内的折叠展开
+    fn splice(rows: &mut Vec<Row>, y: usize, count: usize, lines: &[String], syntax: &Syntax) {
+        let start = y.min(rows.len());
+        let end = (y + count).min(rows.len());
+        let folded: Vec<Option<usize>> = rows[start..end].iter().map(|row| row.folded).collect();
+        rows.splice(start..end, lines.iter().map(|line| Row::new(line.clone(), syntax)));
+        if folded.len() == lines.len() {
+            for (row, folded) in rows[start..].iter_mut().zip(folded) {
+                row.folded = folded;
+            }
+        }
+        update_syntax_from(rows, start, lines.len(), syntax);
+    }
+}
+
+/// 作为一个整体撤销的一组修改
+/// 
+/// # 字段说明
+/// - `kind`: 操作种类
+/// - `edits`: 按执行顺序排列的修改
+/// - `cursor_before` / `cursor_after`: 操作前后的光标位置
+/// - `version_before` / `version_after`: 操作前后的文档版本号，用于恢复修改状态
+struct UndoGroup {
+    kind: EditKind,
+    edits: Vec<Edit>,
+    cursor_before: Position,
+    cursor_after: Position,
+    version_before: u64,
+    version_after: u64,
+}
+
+/// 撤销/重做栈
+/// 
+/// # 功能特点
+/// - 连续输入的字符合并为一组，按单词撤销
+/// - 可以把多个修改显式合并为一组（如粘贴、替换）
+/// - 新的修改会清空重做栈
+#[derive(Default)]
+struct UndoStack {
+    undo: VecDeque<UndoGroup>,       // 可撤销的操作
+    redo: Vec<UndoGroup>,            // 可重做的操作
+    open_group: Option<UndoGroup>,   // 正在记录的操作组
+}
+
+impl UndoStack {
+    /// 开始记录一组修改，直到 `end_group` 前的所有修改作为一个整体撤销
+    fn begin_group(&mut self, kind: EditKind, cursor: Position, version: u64) {
+        if self.open_group.is_none() {
+            self.open_group = Some(UndoGroup {
+                kind,
+                edits: Vec::new(),
+                cursor_before: cursor,
+                cursor_after: cursor,
+                version_before: version,
+                version_after: version,
+            });
+        }
+    }
+
+    /// 结束当前的操作组
+    fn end_group(&mut self, cursor: Position) {
+        if let Some(mut group) = self.open_group.take() {
+            if !group.edits.is_empty() {
+                group.cursor_after = cursor;
+                self.push(group);
+            }
+        }
+    }
+
+    /// 记录一次修改
+    /// 
+    /// 有正在记录的操作组时追加到该组；否则连续的单行字符输入会合并，
+    /// 输入空白字符时开始新的一组，使撤销以单词为单位
+    fn record(&mut self, kind: EditKind, edit: Edit, cursor_before: Position, cursor_after: Position, version: u64, starts_word: bool) {
+        if let Some(group) = self.open_group.as_mut() {
+            group.edits.push(edit);
+            return;
+        }
+        if kind == EditKind::InsertChar && !starts_word && self.redo.is_empty() {
+            if let Some(group) = self.undo.back_mut() {
+                if let Some(last) = group.edits.last_mut() {
+                    if group.kind == EditKind::InsertChar
+                        && group.cursor_after.x == cursor_before.x
+                        && group.cursor_after.y == cursor_before.y
+                        && last.y == edit.y
+                        && last.after.len() == 1
+                        && edit.before.len() == 1
+                    {
+                        last.after = edit.after;
+                        group.cursor_after = cursor_after;
+                        return;
+                    }
+                }
+            }
+        }
+        self.push(UndoGroup {
+            kind,
+            edits: vec![edit],
+            cursor_before,
+            cursor_after,
+            version_before: version,
+            version_after: version,
+        });
+    }
+
+    fn push(&mut self, group: UndoGroup) {
+        self.redo.clear();
+        self.undo.push_back(group);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// 文件的换行符风格
+/// 
+/// 打开文件时检测占多数的换行符，保存时按原样写回
+#[derive(Clone, Copy, Default, PartialEq)]
+enum LineEnding {
+    #[default]
+    Lf,    // Unix 风格 `\n`
+    CrLf,  // Windows 风格 `\r\n`
+}
+
+impl LineEnding {
+    /// 检测文本中占多数的换行符，没有换行符时使用 `Lf`
+    fn detect(contents: &str) -> Self {
+        let crlf = contents.matches("\r\n").count();
+        let lf = contents.matches('\n').count() - crlf;
+        if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// 在状态栏中显示的名称
+    fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// 可以绑定到按键的编辑器命令
+/// 
+/// 默认绑定见 `DEFAULT_KEYMAP`，配置文件中用 `ACTION_NAMES` 里的名称引用。
+/// 输入字符、方向键等光标移动、Tab、Enter、Esc 等编辑键不经过按键映射，
+/// 它们的行为取决于修饰键的组合（如按住 Shift 扩展选择）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Quit,                      // 退出（有未保存的修改时需要连续按多次）
+    Save,                      // 保存
+    ForceSave,                 // 强制保存
+    SaveAs,                    // 另存为
+    Open,                      // 打开其它文件
+    NewDocument,               // 新建空文档
+    Reload,                    // 从磁盘重新加载
+    CommandLine,               // 命令行
+    Search,                    // 搜索
+    SearchNext,                // 查找下一处匹配
+    SearchPrevious,            // 查找上一处匹配
+    Replace,                   // 替换下一处匹配
+    ReplaceAll,                // 替换全部匹配
+    Copy,                      // 复制
+    Cut,                       // 剪切
+    Paste,                     // 粘贴
+    SelectAll,                 // 全选
+    DuplicateLines,            // 复制行
+    MoveLinesUp,               // 上移行
+    MoveLinesDown,             // 下移行
+    ToggleComment,             // 注释/取消注释
+    Undo,                      // 撤销
+    Redo,                      // 重做
+    DeleteBackward,            // 删除选中文本或光标前的字符（与 Backspace 相同）
+    DeleteForward,             // 删除选中文本或光标处的字符
+    DeleteWordBackward,        // 删除光标前的单词
+    DeleteWordForward,         // 删除光标后的单词
+    KillLine,                  // 删除到行尾
+    DeleteLines,               // 删除整行
+    Yank,                      // 插入最近删除的文本
+    NextBuffer,                // 切换到下一个缓冲区
+    PickBuffer,                // 按编号选择缓冲区
+    HexView,                   // 切换十六进制视图
+    InspectChar,               // 查看光标处字符
+    SetFiletype,               // 设置文件类型
+    GotoLine,                  // 跳转到指定行
+    MatchingBracket,           // 跳转到匹配的括号
+    ToggleLineNumbers,         // 显示/隐藏行号
+    ToggleWordWrap,            // 切换自动换行
+    ToggleReadOnly,            // 切换只读模式
+    CycleTheme,                // 切换配色方案
+    ToggleBlockSelection,      // 切换矩形选择
+    ToggleWhitespace,          // 切换空白字符显示
+    ToggleIndentGuides,        // 切换缩进参考线
+    ToggleTrailingWhitespace,  // 切换行尾空白高亮
+    ToggleFold,                // 折叠/展开
+    ToggleHighlightSearch,     // 切换搜索结束后的匹配高亮
+    ToggleSpellCheck,          // 切换拼写检查
+    ToggleVirtualSpace,        // 切换虚拟空间
+    ToggleInsertSpaces,        // 切换 Tab 键插入空格或制表符
+    HalfPageDown,              // 向下滚动半页
+    HalfPageUp,                // 向上滚动半页
+}
+
+/// 配置文件中使用的命令名称
+static ACTION_NAMES: [(&str, Action); 52] = [
+    ("quit", Action::Quit),
+    ("save", Action::Save),
+    ("force_save", Action::ForceSave),
+    ("save_as", Action::SaveAs),
+    ("open", Action::Open),
+    ("new", Action::NewDocument),
+    ("reload", Action::Reload),
+    ("command_line", Action::CommandLine),
+    ("search", Action::Search),
+    ("search_next", Action::SearchNext),
+    ("search_previous", Action::SearchPrevious),
+    ("replace", Action::Replace),
+    ("replace_all", Action::ReplaceAll),
+    ("copy", Action::Copy),
+    ("cut", Action::Cut),
+    ("paste", Action::Paste),
+    ("select_all", Action::SelectAll),
+    ("duplicate_lines", Action::DuplicateLines),
+    ("move_lines_up", Action::MoveLinesUp),
+    ("move_lines_down", Action::MoveLinesDown),
+    ("toggle_comment", Action::ToggleComment),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("delete_backward", Action::DeleteBackward),
+    ("delete_forward", Action::DeleteForward),
+    ("delete_word_backward", Action::DeleteWordBackward),
+    ("delete_word_forward", Action::DeleteWordForward),
+    ("kill_line", Action::KillLine),
+    ("delete_lines", Action::DeleteLines),
+    ("yank", Action::Yank),
+    ("next_buffer", Action::NextBuffer),
+    ("pick_buffer", Action::PickBuffer),
+    ("hex_view", Action::HexView),
+    ("inspect_char", Action::InspectChar),
+    ("set_filetype", Action::SetFiletype),
+    ("goto_line", Action::GotoLine),
+    ("matching_bracket", Action::MatchingBracket),
+    ("toggle_line_numbers", Action::ToggleLineNumbers),
+    ("toggle_word_wrap", Action::ToggleWordWrap),
+    ("toggle_read_only", Action::ToggleReadOnly),
+    ("cycle_theme", Action::CycleTheme),
+    ("toggle_block_selection", Action::ToggleBlockSelection),
+    ("toggle_whitespace", Action::ToggleWhitespace),
+    ("toggle_indent_guides", Action::ToggleIndentGuides),
+    ("toggle_trailing_whitespace", Action::ToggleTrailingWhitespace),
+    ("toggle_fold", Action::ToggleFold),
+    ("toggle_highlight_search", Action::ToggleHighlightSearch),
+    ("toggle_spell_check", Action::ToggleSpellCheck),
+    ("toggle_virtual_space", Action::ToggleVirtualSpace),
+    ("toggle_insert_spaces", Action::ToggleInsertSpaces),
+    ("half_page_down", Action::HalfPageDown),
+    ("half_page_up", Action::HalfPageUp),
+];
+
+const CONTROL_ALT: KeyModifiers = KeyModifiers::CONTROL.union(KeyModifiers::ALT);
+
+/// 默认的按键绑定（即模块文档中列出的快捷键）
+/// 
+/// 带 Shift 的字母键以小写记录，查找时见 `key_binding`。
+/// 
+/// 不使用 Ctrl-Shift-字母和 Ctrl-Tab：编辑器不开启键盘增强协议，传统终端发送的
+/// Ctrl-Shift-E 与 Ctrl-E 是同一个字节，Ctrl-Tab 与 Tab 相同，无法区分
+static DEFAULT_KEYMAP: [(KeyCode, KeyModifiers, Action); 54] = [
+    (KeyCode::Char('q'), KeyModifiers::CONTROL, Action::Quit),
+    (KeyCode::Char('s'), KeyModifiers::CONTROL, Action::Save),
+    (KeyCode::Char('s'), CONTROL_ALT, Action::ForceSave),
+    (KeyCode::Char('a'), KeyModifiers::ALT, Action::SaveAs),
+    (KeyCode::Char('o'), KeyModifiers::CONTROL, Action::Open),
+    (KeyCode::Char('n'), KeyModifiers::CONTROL, Action::NewDocument),
+    (KeyCode::Char('r'), KeyModifiers::CONTROL, Action::Reload),
+    (KeyCode::Char('p'), KeyModifiers::CONTROL, Action::CommandLine),
+    (KeyCode::Char('f'), KeyModifiers::CONTROL, Action::Search),
+    (KeyCode::F(3), KeyModifiers::NONE, Action::SearchNext),
+    (KeyCode::F(3), KeyModifiers::SHIFT, Action::SearchPrevious),
+    (KeyCode::Char('e'), KeyModifiers::CONTROL, Action::Replace),
+    (KeyCode::Char('e'), CONTROL_ALT, Action::ReplaceAll),
+    // 许多终端把 Backspace 发送为 0x08，crossterm 将其报告为 Ctrl-H；
+    // 发送 0x7F 的终端上 Backspace 被报告为 `KeyCode::Backspace`，由编辑键处理
+    (KeyCode::Char('h'), KeyModifiers::CONTROL, Action::DeleteBackward),
+    (KeyCode::Delete, KeyModifiers::NONE, Action::DeleteForward),
+    (KeyCode::Char('c'), KeyModifiers::CONTROL, Action::Copy),
+    (KeyCode::Char('x'), KeyModifiers::CONTROL, Action::Cut),
+    (KeyCode::Char('v'), KeyModifiers::CONTROL, Action::Paste),
+    (KeyCode::Char('a'), KeyModifiers::CONTROL, Action::SelectAll),
+    (KeyCode::Char('d'), KeyModifiers::CONTROL, Action::DuplicateLines),
+    (KeyCode::Up, KeyModifiers::ALT, Action::MoveLinesUp),
+    (KeyCode::Down, KeyModifiers::ALT, Action::MoveLinesDown),
+    // 多数终端把 Ctrl-/ 报告为 Ctrl-7
+    (KeyCode::Char('/'), KeyModifiers::CONTROL, Action::ToggleComment),
+    (KeyCode::Char('7'), KeyModifiers::CONTROL, Action::ToggleComment),
+    (KeyCode::Char('z'), KeyModifiers::CONTROL, Action::Undo),
+    (KeyCode::Char('y'), KeyModifiers::CONTROL, Action::Redo),
+    (KeyCode::Backspace, KeyModifiers::CONTROL, Action::DeleteWordBackward),
+    (KeyCode::Delete, KeyModifiers::CONTROL, Action::DeleteWordForward),
+    (KeyCode::Char('k'), KeyModifiers::CONTROL, Action::KillLine),
+    (KeyCode::Char('k'), CONTROL_ALT, Action::DeleteLines),
+    (KeyCode::Char('u'), KeyModifiers::CONTROL, Action::Yank),
+    (KeyCode::Char('n'), KeyModifiers::ALT, Action::NextBuffer),
+    (KeyCode::Char('b'), KeyModifiers::ALT, Action::PickBuffer),
+    (KeyCode::Char('b'), KeyModifiers::CONTROL, Action::HexView),
+    (KeyCode::Char('i'), KeyModifiers::ALT, Action::InspectChar),
+    (KeyCode::Char('t'), KeyModifiers::ALT, Action::SetFiletype),
+    (KeyCode::Char('g'), KeyModifiers::CONTROL, Action::GotoLine),
+    // 大多数终端把 Ctrl-] 发送为 0x1D，crossterm 将其报告为 Ctrl-5
+    (KeyCode::Char(']'), KeyModifiers::CONTROL, Action::MatchingBracket),
+    (KeyCode::Char('5'), KeyModifiers::CONTROL, Action::MatchingBracket),
+    (KeyCode::Char('l'), KeyModifiers::CONTROL, Action::ToggleLineNumbers),
+    (KeyCode::Char('w'), KeyModifiers::ALT, Action::ToggleWordWrap),
+    (KeyCode::Char('r'), KeyModifiers::ALT, Action::ToggleReadOnly),
+    (KeyCode::Char('c'), KeyModifiers::ALT, Action::CycleTheme),
+    (KeyCode::Char('v'), KeyModifiers::ALT, Action::ToggleBlockSelection),
+    (KeyCode::Char('l'), KeyModifiers::ALT, Action::ToggleWhitespace),
+    (KeyCode::Char('g'), KeyModifiers::ALT, Action::ToggleIndentGuides),
+    (KeyCode::Char('e'), KeyModifiers::ALT, Action::ToggleTrailingWhitespace),
+    (KeyCode::Char('f'), KeyModifiers::ALT, Action::ToggleFold),
+    (KeyCode::Char('h'), KeyModifiers::ALT, Action::ToggleHighlightSearch),
+    (KeyCode::Char('k'), KeyModifiers::ALT, Action::ToggleSpellCheck),
+    (KeyCode::Char('x'), KeyModifiers::ALT, Action::ToggleVirtualSpace),
+    (KeyCode::Char('s'), KeyModifiers::ALT, Action::ToggleInsertSpaces),
+    (KeyCode::Char('d'), KeyModifiers::ALT, Action::HalfPageDown),
+    (KeyCode::Char('u'), KeyModifiers::ALT, Action::HalfPageUp),
+];
+
+/// 按键映射：按键和修饰键到命令
+type Keymap = HashMap<(KeyCode, KeyModifiers), Action>;
+
+/// 建立默认的按键映射
+fn default_keymap() -> Keymap {
+    DEFAULT_KEYMAP
+        .iter()
+        .map(|&(code, modifiers, action)| ((code, modifiers), action))
+        .collect()
+}
+
+/// 按键在按键映射中的键
+/// 
+/// 终端报告 Ctrl-Shift-K 等组合键时字母可能是大写也可能是小写，统一为小写
+fn key_binding(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => (KeyCode::Char(c.to_ascii_lowercase()), modifiers),
+        code => (code, modifiers),
+    }
+}
+
+/// 解析配置文件中的按键名称，如 `ctrl-h`、`ctrl-shift-k`、`alt-up`、`shift-f3`
+/// 
+/// 修饰键为 `ctrl`、`alt`、`shift`，不区分大小写，用 `-` 与按键连接；按键是单个字符或
+/// `backspace`、`delete`、`enter`、`tab`、`esc`、`insert`、`home`、`end`、`pageup`、`pagedown`、
+/// 方向键 `up` / `down` / `left` / `right` 以及 `f1` … `f12`
+/// 
+/// # 返回值
+/// 按键映射中的键（见 `key_binding`），无法识别时返回 None
+fn parse_key(name: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut key = name;
+    // 按键本身可以是 `-`，因此只拆分它之前的部分
+    while let Some((modifier, rest)) = key.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        key = rest;
+    }
+    let mut chars = key.chars();
+    let code = match (chars.next()?, chars.next()) {
+        (c, None) if modifiers.is_empty() => KeyCode::Char(c),
+        // 带修饰键的字母不区分大小写，与终端报告的 Ctrl-H 等组合键一致
+        (c, None) => KeyCode::Char(c.to_ascii_lowercase()),
+        _ => match key.to_lowercase().as_str() {
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "esc" => KeyCode::Esc,
+            "insert" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=12) => KeyCode::F(n),
+                _ => return None,
+            },
+        },
+    };
+    Some(key_binding(code, modifiers))
+}
+
+/// 按键在提示中显示的名称，如 `Ctrl-B`、`Alt-Up`、`Shift-F3`
+/// 
+/// 与 `parse_key` 接受的写法相同，只是首字母大写，带修饰键的字母显示为大写
+fn key_name((code, modifiers): (KeyCode, KeyModifiers)) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in [(KeyModifiers::CONTROL, "Ctrl-"), (KeyModifiers::ALT, "Alt-"), (KeyModifiers::SHIFT, "Shift-")] {
+        if modifiers.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match code {
+        KeyCode::Char(c) if !modifiers.is_empty() => name.push(c.to_ascii_uppercase()),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("F{}", n)),
+        code => name.push_str(&format!("{:?}", code)),
+    }
+    name
+}
+
+/// 用户配置，启动时从配置目录下的 `config.toml` 读取
+/// 
+/// 文件格式是 TOML 的一个子集：每行一个 `键 = 值`，`#` 之后为注释，
+/// 值可以是整数、`true` / `false` 或带双引号的字符串。例如：
+/// 
+/// ```toml
+/// tab_width = 8
+/// insert_spaces = true
+/// line_numbers = true
+/// theme = "light"
+/// scroll_off = 3
+/// modal_editing = true
+/// bind.ctrl-h = "replace"
+/// bind.ctrl-e = "none"
+/// ```
+/// 
+/// `bind.<按键> = "<命令>"` 修改一个按键的绑定，按键名称的写法见 `parse_key`，
+/// 命令名称见 `ACTION_NAMES`；命令为 `"none"` 时取消这个按键原有的绑定。
+/// 上例在 Backspace 不发送 Ctrl-H 的终端上把替换改回 Ctrl-H
+/// 
+/// `filetype.<模式> = "<文件类型>"` 让匹配模式的文件使用指定的语法规则，
+/// 如 `filetype."*.rs.in" = "rust"`，模式的写法见 `glob_match`，先写的映射优先
+/// 
+/// # 字段说明
+/// - `tab_width`: 制表符的显示宽度
+/// - `insert_spaces`: Tab 键是否插入空格
+/// - `line_numbers`: 是否显示行号
+/// - `word_wrap`: 是否自动换行
+/// - `theme`: 配色方案，取 `THEMES` 中的名称
+/// - `scroll_off`: 滚动时光标周围保留的上下文行数，常用值为 3
+/// - `page_overlap`: 翻页时保留的上一页行数，默认为 `PAGE_OVERLAP`
+/// - `final_newline`: 保存时是否在文件末尾写入换行符，未设置时保持打开时的样子
+/// - `large_file_mb`: 打开超过此大小（MB）的文件前请求确认
+/// - `message_timeout`: 普通状态消息的显示时长，配置文件中以秒为单位，默认为 `MESSAGE_TIMEOUT`
+/// - `error_message_timeout`: 错误消息的显示时长，配置文件中以秒为单位，默认为 `ERROR_MESSAGE_TIMEOUT`
+/// - `modal_editing`: 是否启用类似 vi 的模式编辑
+/// - `highlight_search`: 搜索结束后是否继续高亮所有匹配
+/// - `show_indent_guides`: 是否显示缩进参考线
+/// - `highlight_trailing_whitespace`: 是否以红色背景标出行尾空白
+/// - `trim_trailing_whitespace`: 保存时是否删除每行末尾的空格和制表符
+/// - `remember_positions`: 是否记住每个文件的光标位置，默认开启
+/// - `virtual_space`: 光标是否可以移到行尾之后
+/// - `spell_check`: 是否开启拼写检查
+/// - `dictionary`: 拼写检查使用的词表文件，每行一个单词
+/// - `backup`: 是否在第一次覆盖文件前创建备份
+/// - `backup_suffix`: 备份文件名在原文件名之后添加的后缀，如 `"~"` 或 `".bak"`
+/// - `keymap`: 按键绑定，在 `DEFAULT_KEYMAP` 的基础上应用配置文件中的修改
+/// - `filetypes`: 文件名模式到语法规则的映射，按配置文件中的顺序排列
+struct Config {
+    tab_width: usize,          // 制表符的显示宽度
+    insert_spaces: bool,       // Tab 键是否插入空格
+    line_numbers: bool,        // 是否显示行号
+    word_wrap: bool,           // 是否自动换行
+    theme: &'static Theme,     // 配色方案
+    scroll_off: usize,         // 光标周围保留的上下文行数
+    page_overlap: usize,       // 翻页时保留的上下文行数
+    final_newline: Option<bool>,  // 保存时是否写入末尾换行符
+    large_file_mb: u64,        // 大文件确认阈值（MB）
+    message_timeout: Duration,        // 普通状态消息的显示时长
+    error_message_timeout: Duration,  // 错误消息的显示时长
+    modal_editing: bool,       // 是否启用模式编辑
+    highlight_search: bool,    // 搜索结束后是否继续高亮匹配
+    show_indent_guides: bool,  // 是否显示缩进参考线
+    highlight_trailing_whitespace: bool,  // 是否高亮行尾空白
+    trim_trailing_whitespace: bool,  // 保存时是否删除行尾空白
+    remember_positions: bool,  // 是否记住光标位置
+    virtual_space: bool,       // 是否开启虚拟空间
+    spell_check: bool,         // 是否开启拼写检查
+    dictionary: PathBuf,       // 拼写检查的词表文件
+    backup: bool,              // 是否创建备份
+    backup_suffix: String,     // 备份文件名的后缀
+    keymap: Keymap,            // 按键绑定
+    filetypes: Vec<(String, &'static Syntax)>,  // 文件名模式对应的语法规则
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: TAB_WIDTH,
+            insert_spaces: false,
+            line_numbers: false,
+            word_wrap: false,
+            theme: &DARK_THEME,
+            scroll_off: 0,
+            page_overlap: PAGE_OVERLAP,
+            final_newline: None,
+            large_file_mb: LARGE_FILE_MB,
+            message_timeout: MESSAGE_TIMEOUT,
+            error_message_timeout: ERROR_MESSAGE_TIMEOUT,
+            modal_editing: false,
+            highlight_search: false,
+            show_indent_guides: false,
+            highlight_trailing_whitespace: false,
+            trim_trailing_whitespace: false,
+            remember_positions: true,
+            virtual_space: false,
+            spell_check: false,
+            dictionary: PathBuf::from(DICTIONARY_PATH),
+            backup: false,
+            backup_suffix: "~".to_string(),
+            keymap: default_keymap(),
+            filetypes: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// 读取配置文件
+    /// 
+    /// # 返回值
+    /// 配置文件不存在时返回默认配置；无法读取或格式错误时返回默认配置和错误信息，
+    /// 错误不影响编辑器启动
+    fn load() -> (Config, Option<String>) {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return (Config::default(), None);
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return (Config::default(), None),
+            Err(e) => return (Config::default(), Some(format!("无法读取配置文件 {}: {}", path.display(), e))),
+        };
+        match Config::parse(&text) {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(format!("配置文件 {} 有误，已使用默认设置: {}", path.display(), e))),
+        }
+    }
+
+    /// 解析配置文件内容
+    /// 
+    /// # 返回值
+    /// 遇到第一处错误时返回带行号的错误描述
+    fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("第 {} 行: {}", index + 1, message);
+            let (key, value) = line.split_once('=').ok_or_else(|| error("应为 `键 = 值`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let parse_bool = || match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(error(&format!("{} 的值应为 true 或 false", key))),
+            };
+            match key {
+                "tab_width" => {
+                    config.tab_width = value
+                        .parse()
+                        .ok()
+                        .filter(|&width| width > 0)
+                        .ok_or_else(|| error("tab_width 的值应为正整数"))?;
+                }
+                "scroll_off" => {
+                    config.scroll_off = value.parse().map_err(|_| error("scroll_off 的值应为非负整数"))?;
+                }
+                "page_overlap" => {
+                    config.page_overlap = value.parse().map_err(|_| error("page_overlap 的值应为非负整数"))?;
+                }
+                "insert_spaces" => config.insert_spaces = parse_bool()?,
+                "line_numbers" => config.line_numbers = parse_bool()?,
+                "word_wrap" => config.word_wrap = parse_bool()?,
+                "final_newline" => config.final_newline = Some(parse_bool()?),
+                "modal_editing" => config.modal_editing = parse_bool()?,
+                "highlight_search" => config.highlight_search = parse_bool()?,
+                "show_indent_guides" => config.show_indent_guides = parse_bool()?,
+                "highlight_trailing_whitespace" => config.highlight_trailing_whitespace = parse_bool()?,
+                "trim_trailing_whitespace" => config.trim_trailing_whitespace = parse_bool()?,
+                "remember_positions" => config.remember_positions = parse_bool()?,
+                "virtual_space" => config.virtual_space = parse_bool()?,
+                "spell_check" => config.spell_check = parse_bool()?,
+                "dictionary" => {
+                    let path = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| error("dictionary 的值应为带双引号的路径"))?;
+                    config.dictionary = PathBuf::from(path);
+                }
+                "backup" => config.backup = parse_bool()?,
+                "backup_suffix" => {
+                    let suffix = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .filter(|suffix| !suffix.is_empty() && !suffix.contains(['/', '\\']))
+                        .ok_or_else(|| error("backup_suffix 的值应为带双引号的非空后缀，且不含路径分隔符"))?;
+                    config.backup_suffix = suffix.to_string();
+                }
+                "large_file_mb" => {
+                    config.large_file_mb = value.parse().map_err(|_| error("large_file_mb 的值应为非负整数"))?;
+                }
+                "message_timeout" => {
+                    let seconds = value.parse().map_err(|_| error("message_timeout 的值应为非负整数（秒）"))?;
+                    config.message_timeout = Duration::from_secs(seconds);
+                }
+                "error_message_timeout" => {
+                    let seconds = value.parse().map_err(|_| error("error_message_timeout 的值应为非负整数（秒）"))?;
+                    config.error_message_timeout = Duration::from_secs(seconds);
+                }
+                "theme" => {
+                    let name = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| error("theme 的值应为带双引号的字符串"))?;
+                    config.theme = find_theme(name).ok_or_else(|| error(&format!("未知的配色方案 {}", name)))?;
+                }
+                _ if key.starts_with("bind.") => {
+                    let name = &key["bind.".len()..];
+                    let name = name.strip_prefix('"').and_then(|name| name.strip_suffix('"')).unwrap_or(name);
+                    let binding = parse_key(name).ok_or_else(|| error(&format!("无法识别的按键 {}", name)))?;
+                    let action = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| error("按键绑定的值应为带双引号的命令名称"))?;
+                    if action == "none" {
+                        config.keymap.remove(&binding);
+                    } else {
+                        let (_, action) = ACTION_NAMES
+                            .iter()
+                            .find(|(name, _)| *name == action)
+                            .ok_or_else(|| error(&format!("未知的命令 {}", action)))?;
+                        config.keymap.insert(binding, *action);
+                    }
+                }
+                _ if key.starts_with("filetype.") => {
+                    let pattern = &key["filetype.".len()..];
+                    let pattern = pattern.strip_prefix('"').and_then(|pattern| pattern.strip_suffix('"')).unwrap_or(pattern);
+                    let name = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| error("文件类型映射的值应为带双引号的文件类型名称"))?;
+                    let syntax = find_syntax(name).ok_or_else(|| error(&format!("未知的文件类型 {}", name)))?;
+                    config.filetypes.push((pattern.to_string(), syntax));
+                }
+                _ => return Err(error(&format!("未知的配置项 {}", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// 去掉配置文件一行中 `#` 开始的注释，双引号内的 `#` 属于值本身
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// 十六进制视图的状态（只读）
+/// 
+/// # 功能特点
+/// - 每行显示偏移量、十六进制字节和 ASCII 对照
+/// - 光标按字节移动
+/// - 独立于文本行保存字节内容
+/// 
+/// # 字段说明
+/// - `bytes`: 视图中显示的字节
+/// - `cursor`: 光标所在字节的偏移
+/// - `offset`: 屏幕第一行对应的视图行号
+struct HexView {
+    bytes: Vec<u8>,  // 显示的字节内容
+    cursor: usize,   // 光标所在字节的偏移
+    offset: usize,   // 屏幕第一行对应的视图行号
+}
+
+impl HexView {
+    fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            offset: 0,
+        }
+    }
+
+    /// 视图的总行数（空内容时也保留一行）
+    fn line_count(&self) -> usize {
+        self.bytes.len().div_ceil(HEX_BYTES_PER_LINE).max(1)
+    }
+
+    /// 按字节移动光标
+    /// 
+    /// # 参数
+    /// * `key` - 移动方向对应的按键
+    /// * `screen_rows` - 屏幕可显示的行数，用于翻页
+    fn move_cursor(&mut self, key: KeyCode, screen_rows: usize) {
+        let last = self.bytes.len().saturating_sub(1);
+        let page = screen_rows.max(1) * HEX_BYTES_PER_LINE;
+        self.cursor = match key {
+            KeyCode::Left => self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor.saturating_add(1),
+            KeyCode::Up => self.cursor.checked_sub(HEX_BYTES_PER_LINE).unwrap_or(self.cursor),
+            KeyCode::Down if self.cursor + HEX_BYTES_PER_LINE <= last => self.cursor + HEX_BYTES_PER_LINE,
+            KeyCode::PageUp => self.cursor.saturating_sub(page),
+            KeyCode::PageDown => self.cursor.saturating_add(page),
+            KeyCode::Home => self.cursor - self.cursor % HEX_BYTES_PER_LINE,
+            KeyCode::End => self.cursor - self.cursor % HEX_BYTES_PER_LINE + HEX_BYTES_PER_LINE - 1,
+            _ => self.cursor,
+        }
+        .min(last);
+    }
+
+    /// 调整视图偏移，确保光标所在行可见
+    fn scroll(&mut self, screen_rows: usize) {
+        let line = self.cursor / HEX_BYTES_PER_LINE;
+        if line < self.offset {
+            self.offset = line;
+        } else if line >= self.offset + screen_rows {
+            self.offset = line + 1 - screen_rows.max(1);
+        }
+    }
+
+    /// 渲染视图的一行：偏移量、十六进制字节和 ASCII 对照
+    /// 
+    /// # 参数
+    /// * `line` - 视图行号
+    fn render_line(&self, line: usize) -> String {
+        let start = line * HEX_BYTES_PER_LINE;
+        let end = (start + HEX_BYTES_PER_LINE).min(self.bytes.len());
+        let chunk = self.bytes.get(start..end).unwrap_or(&[]);
+        let mut result = format!("{:08x}  ", start);
+        for i in 0..HEX_BYTES_PER_LINE {
+            match chunk.get(i) {
+                Some(byte) => result.push_str(&format!("{:02x} ", byte)),
+                None => result.push_str("   "),
+            }
+            if i == HEX_BYTES_PER_LINE / 2 - 1 {
+                result.push(' ');
+            }
+        }
+        result.push('|');
+        for &byte in chunk {
+            result.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+        }
+        result.push('|');
+        result
+    }
+
+    /// 光标在屏幕上的位置（列，行）
+    fn cursor_screen_position(&self) -> (usize, usize) {
+        let column = self.cursor % HEX_BYTES_PER_LINE;
+        let gap = if column >= HEX_BYTES_PER_LINE / 2 { 1 } else { 0 };
+        (10 + column * 3 + gap, (self.cursor / HEX_BYTES_PER_LINE).saturating_sub(self.offset))
+    }
+}
+
+/// 后台缓冲区：没有显示在屏幕上的已打开文档
+/// 
+/// 当前缓冲区的状态直接保存在 `Editor` 的字段中，
+/// 切换缓冲区时与这里保存的状态互相交换
+/// 
+/// # 字段说明
+/// 与 `Editor` 中的同名字段含义相同
+struct Buffer {
+    rows: Arc<RwLock<Vec<Row>>>,   // 文档内容
+    filename: Option<String>,      // 文件名
+    cursor_position: Position,     // 光标位置
+    offset: Position,              // 视图偏移量
+    version: u64,                  // 修改版本号
+    last_version: u64,             // 已分配过的最大版本号
+    saved_version: u64,            // 与磁盘内容一致时的版本号
+    selection: Option<Selection>,  // 文本选择状态
+    syntax: &'static Syntax,       // 语法高亮规则
+    undo_stack: UndoStack,         // 撤销/重做栈
+    line_ending: LineEnding,       // 换行符风格
+    trailing_newline: bool,        // 文件末尾是否有换行符
+    read_only: bool,               // 是否只读
+}
+
+impl Buffer {
+    /// 创建空的未命名缓冲区
+    fn new() -> Self {
+        Self {
+            rows: Arc::new(RwLock::new(Vec::new())),
+            filename: None,
+            cursor_position: Position::default(),
+            offset: Position::default(),
+            version: 0,
+            last_version: 0,
+            saved_version: 0,
+            selection: None,
+            syntax: &PLAIN_SYNTAX,
+            undo_stack: UndoStack::default(),
+            line_ending: LineEnding::default(),
+            trailing_newline: true,
+            read_only: false,
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.version != self.saved_version
+    }
+}
+
+/// 编辑器的主要结构体，包含所有编辑器状态和功能
+/// 
+/// # 主要职责
+/// - 管理文档内容和状态
+/// - 处理用户输入
+/// - 渲染界面
+/// - 文件操作
+/// - 搜索和替换
+/// - 文本选择
+/// 
+/// # 字段说明
+/// - `should_quit`: 是否应该退出程序
+/// - `cursor_position`: 当前光标位置
+/// - `offset`: 视图偏移量，用于滚动
+/// - `screen_rows`: 屏幕可显示的行数
+/// - `screen_cols`: 屏幕可显示的列数
+/// - `rows`: 文档内容，使用 RwLock 实现并发访问
+/// - `version`: 文档的修改版本号，每次编辑分配新的版本号
+/// - `last_version`: 已分配过的最大版本号，撤销后的新修改不会与旧版本号重复
+/// - `saved_version`: 最近一次与磁盘内容一致时的版本号
+/// - `quit_times`: 剩余的退出确认次数
+/// - `status_message`: 状态栏消息
+/// - `filename`: 当前文件名
+/// - `is_searching`: 是否处于搜索模式
+/// - `current_search`: 当前的搜索文本，开启 `highlight_search` 时搜索结束后仍然保留以高亮匹配
+/// - `search_state`: 搜索状态
+/// - `syntax_job`: 后台进行中的全文语法高亮
+/// - `selection`: 文本选择状态
+/// - `sys_clipboard`: 系统剪贴板访问
+/// - `insert_mode`: 插入模式（true）或覆盖模式（false）
+/// - `page_overlap`: 翻页时保留的上一页行数
+/// - `hex_view`: 十六进制视图（开启时代替文本视图显示）
+/// - `syntax`: 当前生效的语法高亮规则
+/// - `undo_stack`: 撤销/重做栈
+/// - `show_line_numbers`: 是否在左侧显示行号
+/// - `line_ending`: 保存时使用的换行符
+/// - `trailing_newline`: 打开的文件末尾是否有换行符，保存时据此写回
+/// - `final_newline`: 配置文件中的 `final_newline`，设置后保存时总是（或总是不）写入末尾换行符
+/// - `large_file_mb`: 打开超过此大小（MB）的文件前请求确认
+/// - `message_timeout`: 普通状态消息的显示时长
+/// - `error_message_timeout`: 错误消息的显示时长
+/// - `tab_width`: 制表符的显示宽度，也是取消缩进时删除的最多空格数
+/// - `insert_spaces`: 按 Tab 键时插入空格（对齐到下一个制表位）而不是制表符，默认关闭
+/// - `matching_bracket`: 光标旁的括号及与之匹配的括号，刷新屏幕前文档或光标有变化时重新计算
+/// - `matching_bracket_key`: 计算 `matching_bracket` 时的版本号和光标位置，重新高亮或换了文档时清空
+/// - `word_wrap`: 是否自动换行，开启时超出屏幕宽度的行在单词边界处折行显示
+/// - `buffers`: 所有已打开的缓冲区，当前缓冲区的位置为 None（其状态保存在编辑器字段中）
+/// - `current_buffer`: 当前缓冲区在 `buffers` 中的位置
+/// - `read_only`: 当前缓冲区是否只读，只读时拒绝所有修改操作
+/// - `theme`: 语法高亮使用的配色方案，对所有缓冲区生效
+/// - `goal_column`: 连续上下移动光标时希望保持的显示列，其它操作会清除
+/// - `scroll_off`: 滚动时在光标上下（左右）至少保留的行数（列数），默认 0
+/// - `block_clipboard`: 最近一次矩形复制写入剪贴板的内容，剪贴板内容仍与之相同时按矩形粘贴
+/// - `show_whitespace`: 是否以可见符号显示制表符、行尾空格和行末位置
+/// - `show_indent_guides`: 是否在行首缩进中显示缩进参考线
+/// - `highlight_trailing_whitespace`: 是否以红色背景标出行尾空白
+/// - `trim_trailing_whitespace`: 是否在保存前删除行尾空白（作为一次可撤销的修改）
+/// - `remember_positions`: 是否在保存和退出时记录光标位置，并在打开文件时恢复
+/// - `virtual_space`: 光标是否可以左右移动到行尾之后，在那里输入时用空格补齐（自动换行时不生效）
+/// - `spell_check`: 是否给注释和字符串中的拼写错误加下划线
+/// - `dictionary_path`: 拼写检查的词表文件
+/// - `dictionary`: 已加载的词表（小写），第一次开启拼写检查时读取
+/// - `backup_suffix`: 备份文件名的后缀，没有开启备份时为 None
+/// - `backed_up`: 本次运行中已经备份过的文件（绝对路径），之后的保存不再覆盖备份
+/// - `pending_count`: 已输入但尚未使用的重复次数，下一个按键之后清除
+/// - `vi_mode`: 启用模式编辑时的当前模式，未启用时为 None
+/// - `pending_operator`: 普通模式下等待第二个按键的命令（如 `dd` 的第一个 `d`）
+/// - `highlight_search`: 搜索结束后是否继续高亮所有匹配，直到清除或开始新的搜索
+/// - `kill_ring`: 最近删除的文本，最新的在前，最多保留 `KILL_RING_SIZE` 条
+/// - `last_kill`: 上一次删除后的版本号和光标位置，两者都没有变化时下一次删除并入同一条记录
+/// - `keymap`: 按键绑定，由 `process_key` 查找
+/// - `filetypes`: 配置文件中文件名模式到语法规则的映射，打开和另存为时由 `syntax_for_filename` 查找
+/// 
+/// # 线程安全
+/// 该结构体通过 Arc<RwLock<>> 实现了线程安全的文档访问。
+/// 异步语法高亮在文档内容的快照上进行，只在写回结果时短暂地获取写锁。
+pub struct Editor {
+    should_quit: bool,                    // 是否应该退出
+    cursor_position: Position,            // 当前光标位置
+    offset: Position,                     // 视图偏移量
+    screen_rows: usize,                   // 屏幕可显示的行数
+    screen_cols: usize,                   // 屏幕可显示的列数
+    rows: Arc<RwLock<Vec<Row>>>,         // 文档内容，使用RwLock实现并发访问
+    version: u64,                         // 文档的修改版本号
+    last_version: u64,                    // 已分配过的最大版本号
+    saved_version: u64,                   // 与磁盘内容一致时的版本号
+    quit_times: u8,                       // 剩余的退出确认次数
+    status_message: StatusMessage,        // 状态栏消息
+    filename: Option<String>,             // 当前文件名
+    is_searching: bool,                   // 是否处于搜索模式
+    current_search: Option<String>,       // 当前的搜索文本
+    search_state: SearchState,            // 搜索状态
+    syntax_job: Option<SyntaxJob>,  // 后台进行中的全文语法高亮
+    selection: Option<Selection>,          // 文本选择状态
+    sys_clipboard: Option<ClipboardContext>, // 系统剪贴板访问
+    insert_mode: bool,                    // 插入模式，false 时为覆盖模式
+    page_overlap: usize,                  // 翻页时保留的上下文行数
+    hex_view: Option<HexView>,            // 十六进制视图
+    syntax: &'static Syntax,              // 当前生效的语法高亮规则
+    undo_stack: UndoStack,                // 撤销/重做栈
+    show_line_numbers: bool,              // 是否显示行号
+    line_ending: LineEnding,              // 保存时使用的换行符
+    trailing_newline: bool,               // 文件末尾是否有换行符
+    tab_width: usize,                     // 制表符的显示宽度
+    insert_spaces: bool,                  // Tab 键是否插入空格
+    matching_bracket: Option<(Position, Position)>,  // 光标旁的括号和与之匹配的括号
+    matching_bracket_key: Option<(u64, Position)>,   // 计算括号匹配时的版本号和光标位置
+    word_wrap: bool,                      // 是否自动换行
+    buffers: Vec<Option<Buffer>>,         // 所有已打开的缓冲区
+    current_buffer: usize,                // 当前缓冲区的位置
+    read_only: bool,                      // 是否只读
+    theme: &'static Theme,                // 当前的配色方案
+    goal_column: Option<usize>,           // 上下移动时保持的显示列
+    scroll_off: usize,                    // 光标周围保留的上下文行数
+    final_newline: Option<bool>,          // 强制的末尾换行设置，None 时保持文件原样
+    large_file_mb: u64,                   // 大文件确认阈值（MB）
+    message_timeout: Duration,            // 普通状态消息的显示时长
+    error_message_timeout: Duration,      // 错误消息的显示时长
+    block_clipboard: Option<String>,      // 最近一次矩形复制的内容
+    show_whitespace: bool,                // 是否显示空白字符
+    show_indent_guides: bool,             // 是否显示缩进参考线
+    highlight_trailing_whitespace: bool,  // 是否高亮行尾空白
+    trim_trailing_whitespace: bool,       // 保存时是否删除行尾空白
+    remember_positions: bool,             // 是否记住光标位置
+    virtual_space: bool,                  // 是否开启虚拟空间
+    spell_check: bool,                    // 是否开启拼写检查
+    dictionary_path: PathBuf,             // 拼写检查的词表文件
+    dictionary: Option<HashSet<String>>,  // 已加载的词表
+    backup_suffix: Option<String>,        // 备份文件名的后缀
+    backed_up: HashSet<PathBuf>,          // 已备份过的文件
+    pending_count: Option<usize>,         // 下一个移动命令的重复次数
+    vi_mode: Option<ViMode>,              // 模式编辑的当前模式
+    pending_operator: Option<char>,       // 等待第二个按键的普通模式命令
+    highlight_search: bool,               // 搜索结束后是否继续高亮匹配
+    kill_ring: VecDeque<String>,          // 最近删除的文本
+    last_kill: Option<(u64, Position)>,   // 上一次删除后的版本号和光标位置
+    keymap: Keymap,                       // 按键绑定
+    filetypes: Vec<(String, &'static Syntax)>,  // 文件名模式对应的语法规则
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    /// 创建新的编辑器实例，使用配置文件中的设置
+    pub fn new() -> Self {
+        let (config, config_error) = Config::load();
+        Self::with_config(config, config_error)
+    }
+
+    /// 用给定的配置创建编辑器实例
+    /// 
+    /// # 参数
+    /// * `config` - 编辑器设置
+    /// * `config_error` - 读取配置时的错误，启动后显示在状态栏
+    fn with_config(config: Config, mut config_error: Option<String>) -> Self {
+        let size = terminal::size()
+            .map(|(w, h)| (w as usize, h as usize))
+            .unwrap_or((80, 24));
+
+        // 初始化系统剪贴板
+        let sys_clipboard = ClipboardContext::new().ok();
+        let dictionary = if config.spell_check {
+            match load_dictionary(&config.dictionary) {
+                Ok(dictionary) => Some(dictionary),
+                Err(e) => {
+                    config_error.get_or_insert(format!("无法读取词表 {}: {}", config.dictionary.display(), e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        
+        Self {
+            should_quit: false,
+            cursor_position: Position::default(),
+            offset: Position::default(),
+            screen_rows: size.1.saturating_sub(2),
+            screen_cols: size.0,
+            rows: Arc::new(RwLock::new(Vec::new())),
+            version: 0,
+            last_version: 0,
+            saved_version: 0,
+            quit_times: QUIT_TIMES,
+            status_message: config_error.map_or(StatusMessage::from(String::new()), StatusMessage::error),
+            filename: None,
+            is_searching: false,
+            current_search: None,
+            search_state: SearchState::default(),
+            syntax_job: None,
+            selection: None,  // 初始化选择状态
+            sys_clipboard,
+            insert_mode: true,
+            page_overlap: config.page_overlap,
+            hex_view: None,
+            syntax: &PLAIN_SYNTAX,
+            undo_stack: UndoStack::default(),
+            show_line_numbers: config.line_numbers,
+            line_ending: LineEnding::default(),
+            trailing_newline: true,
+            tab_width: config.tab_width,
+            insert_spaces: config.insert_spaces,
+            matching_bracket: None,
+            matching_bracket_key: None,
+            word_wrap: config.word_wrap,
+            buffers: vec![None],
+            current_buffer: 0,
+            read_only: false,
+            theme: config.theme,
+            goal_column: None,
+            scroll_off: config.scroll_off,
+            final_newline: config.final_newline,
+            large_file_mb: config.large_file_mb,
+            message_timeout: config.message_timeout,
+            error_message_timeout: config.error_message_timeout,
+            block_clipboard: None,
+            show_whitespace: false,
+            show_indent_guides: config.show_indent_guides,
+            highlight_trailing_whitespace: config.highlight_trailing_whitespace,
+            trim_trailing_whitespace: config.trim_trailing_whitespace,
+            remember_positions: config.remember_positions,
+            virtual_space: config.virtual_space,
+            spell_check: dictionary.is_some(),
+            dictionary_path: config.dictionary,
+            dictionary,
+            backup_suffix: config.backup.then_some(config.backup_suffix),
+            backed_up: HashSet::new(),
+            pending_count: None,
+            vi_mode: config.modal_editing.then_some(ViMode::Normal),
+            pending_operator: None,
+            highlight_search: config.highlight_search,
+            kill_ring: VecDeque::new(),
+            last_kill: None,
+            keymap: config.keymap,
+            filetypes: config.filetypes,
+        }
+    }
+
+    /// 打开指定文件
+    /// 
+    /// 文件超过 `large_file_mb`、不是普通文件或内容看起来是二进制时先请求用户确认；
+    /// 不是有效的 UTF-8 时有损解码并设为只读
+    /// 
+    /// # 参数
+    /// * `filename` - 要打开的文件路径
+    fn open(&mut self, filename: &str) -> io::Result<()> {
+        // 整个文件会读入内存，过大的文件和设备等非普通文件先确认
+        let metadata = fs::metadata(filename)?;
+        let large = metadata.len() > self.large_file_mb.saturating_mul(1024 * 1024);
+        // 其余的普通文件先读入，根据内容判断是否为二进制
+        let bytes = if metadata.is_file() && !large { Some(fs::read(filename)?) } else { None };
+        let question = if !metadata.is_file() {
+            Some(format!("{} 不是普通文件，仍然打开？(y/n) ", filename))
+        } else if large {
+            Some(format!("{} 有 {} MB，仍然打开？(y/n) ", filename, metadata.len() / (1024 * 1024)))
+        } else if bytes.as_deref().is_some_and(looks_binary) {
+            Some(format!("{} 看起来是二进制文件，仍然打开？(y/n) ", filename))
+        } else {
+            None
+        };
+        if let Some(question) = question {
+            if !self.confirm(&question)? {
+                self.status_message = StatusMessage::from(format!("已取消打开 {}", filename));
+                return Ok(());
+            }
+        }
+        let bytes = match bytes {
+            Some(bytes) => bytes,
+            None => fs::read(filename)?,
+        };
+        // 不是有效的 UTF-8 时按替换字符解码，并以只读方式打开，避免保存时无意中改写原内容
+        let (contents, lossy) = match String::from_utf8(bytes) {
+            Ok(contents) => (contents, false),
+            Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), true),
+        };
+        if lossy {
+            self.read_only = true;
+            self.status_message = StatusMessage::error(format!(
+                "{} 不是有效的 UTF-8，无效字节已显示为 \u{fffd}，已设为只读（保存会改变文件内容，Alt-R 取消只读）",
+                filename
+            ));
+        }
+        self.filename = Some(filename.to_string());
+        self.syntax = syntax_for_filename(filename, &self.filetypes);
+        self.line_ending = LineEnding::detect(&contents);
+        // 空文件视为没有末尾换行，保存后仍为空
+        self.trailing_newline = contents.ends_with('\n');
+        let mut progress = Progress::new("正在加载");
+        let mut loaded = 0;
+        let mut new_rows = Vec::new();
+        for line in contents.lines() {
+            loaded += line.len() + 1;
+            let mut row = Row::new(line.to_string(), self.syntax);
+            if new_rows.last().is_some_and(|previous: &Row| previous.open_comment_at_end) {
+                row.starts_in_comment = true;
+                row.update_syntax(self.syntax);
+            }
+            new_rows.push(row);
+            progress.update(&mut stdout(), loaded, contents.len(), self.screen_rows + 1);
+        }
+        let mut rows = self.rows.write().unwrap();
+        *rows = new_rows;
+        drop(rows);
+        // 载入不改变版本号
+        self.matching_bracket_key = None;
+        self.saved_version = self.version;
+        self.undo_stack.clear();
+        self.restore_position();
+        Ok(())
+    }
+
+    /// 恢复上次离开当前文件时的光标位置，记录的位置已超出文档范围时忽略
+    fn restore_position(&mut self) {
+        let Some(store) = self.positions_store() else {
+            return;
+        };
+        let Some(position) = self.filename.as_deref().and_then(|filename| recall_position(&store, Path::new(filename))) else {
+            return;
+        };
+        let in_range = self.rows.read().unwrap().get(position.y).is_some_and(|row| position.x <= row.len);
+        if in_range {
+            self.jump_to(position.y + 1, Some(position.x + 1));
+        }
+    }
+
+    /// 记录当前文件的光标位置，由 `save`、退出和替换当前文档时调用
+    fn remember_position(&self) {
+        if let Some(filename) = &self.filename {
+            self.remember_position_of(filename, self.cursor_position);
+        }
+    }
+
+    /// 记录指定文件的光标位置，写入失败时忽略（状态文件只是为了方便）
+    fn remember_position_of(&self, filename: &str, position: Position) {
+        if let Some(store) = self.positions_store() {
+            remember_position(&store, Path::new(filename), position).unwrap_or(());
+        }
+    }
+
+    /// 光标位置记录文件的路径，关闭了 `remember_positions` 或无法确定数据目录时返回 None
+    fn positions_store(&self) -> Option<PathBuf> {
+        self.remember_positions.then(data_dir).flatten().map(|dir| dir.join("positions"))
+    }
+
+    /// 提示输入路径，在当前缓冲区中打开另一个文件
+    /// 
+    /// 当前文档有未保存的修改时先确认；文件不存在时询问是否以该名称新建空文档。
+    /// 打开失败或被取消时保留原来的文档
+    fn open_file(&mut self) -> io::Result<()> {
+        if !self.maybe_confirm_discard()? {
+            return Ok(());
+        }
+        let Some(input) = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("Open: ", None)? else {
+            return Ok(());
+        };
+        self.open_path(input.trim())
+    }
+
+    /// 在当前缓冲区中打开指定的文件，由 `open_file` 和 `:e` 命令调用
+    /// 
+    /// 调用前应已确认丢弃当前文档的修改
+    fn open_path(&mut self, filename: &str) -> io::Result<()> {
+        let filename = filename.to_string();
+        if filename.is_empty() {
+            return Ok(());
+        }
+        // 当前文件即将被替换，先记下它的光标位置
+        self.remember_position();
+
+        if fs::metadata(&filename).is_err() {
+            if !self.confirm(&format!("{} 不存在，新建空文件？(y/n) ", filename))? {
+                self.status_message = StatusMessage::from(format!("已取消打开 {}", filename));
+                return Ok(());
+            }
+            let mut buffer = Buffer::new();
+            buffer.syntax = syntax_for_filename(&filename, &self.filetypes);
+            buffer.filename = Some(filename.clone());
+            self.swap_buffer(buffer);
+            self.status_message = StatusMessage::from(format!("新文件 {}", filename));
+            return Ok(());
+        }
+
+        let previous = self.swap_buffer(Buffer::new());
+        match self.open(&filename) {
+            // open 在用户拒绝打开二进制文件时不设置文件名
+            Ok(()) if self.filename.is_none() => {
+                self.swap_buffer(previous);
+            }
+            Ok(()) => {
+                self.status_message = StatusMessage::from(format!("已打开 {}", filename));
+            }
+            Err(e) => {
+                self.swap_buffer(previous);
+                self.status_message = StatusMessage::error(format!("无法打开 {}: {}", filename, e));
+            }
+        }
+        Ok(())
+    }
+
+    /// 用一个未命名的空文档替换当前缓冲区
+    /// 
+    /// 当前文档有未保存的修改时先确认。与 `new_buffer` 不同，不会新增缓冲区
+    fn new_document(&mut self) -> io::Result<()> {
+        if !self.maybe_confirm_discard()? {
+            return Ok(());
+        }
+        self.remember_position();
+        self.swap_buffer(Buffer::new());
+        self.status_message = StatusMessage::from("新建空文档".to_string());
+        Ok(())
+    }
+
+    /// 文档是否有未保存的修改
+    fn is_dirty(&self) -> bool {
+        self.version != self.saved_version
+    }
+
+    /// 从磁盘重新加载当前文件
+    /// 
+    /// 有未保存的修改时先确认是否放弃。重新加载后光标尽量停在原来的位置，
+    /// 超出新内容时收回到文档末尾；文件已不存在或读取失败时保留原来的文档
+    fn reload(&mut self) -> io::Result<()> {
+        let Some(filename) = self.filename.clone() else {
+            self.status_message = StatusMessage::error("当前文档没有对应的文件".to_string());
+            return Ok(());
+        };
+        if fs::metadata(&filename).is_err() {
+            self.status_message = StatusMessage::error(format!("{} 已不存在，无法重新加载", filename));
+            return Ok(());
+        }
+        if self.is_dirty() && !self.confirm("放弃未保存的修改并重新加载？(y/n) ")? {
+            self.status_message = StatusMessage::from("已取消重新加载".to_string());
+            return Ok(());
+        }
+
+        let previous = self.swap_buffer(Buffer::new());
+        match self.open(&filename) {
+            // open 在用户拒绝打开二进制文件时不设置文件名
+            Ok(()) if self.filename.is_none() => {
+                self.swap_buffer(previous);
+            }
+            Ok(()) => {
+                self.cursor_position = self.clamp_cursor(previous.cursor_position);
+                self.offset = previous.offset;
+                // 手动设置的只读模式在重新加载后保留
+                self.read_only |= previous.read_only;
+                self.goal_column = None;
+                self.status_message = StatusMessage::from(format!("已重新加载 {}", filename));
+            }
+            Err(e) => {
+                self.swap_buffer(previous);
+                self.status_message = StatusMessage::error(format!("无法重新加载 {}: {}", filename, e));
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否有任何缓冲区（包括后台缓冲区）有未保存的修改
+    fn any_dirty(&self) -> bool {
+        self.is_dirty() || self.buffers.iter().flatten().any(Buffer::is_dirty)
+    }
+
+    /// 用另一个缓冲区的状态替换当前缓冲区，返回原来的状态
+    fn swap_buffer(&mut self, buffer: Buffer) -> Buffer {
+        // 高亮结果属于当前文档，切换前等待它完成并写回
+        self.finish_syntax_job();
+        let previous = Buffer {
+            rows: std::mem::replace(&mut self.rows, buffer.rows),
+            filename: std::mem::replace(&mut self.filename, buffer.filename),
+            cursor_position: std::mem::replace(&mut self.cursor_position, buffer.cursor_position),
+            offset: std::mem::replace(&mut self.offset, buffer.offset),
+            version: std::mem::replace(&mut self.version, buffer.version),
+            last_version: std::mem::replace(&mut self.last_version, buffer.last_version),
+            saved_version: std::mem::replace(&mut self.saved_version, buffer.saved_version),
+            selection: std::mem::replace(&mut self.selection, buffer.selection),
+            syntax: std::mem::replace(&mut self.syntax, buffer.syntax),
+            undo_stack: std::mem::replace(&mut self.undo_stack, buffer.undo_stack),
+            line_ending: std::mem::replace(&mut self.line_ending, buffer.line_ending),
+            trailing_newline: std::mem::replace(&mut self.trailing_newline, buffer.trailing_newline),
+            read_only: std::mem::replace(&mut self.read_only, buffer.read_only),
+        };
+        self.hex_view = None;
+        self.quit_times = QUIT_TIMES;
+        self.last_kill = None;
+        // 匹配数缓存以版本号区分，但每个新缓冲区的版本号都从 0 开始
+        self.search_state.match_counts = None;
+        self.search_state.match_status = None;
+        self.search_state.last_match = None;
+        self.matching_bracket_key = None;
+        self.goal_column = None;
+        previous
+    }
+
+    /// 检查当前缓冲区是否允许修改，只读时在状态栏提示
+    /// 
+    /// 所有修改文档的操作在开始时调用
+    fn check_writable(&mut self) -> bool {
+        if self.read_only {
+            self.status_message = StatusMessage::error("buffer is read-only".to_string());
+        }
+        !self.read_only
+    }
+
+    /// 检查文档是否可以保存，只读时在状态栏提示并返回 false
+    /// 
+    /// 按替换字符解码的文件以只读方式打开，保存会把无效字节改写为 U+FFFD，
+    /// 强制保存和另存为也同样拒绝
+    fn check_savable(&mut self) -> bool {
+        if self.read_only {
+            self.status_message = StatusMessage::error("只读模式下不能保存（Alt-R 取消只读后再保存）".to_string());
+        }
+        !self.read_only
+    }
+
+    /// 切换只读模式
+    fn toggle_read_only(&mut self) {
+        self.read_only = !self.read_only;
+        self.status_message = StatusMessage::from(if self.read_only {
+            "已切换为只读模式".to_string()
+        } else {
+            "已取消只读模式".to_string()
+        });
+    }
+
+    /// 在丢弃当前缓冲区内容之前确认未保存的修改
+    /// 
+    /// 文档有修改时询问用户保存、放弃还是取消；选择保存时先写入文件。
+    /// 打开、关闭等会替换当前文档的操作应在开始时调用
+    /// 
+    /// # 返回值
+    /// 可以继续（没有修改、已保存或用户选择放弃）时返回 true，否则返回 false
+    fn maybe_confirm_discard(&mut self) -> io::Result<bool> {
+        if !self.is_dirty() {
+            return Ok(true);
+        }
+        let answer = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>(
+            "有未保存的修改：保存 (s) / 放弃 (d) / 取消 (c)? ",
+            None,
+        )?;
+        match answer.map(|a| a.trim().to_lowercase()).as_deref() {
+            Some("s") | Some("save") => {
+                self.save(false)?;
+                // 保存可能被取消（例如未输入文件名），此时不能继续
+                Ok(!self.is_dirty())
+            }
+            Some("d") | Some("discard") => Ok(true),
+            _ => {
+                self.status_message = StatusMessage::from("已取消".to_string());
+                Ok(false)
+            }
+        }
+    }
+
+    /// 切换到 `THEMES` 中的下一个配色方案
+    fn cycle_theme(&mut self) {
+        let index = THEMES.iter().position(|&theme| std::ptr::eq(theme, self.theme)).unwrap_or(0);
+        self.theme = THEMES[(index + 1) % THEMES.len()];
+        self.status_message = StatusMessage::from(format!("配色方案: {}", self.theme.name));
+    }
+
+    /// 切换到指定位置的缓冲区
+    /// 
+    /// # 参数
+    /// * `index` - 目标缓冲区在 `buffers` 中的位置
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        let Some(buffer) = self.buffers[index].take() else {
+            return;
+        };
+        let previous = self.swap_buffer(buffer);
+        self.buffers[self.current_buffer] = Some(previous);
+        self.current_buffer = index;
+        self.status_message = StatusMessage::from(format!(
+            "缓冲区 {}/{}: {}",
+            index + 1,
+            self.buffers.len(),
+            self.filename.as_deref().unwrap_or("[No Name]")
+        ));
+    }
+
+    /// 在已打开的缓冲区之后新建一个空缓冲区并切换过去
+    fn new_buffer(&mut self) {
+        let previous = self.swap_buffer(Buffer::new());
+        self.buffers[self.current_buffer] = Some(previous);
+        self.buffers.push(None);
+        self.current_buffer = self.buffers.len() - 1;
+    }
+
+    /// 提示输入编号选择缓冲区
+    fn pick_buffer(&mut self) -> io::Result<()> {
+        let prompt = format!("缓冲区编号 (1-{}): ", self.buffers.len());
+        let Some(input) = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>(&prompt, None)? else {
+            return Ok(());
+        };
+        match input.trim().parse::<usize>() {
+            Ok(number) if (1..=self.buffers.len()).contains(&number) => self.switch_buffer(number - 1),
+            _ => {
+                self.status_message = StatusMessage::error(format!("无效的缓冲区编号: {}", input.trim()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 标记文档已被修改，分配新的修改版本号
+    fn mark_dirty(&mut self) {
+        self.last_version += 1;
+        self.version = self.last_version;
+    }
+
+    /// 获取从第 `y` 行开始的 `count` 行内容的副本，超出文档的部分忽略
+    fn snapshot_rows(&self, y: usize, count: usize) -> Vec<String> {
+        let rows = self.rows.read().unwrap();
+        rows.iter().skip(y).take(count).map(|row| row.string.clone()).collect()
+    }
+
+    /// 把一次修改记录到撤销栈
+    /// 
+    /// 必须在修改完成后、`mark_dirty` 之前调用，以记录修改前的版本号
+    /// 
+    /// # 参数
+    /// * `kind` - 操作种类
+    /// * `y` - 修改区域的起始行
+    /// * `before` - 修改前从 `y` 开始的行内容
+    /// * `after_len` - 修改后该区域的行数
+    /// * `cursor_before` - 修改前的光标位置
+    fn push_undo(&mut self, kind: EditKind, y: usize, before: Vec<String>, after_len: usize, cursor_before: Position) {
+        self.push_undo_word(kind, y, before, after_len, cursor_before, true);
+    }
+
+    /// 同 `push_undo`，`starts_word` 为 false 时允许与上一次输入合并
+    fn push_undo_word(&mut self, kind: EditKind, y: usize, before: Vec<String>, after_len: usize, cursor_before: Position, starts_word: bool) {
+        // 所有编辑都经过这里：被修改的行在这里统一重新高亮，并把块注释状态传递给后续行。
+        // 编辑操作本身不必单独高亮；`Row::append` 等自带的高亮只保证行本身一致，
+        // 跨行的块注释状态以这里的结果为准
+        update_syntax_from(&mut self.rows.write().unwrap(), y, after_len, self.syntax);
+        let after = self.snapshot_rows(y, after_len);
+        let edit = Edit { y, before, after };
+        let (cursor_after, version) = (self.cursor_position, self.version);
+        self.undo_stack.record(kind, edit, cursor_before, cursor_after, version, starts_word);
+    }
+
+    /// 开始一组作为整体撤销的修改
+    fn begin_undo_group(&mut self, kind: EditKind) {
+        let (cursor, version) = (self.cursor_position, self.version);
+        self.undo_stack.begin_group(kind, cursor, version);
+    }
+
+    /// 结束当前的修改组
+    fn end_undo_group(&mut self) {
+        let cursor = self.cursor_position;
+        self.undo_stack.end_group(cursor);
+    }
+
+    /// 撤销上一组修改
+    fn undo(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Some(mut group) = self.undo_stack.undo.pop_back() else {
+            self.status_message = StatusMessage::from("没有可撤销的操作".to_string());
+            return;
+        };
+        {
+            let mut rows = self.rows.write().unwrap();
+            for edit in group.edits.iter().rev() {
+                edit.revert(&mut rows, self.syntax);
+            }
+        }
+        group.version_after = self.version;
+        self.version = group.version_before;
+        self.cursor_position = group.cursor_before;
+        self.selection = None;
+        self.status_message = StatusMessage::from(format!("已撤销: {}", group.kind.description()));
+        self.undo_stack.redo.push(group);
+    }
+
+    /// 重做上一组被撤销的修改
+    fn redo(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Some(mut group) = self.undo_stack.redo.pop() else {
+            self.status_message = StatusMessage::from("没有可重做的操作".to_string());
+            return;
+        };
+        {
+            let mut rows = self.rows.write().unwrap();
+            for edit in group.edits.iter() {
+                edit.apply(&mut rows, self.syntax);
+            }
+        }
+        group.version_before = self.version;
+        self.version = group.version_after;
+        self.cursor_position = group.cursor_after;
+        self.selection = None;
+        self.status_message = StatusMessage::from(format!("已重做: {}", group.kind.description()));
+        self.undo_stack.undo.push_back(group);
+    }
+
+    /// 保存当前文件
+    /// 
+    /// 如果是新文件，会提示输入文件名。
+    /// 文档自上次保存后没有修改时跳过写入，除非 `force` 为 true。
+    /// 只读模式下（包括不是有效 UTF-8 而以只读方式打开的文件）拒绝保存。
+    /// 保存前后光标和视图的位置保持不变
+    /// 
+    /// # 参数
+    /// * `force` - 是否强制写入磁盘
+    fn save(&mut self, force: bool) -> io::Result<()> {
+        self.preserve_cursor(|editor| editor.write_document(force)).map(|_| ())
+    }
+
+    /// 执行保存的各个步骤，由 `save` 调用
+    /// 
+    /// # 返回值
+    /// 是否写入了文件；没有需要保存的修改、取消或写入失败时返回 false
+    fn write_document(&mut self, force: bool) -> io::Result<bool> {
+        if !self.check_savable() {
+            return Ok(false);
+        }
+        if !force && self.filename.is_some() && !self.is_dirty() {
+            self.status_message = StatusMessage::from("没有需要保存的修改".to_string());
+            return Ok(false);
+        }
+
+        let Some(name) = self.filename.clone() else {
+            return match self.prompt_filename()? {
+                Some(name) => self.save_to(name),
+                None => Ok(false),
+            };
+        };
+        if let Err(e) = self.backup_before_write(&name) {
+            self.status_message = StatusMessage::error(format!("无法创建 {} 的备份，未保存: {}", name, e));
+            return Ok(false);
+        }
+        if self.trim_trailing_whitespace {
+            self.strip_trailing_whitespace();
+        }
+        // 写入失败（如目录不存在或没有权限）时保留修改状态，编辑器继续运行
+        if let Err(e) = fs::write(&name, self.document_contents()) {
+            self.status_message = StatusMessage::error(format!("无法保存 {}: {}", name, e));
+            return Ok(false);
+        }
+        self.saved_version = self.version;
+        let line_count = self.rows.read().unwrap().len();
+        self.status_message = StatusMessage::from(
+            format!("{} written", line_count)
+        );
+        self.remember_position();
+        Ok(true)
+    }
+
+    /// 以新的文件名保存，并按新的扩展名重新选择语法规则
+    /// 
+    /// 写入失败时恢复原来的文件名和语法规则，缓冲区仍然对应原来的文件
+    /// 
+    /// # 返回值
+    /// 是否写入了文件
+    fn save_to(&mut self, name: String) -> io::Result<bool> {
+        let previous_syntax = self.syntax;
+        self.syntax = syntax_for_filename(&name, &self.filetypes);
+        let previous_name = self.filename.replace(name);
+        let written = self.preserve_cursor(|editor| editor.write_document(true))?;
+        if written {
+            self.update_syntax_async();
+        } else {
+            self.filename = previous_name;
+            self.syntax = previous_syntax;
+        }
+        Ok(written)
+    }
+
+    /// 删除每行末尾的空格和制表符，所有改动作为一次修改记录到撤销栈
+    /// 
+    /// 由 `write_document` 在开启 `trim_trailing_whitespace` 时调用，
+    /// 光标由 `save` 收回到新的行尾之内
+    fn strip_trailing_whitespace(&mut self) {
+        let mut edits = Vec::new();
+        let mut rows = self.rows.write().unwrap();
+        for y in 0..rows.len() {
+            let trimmed = rows[y].string.trim_end_matches([' ', '\t']);
+            if trimmed.len() == rows[y].string.len() {
+                continue;
+            }
+            let trimmed = trimmed.to_string();
+            edits.push(Edit {
+                y,
+                before: vec![rows[y].string.clone()],
+                after: vec![trimmed.clone()],
+            });
+            rows[y].set_string(trimmed);
+            update_syntax_from(&mut rows, y, 1, self.syntax);
+        }
+        drop(rows);
+        if !edits.is_empty() {
+            let (cursor, version) = (self.cursor_position, self.version);
+            self.undo_stack.begin_group(EditKind::TrimWhitespace, cursor, version);
+            for edit in edits {
+                self.undo_stack.record(EditKind::TrimWhitespace, edit, cursor, cursor, version, true);
+            }
+            self.undo_stack.end_group(cursor);
+            self.mark_dirty();
+        }
+    }
+
+    /// 保存时写入文件的内容：各行以文档的换行符连接，按设置决定是否加末尾换行
+    fn document_contents(&self) -> String {
+        let rows = self.rows.read().unwrap();
+        let line_ending = self.line_ending.as_str();
+        let mut contents: String = rows.iter().map(|row| row.string.as_str()).collect::<Vec<&str>>().join(line_ending);
+        // 配置文件未指定时与打开时读到的保持一致，重复保存的内容不会变化
+        let final_newline = self.final_newline.unwrap_or(self.trailing_newline);
+        if final_newline && !rows.is_empty() {
+            contents.push_str(line_ending);
+        }
+        contents
+    }
+
+    /// 在本次运行中第一次覆盖文件之前，把磁盘上的原内容复制到备份文件
+    /// 
+    /// 之后的保存不再更新备份，备份始终是开始编辑之前的版本。
+    /// 没有开启备份或文件还不存在时什么也不做
+    /// 
+    /// # 参数
+    /// * `name` - 即将写入的文件名，备份文件名为它加上 `backup_suffix`
+    fn backup_before_write(&mut self, name: &str) -> io::Result<()> {
+        let Some(suffix) = &self.backup_suffix else {
+            return Ok(());
+        };
+        let Ok(path) = fs::canonicalize(name) else {
+            return Ok(());
+        };
+        if self.backed_up.contains(&path) {
+            return Ok(());
+        }
+        fs::copy(&path, format!("{}{}", name, suffix))?;
+        self.backed_up.insert(path);
+        Ok(())
+    }
+
+    /// 执行可能移动光标的操作，之后恢复原来的光标和视图位置
+    /// 
+    /// 操作改变了文档内容时，光标限制在新内容的范围内
+    fn preserve_cursor<T>(&mut self, operation: impl FnOnce(&mut Self) -> T) -> T {
+        let (cursor_position, offset) = (self.cursor_position, self.offset);
+        let result = operation(self);
+        self.cursor_position = self.clamp_cursor(cursor_position);
+        self.offset = offset;
+        result
+    }
+
+    /// 把光标位置限制在文档内，允许停在最后一行之后的空行上
+    fn clamp_cursor(&self, position: Position) -> Position {
+        let rows = self.rows.read().unwrap();
+        let y = position.y.min(rows.len());
+        let x = rows.get(y).map_or(0, |row| position.x.min(row.len));
+        Position { x, y }
+    }
+
+    /// 另存为：总是提示输入新文件名，然后写入新文件
+    fn save_as(&mut self) -> io::Result<()> {
+        if !self.check_savable() {
+            return Ok(());
+        }
+        if let Some(name) = self.prompt_filename()? {
+            self.save_to(name)?;
+        }
+        Ok(())
+    }
+
+    /// 提示输入保存的文件名
+    /// 
+    /// # 返回值
+    /// 输入的文件名；取消、输入为空或拒绝覆盖已有文件时返回 None，并提示保存已取消
+    fn prompt_filename(&mut self) -> io::Result<Option<String>> {
+        let new_name = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("Save as: ", None)?.unwrap_or(String::new());
+        if new_name.is_empty() {
+            self.status_message = StatusMessage::from("Save aborted.".into());
+            return Ok(None);
+        }
+        // 不要悄悄覆盖磁盘上已有的其它文件，写回当前文件本身不需要确认
+        if fs::metadata(&new_name).is_ok()
+            && !self.is_current_file(&new_name)
+            && !self.confirm("File exists. Overwrite? (y/n) ")?
+        {
+            self.status_message = StatusMessage::from("Save aborted.".into());
+            return Ok(None);
+        }
+        Ok(Some(new_name))
+    }
+
+    /// 文件名是否指向当前缓冲区的文件（按规范化后的路径比较）
+    fn is_current_file(&self, name: &str) -> bool {
+        let Some(current) = self.filename.as_deref().and_then(|current| fs::canonicalize(current).ok()) else {
+            return false;
+        };
+        fs::canonicalize(name).is_ok_and(|path| path == current)
+    }
+
+    /// 在当前光标位置插入换行符
+    fn insert_newline(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let Position { x, y } = self.cursor_position;
+        let before = self.snapshot_rows(y, 1);
+        let mut rows = self.rows.write().unwrap();
+        if y == rows.len() {
+            rows.push(Row::new(String::new(), self.syntax));
+            self.cursor_position.y = y + 1;
+            self.cursor_position.x = 0;
+        } else {
+            let new_row = rows[y].split(x, self.syntax);
+            rows.insert(y + 1, new_row);
+            self.cursor_position.y = y + 1;
+            self.cursor_position.x = 0;
+        }
+        drop(rows);
+        let after_len = before.len() + 1;
+        self.push_undo(EditKind::Newline, y, before, after_len, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 行操作（复制、移动）作用的行范围
+    /// 
+    /// 有选择时为选择覆盖的所有行，否则为光标所在行
+    /// 
+    /// # 返回值
+    /// 首行和末行的行号（包含）
+    fn selected_lines(&self) -> (usize, usize) {
+        match self.selection {
+            Some(selection) if !selection.is_empty() => {
+                let (start, end) = selection.normalized();
+                (start.y, end.y)
+            }
+            _ => (self.cursor_position.y, self.cursor_position.y),
+        }
+    }
+
+    /// 将光标所在行（或选中的多行）上移或下移一行
+    /// 
+    /// 光标和选择随被移动的文本一起移动，已经在文档开头或末尾时不做任何操作
+    /// 
+    /// # 参数
+    /// * `down` - 为 true 时下移，否则上移
+    fn move_lines(&mut self, down: bool) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let (first, last) = self.selected_lines();
+        let total_rows = self.rows.read().unwrap().len();
+        if last >= total_rows || (down && last + 1 >= total_rows) || (!down && first == 0) {
+            return;
+        }
+
+        // 被移动的行与相邻的一行共同构成修改区域
+        let (start, end) = if down { (first, last + 1) } else { (first - 1, last) };
+        let before = self.snapshot_rows(start, end - start + 1);
+        let mut rows = self.rows.write().unwrap();
+        if down {
+            rows[start..=end].rotate_right(1);
+        } else {
+            rows[start..=end].rotate_left(1);
+        }
+        drop(rows);
+
+        let shift = |y: usize| if down { y + 1 } else { y - 1 };
+        self.cursor_position.y = shift(self.cursor_position.y);
+        if let Some(selection) = &mut self.selection {
+            selection.start.y = shift(selection.start.y);
+            selection.end.y = shift(selection.end.y);
+        }
+        let after_len = before.len();
+        self.push_undo(EditKind::MoveLines, start, before, after_len, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 注释或取消注释光标所在行（或选中的多行）
+    /// 
+    /// 使用当前语法的行注释标记（没有时默认为 `//`），插入在行首缩进之后。
+    /// 所有非空行都已注释时取消注释，否则为所有非空行添加注释。
+    /// 光标和选择随插入或删除的字符移动
+    fn toggle_comment(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let marker = self.syntax.line_comment.unwrap_or("//");
+        let (first, last) = self.selected_lines();
+        let before = self.snapshot_rows(first, last - first + 1);
+        if before.iter().all(|line| line.trim().is_empty()) {
+            return;
+        }
+
+        let indent_len = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+        let comment = !before.iter()
+            .filter(|line| !line.trim().is_empty())
+            .all(|line| line[indent_len(line)..].starts_with(marker));
+
+        let mut rows = self.rows.write().unwrap();
+        for (i, line) in before.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let y = first + i;
+            let indent = indent_len(line);
+            let (new_line, delta) = if comment {
+                (format!("{}{} {}", &line[..indent], marker, &line[indent..]), marker.len() as isize + 1)
+            } else {
+                let rest = &line[indent + marker.len()..];
+                let rest_trimmed = rest.strip_prefix(' ').unwrap_or(rest);
+                let removed = marker.len() + rest.len() - rest_trimmed.len();
+                (format!("{}{}", &line[..indent], rest_trimmed), -(removed as isize))
+            };
+            rows[y].set_string(new_line);
+
+            // 缩进只包含单字节空白字符，因此字节偏移等于字素位置
+            let adjust = |position: &mut Position| {
+                if position.y == y && position.x >= indent {
+                    position.x = position.x.saturating_add_signed(delta).max(indent);
+                }
+            };
+            adjust(&mut self.cursor_position);
+            if let Some(selection) = &mut self.selection {
+                adjust(&mut selection.start);
+                adjust(&mut selection.end);
+            }
+        }
+        drop(rows);
+
+        let after_len = before.len();
+        self.push_undo(EditKind::ToggleComment, first, before, after_len, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 缩进或取消缩进光标所在行（或选中的多行）
+    /// 
+    /// 缩进时在每个非空行行首插入一级缩进（制表符，或开启软制表符时的 `tab_width` 个空格）；取消缩进时删除行首的一个制表符
+    /// 或最多 `tab_width` 个空格，没有缩进的行保持不变。
+    /// 光标和选择随插入或删除的字符移动，始终覆盖相同的文本
+    /// 
+    /// # 参数
+    /// * `indent` - 为 true 时缩进，否则取消缩进
+    fn indent_lines(&mut self, indent: bool) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let (first, last) = self.selected_lines();
+        let before = self.snapshot_rows(first, last - first + 1);
+        if before.is_empty() {
+            return;
+        }
+
+        let mut changed = false;
+        let mut rows = self.rows.write().unwrap();
+        for (i, line) in before.iter().enumerate() {
+            let y = first + i;
+            // 缩进只涉及单字节的空白字符，因此字节数等于字素数
+            let (new_line, delta) = if indent {
+                if line.is_empty() {
+                    continue;
+                }
+                let unit = self.indent_unit();
+                let delta = unit.len() as isize;
+                (format!("{}{}", unit, line), delta)
+            } else {
+                let removed = if line.starts_with('\t') {
+                    1
+                } else {
+                    line.len() - line.trim_start_matches(' ').len()
+                }
+                .min(self.tab_width.max(1));
+                if removed == 0 {
+                    continue;
+                }
+                (line[removed..].to_string(), -(removed as isize))
+            };
+            rows[y].set_string(new_line);
+            changed = true;
+
+            let adjust = |position: &mut Position| {
+                if position.y == y {
+                    position.x = position.x.saturating_add_signed(delta);
+                }
+            };
+            adjust(&mut self.cursor_position);
+            if let Some(selection) = &mut self.selection {
+                adjust(&mut selection.start);
+                adjust(&mut selection.end);
+            }
+        }
+        drop(rows);
+
+        if changed {
+            let after_len = before.len();
+            self.push_undo(EditKind::Indent, first, before, after_len, cursor_before);
+            self.mark_dirty();
+        }
+    }
+
+    /// 复制光标所在行，插入到其下方，光标随之下移
+    /// 
+    /// 有跨越多行的选择时复制选中的所有行，选择随之移动到副本上
+    fn duplicate_lines(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let (first, last) = self.selected_lines();
+        let count = last - first + 1;
+        let before = self.snapshot_rows(first, count);
+        if before.len() != count {
+            // 光标位于文档末尾之后的空行
+            return;
+        }
+
+        let mut rows = self.rows.write().unwrap();
+        let copies: Vec<Row> = before.iter().map(|line| Row::new(line.clone(), self.syntax)).collect();
+        rows.splice(last + 1..last + 1, copies);
+        drop(rows);
+
+        self.cursor_position.y += count;
+        if let Some(selection) = &mut self.selection {
+            selection.start.y += count;
+            selection.end.y += count;
+        }
+        self.push_undo(EditKind::DuplicateLines, first, before, count * 2, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 异步更新整个文档的语法高亮
+    /// 
+    /// 普通编辑在记录撤销时同步地只重新高亮被修改的行，只有语法规则改变时
+    /// 才需要全量重新扫描。扫描在单独的线程中对文档内容的快照进行，
+    /// 期间不持有文档的锁，结果由 `apply_syntax_job` 写回。
+    /// 之前未完成的任务直接丢弃
+    fn update_syntax_async(&mut self) {
+        let snapshot = self.snapshot_rows(0, usize::MAX);
+        let syntax = self.syntax;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // 各行已按不在块注释中高亮过，只需把块注释状态向下传递
+            let mut rows: Vec<Row> = snapshot.into_iter().map(|line| Row::new(line, syntax)).collect();
+            update_syntax_from(&mut rows, 0, 1, syntax);
+            // 任务已被丢弃时接收端不存在，结果作废即可
+            let _ = sender.send(rows);
+        });
+        self.syntax_job = Some(SyntaxJob { version: self.version, receiver });
+    }
+
+    /// 写回已完成的后台语法高亮结果，任务未完成时立即返回
+    /// 
+    /// 文档在快照之后被修改过时结果已经过时，按当前内容重新开始扫描
+    fn apply_syntax_job(&mut self) {
+        let Some(job) = &self.syntax_job else {
+            return;
+        };
+        let rows = match job.receiver.try_recv() {
+            Ok(rows) => rows,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.syntax_job = None;
+                return;
+            }
+        };
+        self.install_syntax_rows(rows);
+    }
+
+    /// 等待后台语法高亮完成并写回结果
+    fn finish_syntax_job(&mut self) {
+        while let Some(job) = &self.syntax_job {
+            match job.receiver.recv() {
+                Ok(rows) => self.install_syntax_rows(rows),
+                Err(_) => self.syntax_job = None,
+            }
+        }
+    }
+
+    /// 用后台重新高亮的行替换文档的行
+    /// 
+    /// 版本号相同说明内容与快照一致，可以直接整体替换；否则重新开始扫描
+    fn install_syntax_rows(&mut self, rows: Vec<Row>) {
+        let Some(job) = self.syntax_job.take() else {
+            return;
+        };
+        if job.version == self.version {
+            let mut rows = rows;
+            let mut current = self.rows.write().unwrap();
+            // 折叠状态不属于文档内容，从当前的行中保留下来
+            for (row, old) in rows.iter_mut().zip(current.iter()) {
+                row.folded = old.folded;
+            }
+            *current = rows;
+            // 高亮改变了哪些括号在字符串和注释中
+            self.matching_bracket_key = None;
+        } else {
+            self.update_syntax_async();
+        }
+    }
+
+    /// 在当前光标位置插入字符
+    /// 
+    /// 覆盖模式下替换光标处的字符，在行尾时追加。光标在虚拟空间中时先用空格补齐到光标处
+    /// 
+    /// # 参数
+    /// * `c` - 要插入的字符
+    fn insert_char(&mut self, c: char) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let before = self.snapshot_rows(cursor_before.y, 1);
+        let mut rows = self.rows.write().unwrap();
+        if self.cursor_position.y == rows.len() {
+            rows.push(Row::new(String::new(), self.syntax));
+        }
+        let row = &mut rows[self.cursor_position.y];
+        row.pad_to(self.cursor_position.x, self.syntax);
+        if self.insert_mode {
+            row.insert(self.cursor_position.x, c);
+        } else {
+            row.replace_at(self.cursor_position.x, c);
+        }
+        self.cursor_position.x += 1;
+        drop(rows); // 释放写锁
+        self.push_undo_word(EditKind::InsertChar, cursor_before.y, before, 1, cursor_before, c.is_whitespace());
+        self.mark_dirty();
+    }
+
+    /// 在光标处插入一段文本（不含换行），作为一次修改记录到撤销栈
+    /// 
+    /// 覆盖模式下替换光标后相同数量的字素
+    /// 
+    /// # 参数
+    /// * `text` - 要插入的文本
+    fn insert_str(&mut self, text: &str) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let count = text.graphemes(true).count();
+        let before = self.snapshot_rows(cursor_before.y, 1);
+        let mut rows = self.rows.write().unwrap();
+        if self.cursor_position.y == rows.len() {
+            rows.push(Row::new(String::new(), self.syntax));
+        }
+        let replaced = if self.insert_mode { 0 } else { count };
+        let row = &mut rows[self.cursor_position.y];
+        row.pad_to(self.cursor_position.x, self.syntax);
+        row.replace_range(self.cursor_position.x, replaced, text, self.syntax);
+        self.cursor_position.x += count;
+        drop(rows);
+        self.push_undo_word(EditKind::InsertChar, cursor_before.y, before, 1, cursor_before, true);
+        self.mark_dirty();
+    }
+
+    /// 在光标处插入一段可能包含多行的文本，作为一次修改记录到撤销栈
+    /// 
+    /// 直接构造插入后的各行，受影响的行只在最后统一重新高亮一次，
+    /// 粘贴大段文本时不会逐字符地复制行和记录撤销。光标移到插入文本的末尾。
+    /// 不含换行的文本交给 `insert_str`，多行文本总是插入而不覆盖
+    /// 
+    /// # 参数
+    /// * `text` - 要插入的文本，用 `\n` 分隔各行
+    fn insert_text(&mut self, text: &str) {
+        if !text.contains('\n') {
+            self.insert_str(text);
+            return;
+        }
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let Position { x, y } = cursor_before;
+        let before = self.snapshot_rows(y, 1);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let last = lines[lines.len() - 1];
+
+        let mut rows = self.rows.write().unwrap();
+        if let Some(row) = rows.get_mut(y) {
+            row.pad_to(x, self.syntax);
+        }
+        let (head, tail) = match rows.get(y) {
+            Some(row) => {
+                let split = row.string.grapheme_indices(true).nth(x).map_or(row.string.len(), |(i, _)| i);
+                row.string.split_at(split)
+            }
+            None => ("", ""),
+        };
+        let mut strings: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        strings[0].insert_str(0, head);
+        strings[lines.len() - 1].push_str(tail);
+        let new_rows: Vec<Row> = strings.into_iter().map(|string| Row::new(string, self.syntax)).collect();
+        let replaced = y..(y + 1).min(rows.len());
+        rows.splice(replaced, new_rows);
+        drop(rows);
+
+        self.cursor_position = Position {
+            x: last.graphemes(true).count(),
+            y: y + lines.len() - 1,
+        };
+        self.push_undo(EditKind::Paste, y, before, lines.len(), cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 一级缩进：制表符，开启软制表符时为 `tab_width` 个空格
+    fn indent_unit(&self) -> String {
+        if self.insert_spaces {
+            " ".repeat(self.tab_width.max(1))
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    /// 处理 Tab 键
+    /// 
+    /// 默认插入制表符；开启软制表符时插入空格，使光标对齐到下一个制表位
+    fn insert_tab(&mut self) {
+        if !self.insert_spaces {
+            self.insert_char('\t');
+            return;
+        }
+        let tab_width = self.tab_width.max(1);
+        let spaces = tab_width - self.cursor_display_x() % tab_width;
+        self.insert_str(&" ".repeat(spaces));
+    }
+
+    /// 删除光标前的字符
+    /// 
+    /// 开启 `insert_spaces` 且光标左侧只有空格时，删除到上一个制表位为止，
+    /// 即取消一级缩进。光标在虚拟空间中时只向左移动一列，在文档末尾之后的空行上时
+    /// 只移到最后一行的行尾，都不修改文本
+    fn delete_char(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let len = self.rows.read().unwrap().get(cursor_before.y).map_or(0, |row| row.len);
+        if cursor_before.x > len {
+            self.cursor_position.x -= 1;
+            return;
+        }
+        let before = if cursor_before.x > 0 {
+            self.snapshot_rows(cursor_before.y, 1)
+        } else {
+            self.snapshot_rows(cursor_before.y.saturating_sub(1), 2)
+        };
+        let mut rows = self.rows.write().unwrap();
+        if self.cursor_position.y == rows.len() {
+            if let Some(last) = rows.len().checked_sub(1) {
+                let y = visible_row(&rows, last);
+                self.cursor_position = Position { x: rows[y].len, y };
+            }
+            return;
+        }
+        let row = &mut rows[self.cursor_position.y];
+        if self.cursor_position.x > 0 {
+            let x = self.cursor_position.x;
+            // 前导空格中每个空格占一列，光标所在的列就是 x
+            let in_indent = self.insert_spaces
+                && self.tab_width > 0
+                && row.string.graphemes(true).take(x).all(|g| g == " ");
+            let count = if in_indent {
+                match x % self.tab_width {
+                    0 => self.tab_width,
+                    rest => rest,
+                }
+            } else {
+                1
+            };
+            for _ in 0..count {
+                row.delete(x - count);
+            }
+            self.cursor_position.x -= count;
+            drop(rows); // 释放写锁
+            self.push_undo(EditKind::DeleteChar, cursor_before.y, before, 1, cursor_before);
+            self.mark_dirty();
+        } else if self.cursor_position.y > 0 {
+            let previous_len = rows[self.cursor_position.y - 1].len;
+            let row = rows.remove(self.cursor_position.y);
+            self.cursor_position.y -= 1;
+            self.cursor_position.x = previous_len;
+            rows[self.cursor_position.y].append(&row, self.syntax);
+            drop(rows); // 释放写锁
+            self.push_undo(EditKind::JoinLines, cursor_before.y - 1, before, 1, cursor_before);
+            self.mark_dirty();
+        }
+    }
+
+    /// 删除光标处的字符（Delete 键）
+    /// 
+    /// 光标在行尾时与下一行合并；在最后一行的行尾或文档末尾之后的空行上时不做任何操作
+    fn delete_char_forward(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let rows = self.rows.read().unwrap();
+        let Some(len) = rows.get(y).map(|row| row.len) else {
+            return;
+        };
+        let (end, kind) = if x < len {
+            (Position { x: x + 1, y }, EditKind::DeleteChar)
+        } else if y + 1 < rows.len() {
+            (Position { x: 0, y: y + 1 }, EditKind::JoinLines)
+        } else {
+            return;
+        };
+        drop(rows);
+        // 光标在虚拟空间中时由 `delete_range` 补齐到光标所在的列再合并
+        self.delete_range(Position { x, y }, end, kind);
+    }
+
+    /// 显示提示并获取用户输入
+    /// 
+    /// # 参数
+    /// * `prompt` - 提示文本
+    /// * `callback` - 可选的回调函数，用于处理输入过程中的按键
+    fn prompt<C>(&mut self, prompt: &str, callback: Option<C>) -> io::Result<Option<String>>
+    where
+        C: Fn(&mut Self, &str, KeyCode) -> bool,
+    {
+        let result = self.prompt_loop(prompt, callback);
+        // 提示结束后清除提示行，回调留下的普通消息按正常时长超时
+        self.clear_persistent_message();
+        result
+    }
+
+    /// 提示输入的主循环，由 `prompt` 调用
+    fn prompt_loop<C>(&mut self, prompt: &str, callback: Option<C>) -> io::Result<Option<String>>
+    where
+        C: Fn(&mut Self, &str, KeyCode) -> bool,
+    {
+        let mut result = String::new();
+
+        loop {
+            self.status_message = StatusMessage::persistent(format!("{}{}", prompt, result));
+            self.refresh_screen()?;
+
+            if event::poll(Duration::from_millis(500))? {
+                let event = event::read()?;
+                // 输入提示时终端尺寸也可能改变，下一轮刷新按新尺寸绘制
+                if let Event::Resize(columns, rows) = event {
+                    self.resize(columns as usize, rows as usize);
+                }
+                if let Event::Key(key_event) = event {
+                    if key_event.kind == KeyEventKind::Press {
+                        match key_event.code {
+                            KeyCode::Enter => {
+                                if let Some(ref callback) = callback {
+                                    if !callback(self, &result, KeyCode::Enter) {
+                                        // 如果回调返回 false，我们保持当前的状态消息
+                                        return Ok(Some(result));
+                                    }
+                                } else {
+                                    return Ok(Some(result));
+                                }
+                            }
+                            KeyCode::Esc => {
+                                if let Some(ref callback) = callback {
+                                    callback(self, &result, KeyCode::Esc);
+                                }
+                                return Ok(None);
+                            }
+                            KeyCode::Backspace => {
+                                if !result.is_empty() {
+                                    result.truncate(result.len() - 1);
+                                    if let Some(ref callback) = callback {
+                                        callback(self, &result, KeyCode::Backspace);
+                                    }
+                                }
+                            }
+                            KeyCode::Char(c) => {
+                                if !c.is_control() {
+                                    result.push(c);
+                                    if let Some(ref callback) = callback {
+                                        callback(self, &result, KeyCode::Char(c));
+                                    }
+                                }
+                            }
+                            _ => {
+                                if let Some(ref callback) = callback {
+                                    callback(self, &result, key_event.code);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 显示是/否问题并等待用户回答
+    /// 
+    /// # 参数
+    /// * `question` - 提示的问题
+    /// 
+    /// # 返回值
+    /// 用户输入 `y` 或 `yes` 时返回 true，其他输入或按 Esc 返回 false
+    fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        let answer = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>(question, None)?;
+        Ok(matches!(
+            answer.map(|a| a.trim().to_lowercase()).as_deref(),
+            Some("y") | Some("yes")
+        ))
+    }
+
+    /// 清除当前的常驻消息
+    /// 
+    /// 在持有常驻消息的模式（搜索、替换、输入提示）结束时调用
+    fn clear_persistent_message(&mut self) {
+        if self.status_message.persistent {
+            self.status_message = StatusMessage::from(String::new());
+        }
+    }
+
+    /// 处理搜索回调
+    /// 
+    /// 在搜索过程中处理用户输入，支持实时搜索
+    fn find_callback(&mut self, query: &str, key: KeyCode) -> bool {
+        // 立即更新当前搜索文本，这样渲染时就能看到高亮
+        self.current_search = if query.is_empty() {
+            None
+        } else {
+            Some(query.to_string())
+        };
+
+        match key {
+            KeyCode::Enter | KeyCode::Char('n') => {
+                // 按下回车或n时，移动到下一个匹配项
+                self.search_state.direction = 1;
+                self.search_state.last_match = None;
+            }
+            KeyCode::Esc => {
+                self.search_state.last_match = None;
+                self.search_state.direction = 1;
+                self.is_searching = false;
+                self.current_search = None;
+                self.status_message = StatusMessage::from(String::new());
+                if let Err(e) = self.refresh_screen() {
+                    eprintln!("Error refreshing screen: {}", e);
+                }
+                return false;
+            }
+            KeyCode::Right | KeyCode::Down => self.search_state.direction = 1,
+            KeyCode::Left | KeyCode::Up => self.search_state.direction = -1,
+            _ => {
+                if query.is_empty() {
+                    self.search_state.last_match = None;
+                    self.search_state.direction = 1;
+                    self.status_message = StatusMessage::from(String::new());
+                    if let Err(e) = self.refresh_screen() {
+                        eprintln!("Error refreshing screen: {}", e);
+                    }
+                    return false;
+                }
+
+                self.search_state.last_match = None;
+                self.search_state.direction = 1;
+            }
+        }
+
+        // 从上一个匹配继续查找下一处时要跳过该匹配本身
+        let current = match self.search_state.last_match {
+            Some(last) if self.search_state.direction == 1 => Position { x: last.x + 1, ..last },
+            Some(last) => last,
+            None => {
+                self.search_state.direction = 1;
+                Position { x: 0, y: 0 }
+            }
+        };
+        let found = self.move_to_match(query, current, self.search_state.direction);
+        let wrapped = found == Some(true);
+        let found = found.is_some();
+        self.update_match_status(query);
+
+        // 更新状态消息并立即刷新屏幕
+        if !found {
+            self.status_message = StatusMessage::from(
+                format!("未找到匹配项: \"{}\"", query)
+            );
+            // 立即刷新屏幕以显示错误消息
+            if let Err(e) = self.refresh_screen() {
+                eprintln!("Error refreshing screen: {}", e);
+            }
+            // 清除搜索状态
+            self.current_search = None;
+            self.is_searching = false;
+            self.search_state.last_match = None;
+            return false;
+        }
+
+        self.status_message = StatusMessage::from(if !wrapped {
+            format!("找到 \"{}\" (按 'n' 查找下一个)", query)
+        } else if self.search_state.direction == 1 {
+            format!("已搜索到末尾，从开头继续: \"{}\"", query)
+        } else {
+            format!("已搜索到开头，从末尾继续: \"{}\"", query)
+        });
+
+        // 立即刷新屏幕以显示状态消息
+        if let Err(e) = self.refresh_screen() {
+            eprintln!("Error refreshing screen: {}", e);
+        }
+        true
+    }
+
+    /// 用 `find_match` 查找匹配，找到时把光标移到匹配处并记为上一个匹配
+    /// 
+    /// 由 `find_callback` 和 `search_next` 调用
+    /// 
+    /// # 参数
+    /// * `query` - 搜索文本
+    /// * `from` - 开始查找的位置
+    /// * `direction` - 1 查找下一处，-1 查找上一处
+    /// 
+    /// # 返回值
+    /// 找到时返回是否越过了文档的末尾（或开头），否则返回 None
+    fn move_to_match(&mut self, query: &str, from: Position, direction: i32) -> Option<bool> {
+        let found = self.find_match(query, from, direction)?;
+        self.search_state.last_match = Some(found);
+        self.cursor_position = found;
+
+        // 确保光标在可见区域内
+        if found.y < self.offset.y {
+            self.offset.y = found.y;
+        } else if found.y >= self.offset.y + self.screen_rows {
+            self.offset.y = found.y - self.screen_rows + 1;
+        }
+        // 水平偏移以显示宽度计算，由 scroll() 在刷新时调整
+
+        // 查找下一处却找到了起点之前的匹配（或反过来），说明越过了文档边界
+        let before_start = (found.y, found.x) < (from.y, from.x);
+        Some(before_start == (direction == 1))
+    }
+
+    /// 从 `from` 开始按 `direction` 查找下一处匹配，不移动光标
+    /// 
+    /// 查找下一处时匹配从 `from` 或之后开始，查找上一处时匹配从 `from` 之前开始并取离它最近的一处。
+    /// 到达文档末尾（或开头）后从另一端继续，最后再检查起始行的另一侧
+    /// 
+    /// # 参数
+    /// * `query` - 搜索文本
+    /// * `from` - 开始查找的位置
+    /// * `direction` - 1 查找下一处，-1 查找上一处
+    /// 
+    /// # 返回值
+    /// 返回匹配开始的位置，如果未找到则返回None
+    fn find_match(&self, query: &str, from: Position, direction: i32) -> Option<Position> {
+        if query.is_empty() {
+            return None;
+        }
+        let rows = self.rows.read().unwrap();
+        let total_rows = rows.len();
+        let last = total_rows.checked_sub(1)?;
+        let forward = direction == 1;
+        // 光标可以停在最后一行之后的空行上，从那里查找下一处即从头开始
+        let from = match (from.y > last, forward) {
+            (false, _) => from,
+            (true, true) => Position { x: 0, y: 0 },
+            (true, false) => Position { x: usize::MAX, y: last },
+        };
+
+        for i in 0..=total_rows {
+            let step = i % total_rows;
+            let y = if forward {
+                (from.y + step) % total_rows
+            } else {
+                (from.y + total_rows - step) % total_rows
+            };
+            let row = &rows[y];
+            let x = match (forward, i == 0) {
+                (true, true) => row.search(query, from.x),
+                (true, false) => row.search(query, 0),
+                (false, true) => row.rsearch(query, from.x),
+                (false, false) => row.rsearch(query, usize::MAX),
+            };
+            if let Some(x) = x {
+                return Some(Position { x, y });
+            }
+        }
+        None
+    }
+
+    /// 更新当前匹配的序号和匹配总数，显示在状态栏
+    /// 
+    /// 每行的匹配数按查询文本和文档版本缓存；统计到 `MATCH_COUNT_LIMIT` 个后停止，
+    /// 避免在大文件中每次按键都扫描整个文档
+    /// 
+    /// # 参数
+    /// * `query` - 当前的搜索文本
+    fn update_match_status(&mut self, query: &str) {
+        let Some(current) = self.search_state.last_match.filter(|_| !query.is_empty()) else {
+            self.search_state.match_status = None;
+            return;
+        };
+        let rows = self.rows.read().unwrap();
+
+        let cached = matches!(
+            &self.search_state.match_counts,
+            Some((counted_query, version, _)) if counted_query == query && *version == self.version
+        );
+        if !cached {
+            let mut counts = Vec::new();
+            let mut total = 0;
+            for row in rows.iter() {
+                if total >= MATCH_COUNT_LIMIT {
+                    break;
+                }
+                let count = row.match_positions(query).count();
+                total += count;
+                counts.push(count);
+            }
+            self.search_state.match_counts = Some((query.to_string(), self.version, counts));
+        }
+        let Some((_, _, counts)) = &self.search_state.match_counts else {
+            return;
+        };
+
+        let total: usize = counts.iter().sum();
+        let capped = total >= MATCH_COUNT_LIMIT;
+        let index = rows.get(current.y).filter(|_| current.y < counts.len()).map(|row| {
+            let before_in_row = row.match_positions(query).take_while(|&x| x < current.x).count();
+            (counts[..current.y].iter().sum::<usize>() + before_in_row + 1).min(total)
+        });
+        self.search_state.match_status = Some((index, total, capped));
+    }
+
+    /// 替换回调函数
+    /// 
+    /// 在替换过程中处理用户输入，支持实时搜索和高亮显示。
+    /// 
+    /// # 参数
+    /// * `query` - 用户输入的搜索文本
+    /// * `key` - 用户按下的按键
+    /// 
+    /// # 返回值
+    /// * `true` - 继续接收用户输入
+    /// * `false` - 结束输入过程
+    fn replace_callback(&mut self, query: &str, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Enter => {
+                if query.is_empty() {
+                    return false;
+                }
+                self.current_search = Some(query.to_string());
+                false
+            }
+            KeyCode::Esc => {
+                self.current_search = None;
+                self.search_state.last_match = None;
+                false
+            }
+            _ => {
+                // 更新搜索文本，实时显示高亮
+                self.current_search = if query.is_empty() {
+                    None
+                } else {
+                    Some(query.to_string())
+                };
+                
+                // 查找并高亮显示匹配项
+                if let Some(found) = self.find_match(query, Position::default(), 1) {
+                    self.search_state.last_match = Some(found);
+                }
+                
+                // 刷新屏幕以显示高亮
+                if let Err(e) = self.refresh_screen() {
+                    eprintln!("Error refreshing screen: {}", e);
+                }
+                true
+            }
+        }
+    }
+
+    /// 启动替换操作
+    /// 
+    /// 执行文本替换的完整流程：
+    /// 1. 获取要搜索的文本（实时显示匹配项）
+    /// 2. 获取要替换成的文本
+    /// 3. 执行替换操作
+    /// 
+    /// # 参数
+    /// * `all` - 为 true 时替换文档中的全部匹配，否则只替换光标之后的下一处匹配
+    /// 
+    /// # 错误
+    /// 如果发生 I/O 错误，将返回该错误
+    fn replace(&mut self, all: bool) -> io::Result<()> {
+        if !self.check_writable() {
+            return Ok(());
+        }
+        let saved_cursor_position = self.cursor_position;
+        let saved_offset = self.offset;
+
+        // 第一步：获取搜索文本
+        self.is_searching = true;
+        self.search_state.match_status = None;
+        match self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("搜索要替换的文本: ", Some(Editor::replace_callback))? {
+            Some(search_text) if !search_text.is_empty() => {
+                // 第二步：获取替换文本
+                match self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("替换为: ", None)? {
+                    Some(replace_text) => {
+                        self.search_state.replace_text = Some(replace_text);
+                        if all {
+                            self.replace_all_matches();
+                        } else {
+                            self.replace_current_match();
+                        }
+                    }
+                    None => {
+                        self.status_message = StatusMessage::from("替换已取消".to_string());
+                    }
+                }
+            }
+            _ => {
+                self.status_message = StatusMessage::from("搜索已取消".to_string());
+            }
+        }
+
+        // 恢复状态
+        self.is_searching = false;
+        self.cursor_position = saved_cursor_position;
+        self.offset = saved_offset;
+        self.current_search = None;
+        self.search_state.last_match = None;
+        self.search_state.replace_text = None;
+        self.refresh_screen()?;
+        
+        Ok(())
+    }
+
+    /// 替换光标之后的下一处匹配
+    /// 
+    /// 从光标位置开始向后查找，到达文档末尾后从头继续。
+    /// 匹配位置和替换都以字素为单位，多字节字符不会被截断
+    fn replace_current_match(&mut self) {
+        let (Some(query), Some(replace_text)) = (self.current_search.clone(), self.search_state.replace_text.clone()) else {
+            return;
+        };
+        if query.is_empty() {
+            self.status_message = StatusMessage::from("搜索文本不能为空".to_string());
+            return;
+        }
+
+        let Some(position) = self.find_match(&query, self.cursor_position, 1) else {
+            self.status_message = StatusMessage::from("未找到匹配项".to_string());
+            return;
+        };
+
+        let mut rows = self.rows.write().unwrap();
+        let row = &mut rows[position.y];
+        let before = vec![row.string.clone()];
+        row.replace_range(position.x, query.graphemes(true).count(), &replace_text, self.syntax);
+        let after = vec![row.string.clone()];
+        update_syntax_from(&mut rows, position.y, 1, self.syntax);
+        drop(rows);
+
+        let (cursor, version) = (self.cursor_position, self.version);
+        let edit = Edit { y: position.y, before, after };
+        self.undo_stack.begin_group(EditKind::Replace, cursor, version);
+        self.undo_stack.record(EditKind::Replace, edit, cursor, cursor, version, true);
+        self.undo_stack.end_group(cursor);
+        self.mark_dirty();
+        self.status_message = StatusMessage::from(format!("已替换第 {} 行的匹配项", position.y + 1));
+    }
+
+    /// 替换所有匹配的文本
+    /// 
+    /// 在整个文档中查找并替换所有匹配项：
+    /// - 每行从左到右替换互不重叠的匹配
+    /// - 保持语法高亮
+    /// - 更新文档状态
+    /// - 显示替换结果统计
+    fn replace_all_matches(&mut self) {
+        if let (Some(query), Some(replace_text)) = (&self.current_search, &self.search_state.replace_text) {
+            if query.is_empty() {
+                self.status_message = StatusMessage::from("搜索文本不能为空".to_string());
+                return;
+            }
+
+            let mut total_replacements = 0;
+            let mut edits = Vec::new();
+            let mut progress = Progress::new("正在替换");
+            let total_rows = self.rows.read().unwrap().len();
+            
+            // 遍历所有行，每行单独加锁，绘制进度时不持有写锁
+            for y in 0..total_rows {
+                progress.update(&mut stdout(), y, total_rows, self.screen_rows + 1);
+                let mut rows = self.rows.write().unwrap();
+                let row = &mut rows[y];
+                // 替换前统计匹配次数，替换文本本身不会被计入
+                let count = row.string.matches(&query[..]).count();
+                if count == 0 {
+                    continue;
+                }
+
+                let new_string = row.string.replace(&query[..], replace_text);
+                total_replacements += count;
+                if new_string != row.string {
+                    edits.push(Edit {
+                        y,
+                        before: vec![row.string.clone()],
+                        after: vec![new_string.clone()],
+                    });
+                    row.set_string(new_string);
+                    update_syntax_from(&mut rows, y, 1, self.syntax);
+                }
+            }
+            if !edits.is_empty() {
+                let (cursor, version) = (self.cursor_position, self.version);
+                self.undo_stack.begin_group(EditKind::Replace, cursor, version);
+                for edit in edits {
+                    self.undo_stack.record(EditKind::Replace, edit, cursor, cursor, version, true);
+                }
+                self.undo_stack.end_group(cursor);
+                self.mark_dirty();
+            }
+
+            // 更新状态消息
+            if total_replacements > 0 {
+                self.status_message = StatusMessage::from(
+                    format!("已替换 {} 处匹配项", total_replacements)
+                );
+            } else {
+                self.status_message = StatusMessage::from(
+                    "未找到匹配项".to_string()
+                );
+            }
+        }
+    }
+
+    /// 开始文本选择
+    fn start_selection(&mut self) {
+        self.selection = Some(Selection::new(self.cursor_position));
+    }
+
+    /// 更新选择范围
+    fn update_selection(&mut self) {
+        if let Some(mut selection) = self.selection {
+            selection.end = self.cursor_position;
+            self.selection = Some(selection);
+        }
+    }
+
+    /// 选择整个文档，并将光标移到文档末尾
+    /// 
+    /// 空文档时不做任何操作
+    fn select_all(&mut self) {
+        let end = {
+            let rows = self.rows.read().unwrap();
+            let Some(last_row) = rows.last() else {
+                return;
+            };
+            Position { x: last_row.len, y: rows.len() - 1 }
+        };
+        self.selection = Some(Selection { start: Position::default(), end, block: false });
+        self.cursor_position = end;
+    }
+
+    /// 计算第 `y` 行被选中的字素范围
+    /// 
+    /// # 参数
+    /// * `rows` - 文档的所有行（调用者已持有读锁）
+    /// * `y` - 行号
+    /// 
+    /// # 返回值
+    /// 选中范围 `[from, to)`，`to` 为 `usize::MAX` 表示一直选到行尾；本行没有被选中时返回 None。
+    /// 矩形选择在每行上取相同的显示列范围
+    fn selection_span(&self, rows: &[Row], y: usize) -> Option<(usize, usize)> {
+        let selection = self.selection?;
+        let (from, to) = selection.normalized();
+        if !(from.y..=to.y).contains(&y) {
+            return None;
+        }
+        if selection.block {
+            let (left, right) = self.block_columns(rows, selection);
+            let row = rows.get(y)?;
+            return Some((self.grapheme_at_column(row, left).0, self.grapheme_at_column(row, right).0));
+        }
+        Some((
+            if from.y == y { from.x } else { 0 },
+            if to.y == y { to.x } else { usize::MAX },
+        ))
+    }
+
+    /// 矩形选择的左右边界（显示列，不含右边界）
+    fn block_columns(&self, rows: &[Row], selection: Selection) -> (usize, usize) {
+        let column = |position: Position| rows.get(position.y).map_or(0, |row| self.display_column(row, position.x));
+        let (a, b) = (column(selection.start), column(selection.end));
+        (a.min(b), a.max(b))
+    }
+
+    /// 切换矩形选择，没有选择时从光标处开始一个矩形选择
+    fn toggle_block_selection(&mut self) {
+        let mut selection = self.selection.unwrap_or_else(|| Selection::new(self.cursor_position));
+        selection.block = !selection.block;
+        self.selection = Some(selection);
+        self.status_message = StatusMessage::from(if selection.block {
+            "矩形选择".to_string()
+        } else {
+            "普通选择".to_string()
+        });
+    }
+
+    /// 清除选择
+    fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// 获取选中的文本
+    /// 
+    /// 每行按字素取出被选中的部分（与光标位置的单位一致），用换行连接；
+    /// 矩形选择与普通选择的区别只在于每行的选中范围
+    /// 
+    /// # 返回值
+    /// 没有选择、选择为空或文档为空时返回 None
+    fn selected_text(&self) -> Option<String> {
+        let selection = self.selection.filter(|selection| !selection.is_empty())?;
+        let (start, end) = selection.normalized();
+        let (start, end) = (self.clamp_to_document(start)?, self.clamp_to_document(end)?);
+        let rows = self.rows.read().unwrap();
+        let lines: Vec<String> = (start.y..=end.y)
+            .map(|y| {
+                let (from, to) = self.selection_span(&rows, y).unwrap_or((0, 0));
+                rows[y].string[..].graphemes(true).skip(from).take(to.saturating_sub(from)).collect()
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// 复制选中的文本到系统剪贴板
+    fn copy_selection(&mut self) {
+        if let Some(selection) = self.selection {
+            let Some(content) = self.selected_text() else {
+                return;
+            };
+
+            // 保存到系统剪贴板
+            if let Some(ctx) = self.sys_clipboard.as_mut() {
+                if let Err(e) = ctx.set_contents(content.clone()) {
+                    self.status_message = StatusMessage::error(
+                        format!("无法复制到系统剪贴板: {}", e)
+                    );
+                    return;
+                }
+                self.block_clipboard = selection.block.then(|| content.clone());
+                self.status_message = StatusMessage::from(
+                    format!("{} 个字符已复制到剪贴板", content.len())
+                );
+            } else {
+                self.status_message = StatusMessage::error(
+                    "系统剪贴板不可用".to_string()
+                );
+            }
+        }
+    }
+
+    /// 把位置限制在文档现有的内容内
+    /// 
+    /// 光标可以停在最后一行之后的空行上，以该处为端点的选择在复制和删除前
+    /// 需要收回到最后一行的行尾
+    /// 
+    /// # 返回值
+    /// 文档为空时返回 None
+    fn clamp_to_document(&self, position: Position) -> Option<Position> {
+        let rows = self.rows.read().unwrap();
+        let last = rows.len().checked_sub(1)?;
+        Some(if position.y > last {
+            Position { x: rows[last].len, y: last }
+        } else {
+            Position { x: position.x.min(rows[position.y].len), y: position.y }
+        })
+    }
+
+    /// 把要删除的范围限制在文档现有的内容内
+    /// 
+    /// 跨行时开始位置保留虚拟空间中的列，由 `delete_range` 补齐后再合并，
+    /// 与在同一位置按 Delete 一致
+    /// 
+    /// # 返回值
+    /// 文档为空时返回 None
+    fn clamp_range_to_document(&self, start: Position, end: Position) -> Option<(Position, Position)> {
+        let end = self.clamp_to_document(end)?;
+        let start = if start.y < end.y { start } else { self.clamp_to_document(start)? };
+        Some((start, end))
+    }
+
+    /// 删除选中的文本
+    fn delete_selection(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        if let Some(selection) = self.selection {
+            if selection.is_empty() {
+                return;
+            }
+
+            if selection.block {
+                self.delete_block();
+                return;
+            }
+            let (start, end) = selection.normalized();
+            // 先清除选择，避免后续的借用冲突
+            self.clear_selection();
+            // 选择可能延伸到文档末尾之后的空行，删除前收回到最后一行的行尾
+            if let Some((start, end)) = self.clamp_range_to_document(start, end) {
+                if start != end {
+                    self.delete_range(start, end, EditKind::DeleteSelection);
+                }
+            }
+        }
+    }
+
+    /// 删除矩形选择覆盖的部分，每行删除相同显示列范围内的字素，行数不变
+    fn delete_block(&mut self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        let (start, end) = selection.normalized();
+        let cursor_before = self.cursor_position;
+        let last = {
+            let rows = self.rows.read().unwrap();
+            if start.y >= rows.len() {
+                return;
+            }
+            end.y.min(rows.len() - 1)
+        };
+        let before = self.snapshot_rows(start.y, last - start.y + 1);
+
+        let mut rows = self.rows.write().unwrap();
+        let spans: Vec<Option<(usize, usize)>> = (start.y..=last).map(|y| self.selection_span(&rows, y)).collect();
+        // 光标移到矩形在第一行的左边界
+        let x = spans[0].map_or(0, |(from, _)| from);
+        for (y, span) in (start.y..=last).zip(spans) {
+            if let Some((from, to)) = span {
+                let kept: String = rows[y].string[..]
+                    .graphemes(true)
+                    .enumerate()
+                    .filter(|&(index, _)| index < from || index >= to)
+                    .map(|(_, g)| g)
+                    .collect();
+                rows[y].set_string(kept);
+            }
+        }
+        update_syntax_from(&mut rows, start.y, last - start.y + 1, self.syntax);
+        drop(rows);
+
+        self.selection = None;
+        self.cursor_position = Position { x, y: start.y };
+        self.push_undo(EditKind::DeleteSelection, start.y, before, last - start.y + 1, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 删除从 `start` 到 `end`（不含）之间的文本，跨行时合并首尾两行
+    /// 
+    /// 删除作为一个撤销单元记录，光标移到 `start`。跨行时 `start` 可以在行尾之后的虚拟空间中，
+    /// 合并前用空格补齐
+    /// 
+    /// # 参数
+    /// * `start` - 删除范围的开始位置
+    /// * `end` - 删除范围的结束位置，不能在 `start` 之前
+    /// * `kind` - 记录到撤销栈中的操作种类
+    fn delete_range(&mut self, start: Position, end: Position, kind: EditKind) {
+        let cursor_before = self.cursor_position;
+        let before = self.snapshot_rows(start.y, end.y - start.y + 1);
+        
+        let mut rows = self.rows.write().unwrap();
+        let mut unfolded = false;
+
+        // 如果删除范围在同一行内
+        if start.y == end.y {
+            let row = &mut rows[start.y];
+            let mut result = String::new();
+            for (index, grapheme) in row.string[..].graphemes(true).enumerate() {
+                if index < start.x || index >= end.x {
+                    result.push_str(grapheme);
+                }
+            }
+            row.set_string(result);
+        } else {
+            // 处理跨行删除
+            // 保留第一行开始部分
+            let mut first_line = String::new();
+            let first_row = &rows[start.y];
+            for (index, grapheme) in first_row.string[..].graphemes(true).enumerate() {
+                if index < start.x {
+                    first_line.push_str(grapheme);
+                }
+            }
+            // 开始位置在虚拟空间中时先用空格补齐到该列，与输入时一致
+            first_line.push_str(&" ".repeat(start.x.saturating_sub(first_row.len)));
+
+            // 保留最后一行结束部分
+            let mut last_line = String::new();
+            let last_row = &rows[end.y];
+            for (index, grapheme) in last_row.string[..].graphemes(true).enumerate() {
+                if index >= end.x {
+                    last_line.push_str(grapheme);
+                }
+            }
+
+            // 合并第一行和最后一行
+            first_line.push_str(&last_line);
+            
+            // 删除中间的行
+            rows.drain(start.y + 1..=end.y);
+            
+            // 更新第一行。合并进来的是被折叠隐藏的行，折叠随之展开
+            unfolded = rows[start.y].folded.take().is_some();
+            rows[start.y].set_string(first_line);
+        }
+
+        // 更新光标位置到删除范围的开始位置
+        drop(rows);
+        if unfolded {
+            self.status_message = StatusMessage::from(format!("已展开第 {} 行的折叠", start.y + 1));
+        }
+        self.cursor_position = start;
+        self.push_undo(kind, start.y, before, 1, cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 从系统剪贴板粘贴文本
+    fn paste(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        // 从系统剪贴板获取内容
+        let content = if let Some(ctx) = self.sys_clipboard.as_mut() {
+            match ctx.get_contents() {
+                Ok(text) => text,
+                Err(e) => {
+                    self.status_message = StatusMessage::error(
+                        format!("无法从系统剪贴板获取内容: {}", e)
+                    );
+                    return;
+                }
+            }
+        } else {
+            self.status_message = StatusMessage::error(
+                "系统剪贴板不可用".to_string()
+            );
+            return;
+        };
+
+        // 删除选中文本和插入内容作为一次操作撤销
+        self.begin_undo_group(EditKind::Paste);
+
+        // 如果有选中的文本，先删除它
+        if self.selection.is_some() {
+            self.delete_selection();
+        }
+
+        // 剪贴板仍是最近一次矩形复制的内容时按矩形粘贴
+        if self.block_clipboard.as_deref() == Some(content.as_str()) {
+            self.paste_block(&content);
+            self.end_undo_group();
+            self.status_message = StatusMessage::from(
+                format!("已按矩形粘贴 {} 行", content.split('\n').count())
+            );
+            return;
+        }
+
+        self.insert_text(&content);
+        self.end_undo_group();
+
+        self.status_message = StatusMessage::from(
+            format!("已粘贴 {} 个字符", content.len())
+        );
+    }
+
+    /// 以矩形方式粘贴：每一行插入到从光标行开始的连续各行中光标所在的显示列
+    /// 
+    /// 比该列短的行先用空格补齐，超出文档末尾时追加新行。光标留在矩形的左上角
+    /// 
+    /// # 参数
+    /// * `content` - 用换行分隔的各行内容
+    fn paste_block(&mut self, content: &str) {
+        let column = self.cursor_display_x();
+        let cursor_before = self.cursor_position;
+        let y = cursor_before.y;
+        let lines: Vec<&str> = content.split('\n').collect();
+        let before = self.snapshot_rows(y, lines.len());
+
+        let mut rows = self.rows.write().unwrap();
+        let mut cursor_x = 0;
+        for (i, line) in lines.iter().enumerate() {
+            if y + i >= rows.len() {
+                rows.push(Row::new(String::new(), self.syntax));
+            }
+            let row = &rows[y + i];
+            let (index, start_column) = self.grapheme_at_column(row, column);
+            // 只有列位于行尾之后时才需要补齐
+            let padding = if index == row.len { column.saturating_sub(start_column) } else { 0 };
+            let mut text: String = row.string[..].graphemes(true).take(index).collect();
+            text.push_str(&" ".repeat(padding));
+            text.push_str(line);
+            text.extend(row.string[..].graphemes(true).skip(index));
+            if i == 0 {
+                cursor_x = index + padding;
+            }
+            rows[y + i].set_string(text);
+        }
+        update_syntax_from(&mut rows, y, lines.len(), self.syntax);
+        drop(rows);
+
+        self.cursor_position = Position { x: cursor_x, y };
+        self.push_undo(EditKind::Paste, y, before, lines.len(), cursor_before);
+        self.mark_dirty();
+    }
+
+    /// 启动搜索操作
+    fn search(&mut self) -> io::Result<()> {
+        let saved_cursor_position = self.cursor_position;
+        let saved_offset = self.offset;
+
+        self.is_searching = true;
+        self.search_state.match_status = None;
+        if let Some(query) = self.prompt("Search: ", Some(Editor::find_callback))? {
+            self.is_searching = false;
+            if !query.is_empty() {
+                self.search_state.last_query = Some(query);
+            }
+            // 开启持续高亮时保留搜索文本，由 render_row 继续高亮匹配
+            if !self.highlight_search {
+                self.current_search = None;
+            }
+            self.refresh_screen()?;
+        } else {
+            self.cursor_position = saved_cursor_position;
+            self.offset = saved_offset;
+            self.is_searching = false;
+            self.current_search = None;
+            self.search_state.last_match = None;
+            self.refresh_screen()?;
+        }
+        Ok(())
+    }
+
+    /// 从光标位置查找上一次搜索文本的下一处（或上一处）匹配
+    /// 
+    /// # 参数
+    /// * `forward` - true 查找下一处，false 查找上一处
+    fn search_next(&mut self, forward: bool) {
+        let Some(query) = self.search_state.last_query.clone() else {
+            self.status_message = StatusMessage::from("还没有搜索过".to_string());
+            return;
+        };
+        // 查找下一处时跳过光标处的匹配
+        let from = if forward {
+            Position { x: self.cursor_position.x + 1, ..self.cursor_position }
+        } else {
+            self.cursor_position
+        };
+        self.clear_selection();
+        let direction = if forward { 1 } else { -1 };
+        match self.move_to_match(&query, from, direction) {
+            None => {
+                self.status_message = StatusMessage::from(format!("未找到匹配项: \"{}\"", query));
+            }
+            Some(wrapped) => {
+                self.status_message = StatusMessage::from(match (wrapped, forward) {
+                    (false, _) => format!("找到 \"{}\"", query),
+                    (true, true) => format!("已搜索到末尾，从开头继续: \"{}\"", query),
+                    (true, false) => format!("已搜索到开头，从末尾继续: \"{}\"", query),
+                });
+                if self.highlight_search {
+                    self.current_search = Some(query);
+                }
+            }
+        }
+    }
+
+    /// 跳转到指定行
+    /// 
+    /// 提示输入行号（从 1 开始），超出范围时限制在文档的首行和末行之间，
+    /// 跳转后将目标行置于屏幕中央。输入无效时光标保持不动
+    fn goto_line(&mut self) -> io::Result<()> {
+        let Some(input) = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("Goto line: ", None)? else {
+            return Ok(());
+        };
+        self.jump_to_line(input.trim());
+        Ok(())
+    }
+
+    /// 跳转到输入的行号，由 `goto_line` 和 `goto` 命令调用
+    fn jump_to_line(&mut self, input: &str) {
+        match input.parse::<usize>() {
+            Ok(line) => self.jump_to(line, None),
+            Err(_) => {
+                self.status_message = StatusMessage::error(format!("无效的行号: {}", input));
+            }
+        }
+    }
+
+    /// 跳转到第 `line` 行（从 1 开始），并将目标行置于屏幕中央
+    /// 
+    /// # 参数
+    /// * `line` - 目标行号，超出范围时限制在文档的首行和末行之间
+    /// * `column` - 目标列（字素位置，从 1 开始），超出行尾时停在行尾；为 None 时保持光标所在的列
+    fn jump_to(&mut self, line: usize, column: Option<usize>) {
+        let target = {
+            let rows = self.rows.read().unwrap();
+            if rows.is_empty() {
+                return;
+            }
+            let y = line.clamp(1, rows.len()) - 1;
+            let x = column.map_or(self.cursor_position.x, |column| column.saturating_sub(1));
+            Position {
+                x: x.min(rows[y].len),
+                y,
+            }
+        };
+
+        self.clear_selection();
+        self.cursor_position = target;
+        // 将目标行置于屏幕中央，scroll() 会据此处理水平方向
+        self.offset.y = target.y.saturating_sub(self.screen_rows / 2);
+    }
+
+    /// 在状态栏显示光标处字素的 Unicode 信息
+    /// 
+    /// 显示每个码位的编号和名称（如果已知）以及字素的显示宽度，
+    /// 例如 `U+00A0 NO-BREAK SPACE, width 1`
+    fn inspect_char(&mut self) {
+        let rows = self.rows.read().unwrap();
+        let grapheme = rows
+            .get(self.cursor_position.y)
+            .and_then(|row| row.string[..].graphemes(true).nth(self.cursor_position.x))
+            .map(str::to_string);
+        drop(rows);
+
+        let message = match grapheme {
+            Some(grapheme) => {
+                let code_points: Vec<String> = grapheme
+                    .chars()
+                    .map(|c| match unicode_name(c) {
+                        Some(name) => format!("U+{:04X} {}", c as u32, name),
+                        None => format!("U+{:04X}", c as u32),
+                    })
+                    .collect();
+                let width = self.grapheme_width(&grapheme, self.cursor_display_x());
+                format!("{}, width {}", code_points.join(" + "), width)
+            }
+            None => "光标处没有字符".to_string(),
+        };
+        self.status_message = StatusMessage::from(message);
+    }
+
+    /// 手动设置当前缓冲区的文件类型
+    /// 
+    /// 提示输入文件类型名称（如 `rust`、`text`），
+    /// 设置后使用新的语法规则重新高亮所有行
+    fn set_filetype(&mut self) -> io::Result<()> {
+        let name = match self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>("文件类型: ", None)? {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => return Ok(()),
+        };
+        self.apply_filetype(&name);
+        Ok(())
+    }
+
+    /// 按名称切换当前缓冲区的语法规则并重新高亮，由 Alt-T 和 `set filetype=` 使用
+    /// 
+    /// # 参数
+    /// * `name` - 文件类型名称或别名
+    fn apply_filetype(&mut self, name: &str) {
+        match find_syntax(name) {
+            Some(syntax) => {
+                self.syntax = syntax;
+                self.update_syntax_async();
+                self.status_message = StatusMessage::from(format!("文件类型已设置为 {}", syntax.name));
+            }
+            None => {
+                self.status_message = StatusMessage::error(format!("未知的文件类型: {}", name.trim()));
+            }
+        }
+    }
+
+    /// 切换十六进制视图
+    /// 
+    /// 有文件名且没有未保存的修改时显示磁盘上文件的原始字节（包括无效的 UTF-8、
+    /// CRLF 和末尾换行），否则显示缓冲区保存时会写入的字节
+    fn toggle_hex_view(&mut self) {
+        if self.hex_view.take().is_some() {
+            self.status_message = StatusMessage::from(String::new());
+            return;
+        }
+        let on_disk = self
+            .filename
+            .as_ref()
+            .filter(|_| !self.is_dirty())
+            .and_then(|name| fs::read(name).ok());
+        let (bytes, source) = match on_disk {
+            Some(bytes) => (bytes, "磁盘上的文件内容"),
+            None => (self.document_contents().into_bytes(), "缓冲区内容，尚未保存到磁盘"),
+        };
+        self.hex_view = Some(HexView::new(bytes));
+        // 提示当前绑定的按键，没有绑定时只能用 Esc 返回
+        let key = self
+            .keymap
+            .iter()
+            .filter(|&(_, &action)| action == Action::HexView)
+            .map(|(&binding, _)| key_name(binding))
+            .min()
+            .unwrap_or_else(|| "Esc".to_string());
+        self.status_message = StatusMessage::from(format!("十六进制视图（{}，只读），按 {} 返回", source, key));
+    }
+
+    /// 处理十六进制视图中的按键
+    /// 
+    /// 视图为只读，只支持光标移动、退出和返回文本视图。
+    /// 返回和退出按按键映射中 `HexView` 和 `Quit` 的绑定查找，Esc 总是返回文本视图
+    fn process_hex_keypress(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        let action = self.keymap.get(&key_binding(code, modifiers)).copied();
+        match (action, code) {
+            (Some(Action::HexView), _) | (_, KeyCode::Esc) => self.toggle_hex_view(),
+            (Some(Action::Quit), _) => {
+                // 回到文本视图，有未保存的修改时由正常的退出确认处理
+                self.hex_view = None;
+                self.request_quit();
+            }
+            (_, KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right
+                | KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End) => {
+                let screen_rows = self.screen_rows;
+                if let Some(hex_view) = self.hex_view.as_mut() {
+                    hex_view.move_cursor(code, screen_rows);
+                }
+            }
+            _ => {
+                self.status_message = StatusMessage::error("十六进制视图为只读".to_string());
+            }
+        }
+        // 只有连续的退出命令才计入退出确认
+        if action != Some(Action::Quit) {
+            self.quit_times = QUIT_TIMES;
+        }
+    }
+
+    /// 处理按键事件
+    /// 
+    /// 处理所有的键盘输入，包括：
+    /// - 编辑操作（插入、删除、复制、粘贴）
+    /// - 光标移动
+    /// - 文本选择
+    /// - 文件操作
+    /// - 搜索和替换
+    /// 
+    /// # 错误
+    /// 如果发生 I/O 错误，将返回该错误
+    fn process_keypress(&mut self) -> io::Result<()> {
+        if event::poll(Duration::from_millis(500))? {
+            match event::read()? {
+                Event::Key(key_event) if key_event.kind == KeyEventKind::Press && self.hex_view.is_some() => {
+                    self.process_hex_keypress(key_event.code, key_event.modifiers);
+                }
+                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                    // 按键操作的错误（如打开文件失败）只显示在状态栏：
+                    // 交给 run_loop 会退出编辑器，丢失所有缓冲区中未保存的修改
+                    if let Err(e) = self.process_key(key_event.code, key_event.modifiers) {
+                        self.status_message = StatusMessage::error(format!("操作失败: {}", e));
+                    }
+                }
+                Event::Mouse(event) => {
+                    match event.kind {
+                        event::MouseEventKind::Down(event::MouseButton::Left) => {
+                            let Some(position) = self.mouse_position(event.column as usize, event.row as usize) else {
+                                return Ok(());
+                            };
+                            self.cursor_position = position;
+                            self.goal_column = None;
+                            self.clear_selection();
+                        }
+                        event::MouseEventKind::Drag(event::MouseButton::Left) => {
+                            // 拖到正文区域的顶部或底部边缘时滚动一行，使选择可以延伸到屏幕之外
+                            let last_screen_row = self.screen_rows.saturating_sub(1);
+                            let screen_row = (event.row as usize).min(last_screen_row);
+                            if screen_row == 0 {
+                                self.scroll_view(1, false);
+                            } else if screen_row == last_screen_row
+                                && self.offset.y + self.screen_rows < self.rows.read().unwrap().len()
+                            {
+                                self.scroll_view(1, true);
+                            }
+                            let Some(position) = self.mouse_position(event.column as usize, screen_row) else {
+                                return Ok(());
+                            };
+                            if self.selection.is_none() {
+                                self.start_selection();
+                            }
+                            // 按住 Alt 拖动时为矩形选择
+                            if let Some(selection) = self.selection.as_mut() {
+                                selection.block = event.modifiers.contains(KeyModifiers::ALT);
+                            }
+                            self.cursor_position = position;
+                            self.goal_column = None;
+                            self.update_selection();
+                        }
+                        event::MouseEventKind::ScrollUp => self.scroll_view(3, false),
+                        event::MouseEventKind::ScrollDown => self.scroll_view(3, true),
+                        _ => (),
+                    }
+                }
+                Event::Resize(columns, rows) => self.resize(columns as usize, rows as usize),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理一次按键
+    /// 
+    /// 普通模式下先由 `normal_mode_key` 处理；按键映射中有绑定时执行对应的命令，
+    /// 否则作为输入字符、光标移动等编辑键处理
+    /// 
+    /// # 参数
+    /// * `code` - 按键
+    /// * `modifiers` - 同时按下的修饰键
+    /// 
+    /// # 错误
+    /// 如果发生 I/O 错误，将返回该错误
+    fn process_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> io::Result<()> {
+        // 重复次数只作用于紧接着的一个按键
+        let count = self.pending_count.take();
+        let repeat = count.map_or(1, |count| count.max(1));
+        // 普通模式下的命令键就地执行，移动键换成对应的功能键继续处理
+        let key = match self.vi_mode {
+            Some(ViMode::Normal) => self.normal_mode_key(code, modifiers, count)?,
+            Some(ViMode::Insert) if code == KeyCode::Esc => {
+                self.vi_mode = Some(ViMode::Normal);
+                None
+            }
+            _ => Some((code, modifiers)),
+        };
+        let Some((code, modifiers)) = key else {
+            self.quit_times = QUIT_TIMES;
+            return Ok(());
+        };
+        let action = self.keymap.get(&key_binding(code, modifiers)).copied();
+        if let Some(action) = action {
+            self.run_action(action, repeat)?;
+        } else {
+            match (code, modifiers) {
+                // 删除选中文本
+                (KeyCode::Backspace, _) => self.run_action(Action::DeleteBackward, repeat)?,
+                (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                    if self.selection.is_none() {
+                        self.start_selection();
+                    }
+                    self.insert_char(c);
+                    self.update_selection();
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE) => {
+                    self.clear_selection();
+                    self.insert_char(c);
+                }
+                (KeyCode::Tab, _) => {
+                    if self.selection.is_some_and(|selection| selection.start.y != selection.end.y) {
+                        self.indent_lines(true);
+                    } else {
+                        if self.selection.is_some() {
+                            self.delete_selection();
+                        }
+                        self.insert_tab();
+                    }
+                }
+                (KeyCode::BackTab, _) => self.indent_lines(false),
+                (KeyCode::Enter, _) => {
+                    if self.selection.is_some() {
+                        self.delete_selection();
+                    }
+                    self.insert_newline();
+                }
+                (KeyCode::Left | KeyCode::Right, mods) if mods.contains(KeyModifiers::CONTROL) => {
+                    let forward = code == KeyCode::Right;
+                    if mods.contains(KeyModifiers::SHIFT) {
+                        if self.selection.is_none() {
+                            self.start_selection();
+                        }
+                        self.repeat_movement(repeat, |editor| editor.move_word(forward));
+                        self.update_selection();
+                    } else {
+                        self.clear_selection();
+                        self.repeat_movement(repeat, |editor| editor.move_word(forward));
+                    }
+                }
+                (KeyCode::Up, mods) | (KeyCode::Down, mods) |
+                (KeyCode::Left, mods) | (KeyCode::Right, mods) => {
+                    if mods.contains(KeyModifiers::SHIFT) {
+                        if self.selection.is_none() {
+                            self.start_selection();
+                        }
+                        self.repeat_movement(repeat, |editor| editor.move_cursor(code));
+                        self.update_selection();
+                    } else {
+                        self.clear_selection();
+                        self.repeat_movement(repeat, |editor| editor.move_cursor(code));
+                    }
+                }
+                (KeyCode::Esc, _) => self.clear_search_highlight(),
+                (KeyCode::Insert, _) => {
+                    self.insert_mode = !self.insert_mode;
+                }
+                (KeyCode::PageUp, _) => {
+                    self.clear_selection();
+                    self.repeat_movement(repeat, |editor| editor.move_cursor(KeyCode::PageUp));
+                }
+                (KeyCode::PageDown, _) => {
+                    self.clear_selection();
+                    self.repeat_movement(repeat, |editor| editor.move_cursor(KeyCode::PageDown));
+                }
+                (KeyCode::Char(c @ '0'..='9'), KeyModifiers::ALT) => {
+                    let digit = c as usize - '0' as usize;
+                    let count = count.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                    self.pending_count = Some(count);
+                    self.status_message = StatusMessage::from(format!("重复次数: {}", count));
+                }
+                (KeyCode::Home, _) => {
+                    self.clear_selection();
+                    self.move_cursor(KeyCode::Home);
+                }
+                (KeyCode::End, _) => {
+                    self.clear_selection();
+                    self.move_cursor(KeyCode::End);
+                }
+                _ => (),
+            }
+        }
+        // 除上下移动外的所有操作（水平移动、编辑等）都会重新确定目标列，
+        // 输入重复次数不影响目标列
+        if !matches!(code, KeyCode::Up | KeyCode::Down) && self.pending_count.is_none() {
+            self.goal_column = None;
+        }
+        // 只有连续的退出命令才计入退出确认
+        if action != Some(Action::Quit) {
+            self.quit_times = QUIT_TIMES;
+        }
+        Ok(())
+    }
+
+    /// 执行按键绑定的命令
+    /// 
+    /// # 参数
+    /// * `action` - 要执行的命令
+    /// * `repeat` - 重复次数，用于删除整行
+    /// 
+    /// # 错误
+    /// 如果发生 I/O 错误，将返回该错误
+    fn run_action(&mut self, action: Action, repeat: usize) -> io::Result<()> {
+        match action {
+            Action::Quit => self.request_quit(),
+            Action::Save => self.save(false)?,
+            Action::ForceSave => self.save(true)?,
+            Action::SaveAs => self.save_as()?,
+            Action::Open => self.open_file()?,
+            Action::NewDocument => self.new_document()?,
+            Action::Reload => self.reload()?,
+            Action::CommandLine => self.command_line()?,
+            Action::Search => self.search()?,
+            Action::SearchNext => self.search_next(true),
+            Action::SearchPrevious => self.search_next(false),
+            Action::Replace => self.replace(false)?,
+            Action::ReplaceAll => self.replace(true)?,
+            Action::Copy => self.copy_selection(),
+            Action::Cut => {
+                self.copy_selection();
+                self.delete_selection();
+            }
+            Action::Paste => self.paste(),
+            Action::SelectAll => self.select_all(),
+            Action::DuplicateLines => self.duplicate_lines(),
+            Action::MoveLinesUp => self.move_lines(false),
+            Action::MoveLinesDown => self.move_lines(true),
+            Action::ToggleComment => self.toggle_comment(),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::DeleteBackward => {
+                if self.selection.is_some() {
+                    self.delete_selection();
+                } else {
+                    self.delete_char();
+                }
+            }
+            Action::DeleteForward => {
+                if self.selection.is_some() {
+                    self.delete_selection();
+                } else {
+                    self.delete_char_forward();
+                }
+            }
+            Action::DeleteWordBackward => self.delete_word(false),
+            Action::DeleteWordForward => self.delete_word(true),
+            Action::KillLine => self.kill_line(),
+            Action::DeleteLines => {
+                self.clear_selection();
+                self.delete_lines(repeat);
+            }
+            Action::Yank => self.yank(),
+            Action::NextBuffer => self.switch_buffer((self.current_buffer + 1) % self.buffers.len()),
+            Action::PickBuffer => self.pick_buffer()?,
+            Action::HexView => self.toggle_hex_view(),
+            Action::InspectChar => self.inspect_char(),
+            Action::SetFiletype => self.set_filetype()?,
+            Action::GotoLine => self.goto_line()?,
+            Action::MatchingBracket => self.jump_to_matching_bracket(),
+            Action::ToggleLineNumbers => self.toggle_line_numbers(),
+            Action::ToggleWordWrap => self.toggle_word_wrap(),
+            Action::ToggleReadOnly => self.toggle_read_only(),
+            Action::CycleTheme => self.cycle_theme(),
+            Action::ToggleBlockSelection => self.toggle_block_selection(),
+            Action::ToggleWhitespace => self.toggle_whitespace(),
+            Action::ToggleIndentGuides => self.toggle_indent_guides(),
+            Action::ToggleTrailingWhitespace => self.toggle_trailing_whitespace(),
+            Action::ToggleFold => self.toggle_fold(),
+            Action::ToggleHighlightSearch => self.toggle_highlight_search(),
+            Action::ToggleSpellCheck => self.toggle_spell_check(),
+            Action::ToggleVirtualSpace => self.toggle_virtual_space(),
+            Action::ToggleInsertSpaces => {
+                self.insert_spaces = !self.insert_spaces;
+                self.status_message = StatusMessage::from(if self.insert_spaces {
+                    format!("Tab 键插入 {} 个空格宽度的缩进", self.tab_width)
+                } else {
+                    "Tab 键插入制表符".to_string()
+                });
+            }
+            Action::HalfPageDown => {
+                self.clear_selection();
+                self.scroll_half_page(true);
+            }
+            Action::HalfPageUp => {
+                self.clear_selection();
+                self.scroll_half_page(false);
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理一次 Ctrl-Q
+    /// 
+    /// 有未保存的修改时需要连续按 `QUIT_TIMES` 次才退出，之前每次都提示还需要再按的次数
+    fn request_quit(&mut self) {
+        self.quit_times = self.quit_times.saturating_sub(1);
+        if self.any_dirty() && self.quit_times > 0 {
+            let times = if self.quit_times == 1 { "time" } else { "times" };
+            self.status_message = StatusMessage::from(format!(
+                "WARNING!!! File has unsaved changes. Press Ctrl-Q {} more {} to quit.",
+                self.quit_times, times
+            ));
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    /// 处理终端尺寸变化
+    /// 
+    /// 更新屏幕尺寸，并重新计算视图偏移，
+    /// 尽量保持光标在屏幕上的相对位置不变
+    /// 
+    /// # 参数
+    /// * `columns` - 新的终端列数
+    /// * `rows` - 新的终端行数
+    fn resize(&mut self, columns: usize, rows: usize) {
+        let cursor_x = self.cursor_display_x();
+        let relative_y = self.cursor_position.y.saturating_sub(self.offset.y);
+        let relative_x = cursor_x.saturating_sub(self.offset.x);
+
+        self.screen_cols = columns;
+        self.screen_rows = rows.saturating_sub(2);
+
+        let max_y = self.screen_rows.saturating_sub(1);
+        let max_x = self.text_cols().saturating_sub(1);
+        self.offset.y = self.cursor_position.y.saturating_sub(relative_y.min(max_y));
+        self.offset.x = cursor_x.saturating_sub(relative_x.min(max_x));
+        let total_rows = self.rows.read().unwrap().len();
+        self.offset.y = self.offset.y.min(total_rows);
+    }
+
+    /// 光标所在位置的显示列（光标前所有字素的显示宽度之和）
+    fn cursor_display_x(&self) -> usize {
+        let Position { x, y } = self.cursor_position;
+        self.rows.read().unwrap().get(y).map_or(0, |row| self.display_column(row, x))
+    }
+
+    /// 查找覆盖指定显示列的字素
+    /// 
+    /// # 参数
+    /// * `row` - 所在的行
+    /// * `column` - 显示列
+    /// 
+    /// # 返回值
+    /// 字素位置和该字素起始的显示列；显示列超出行尾时返回行的长度和行的显示宽度
+    fn grapheme_at_column(&self, row: &Row, column: usize) -> (usize, usize) {
+        let mut current_width = 0;
+        for (i, grapheme) in row.string[..].graphemes(true).enumerate() {
+            let width = self.grapheme_width(grapheme, current_width);
+            if current_width + width > column {
+                return (i, current_width);
+            }
+            current_width += width;
+        }
+        (row.len, current_width)
+    }
+
+    /// 上下移动或点击时落在指定显示列上的光标位置
+    /// 
+    /// 通常是覆盖该列的字素；开启虚拟空间且该列超出行尾时，为行尾之后对应的虚拟位置
+    /// 
+    /// # 参数
+    /// * `row` - 所在的行
+    /// * `column` - 显示列
+    fn position_at_column(&self, row: &Row, column: usize) -> usize {
+        let (x, start_column) = self.grapheme_at_column(row, column);
+        if x == row.len && self.in_virtual_space() {
+            x + column - start_column
+        } else {
+            x
+        }
+    }
+
+    /// 行号栏的宽度（包括与正文之间的空格），未显示行号时为 0
+    /// 
+    /// 宽度由总行数的位数决定，保证所有行号右对齐
+    fn gutter_width(&self) -> usize {
+        if !self.show_line_numbers {
+            return 0;
+        }
+        let total_rows = self.rows.read().unwrap().len().max(1);
+        total_rows.to_string().len() + 1
+    }
+
+    /// 除去行号栏后可用于显示正文的列数
+    fn text_cols(&self) -> usize {
+        self.screen_cols.saturating_sub(self.gutter_width())
+    }
+
+    /// 切换行号显示
+    fn toggle_line_numbers(&mut self) {
+        self.show_line_numbers = !self.show_line_numbers;
+        self.status_message = StatusMessage::from(if self.show_line_numbers {
+            "已显示行号".to_string()
+        } else {
+            "已隐藏行号".to_string()
+        });
+    }
+
+    /// 查找光标旁括号的匹配括号
+    /// 
+    /// 优先检查光标处的字符，其次检查光标前的字符。
+    /// 按嵌套深度向前或向后跨行扫描，字符串和注释中的括号不计入；
+    /// 最多扫描 `BRACKET_SCAN_LINES` 行，找不到匹配时返回 None
+    /// 
+    /// # 返回值
+    /// 光标旁括号的位置和与之匹配的括号的位置
+    fn find_matching_bracket(&self) -> Option<(Position, Position)> {
+        const PAIRS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+        let rows = self.rows.read().unwrap();
+        let Position { x, y } = self.cursor_position;
+        let line = code_graphemes(rows.get(y)?);
+
+        let is_bracket = |g: &str| PAIRS.iter().any(|&(open, close)| g == open || g == close);
+        let bracket_x = [Some(x), x.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .find(|&bx| line.get(bx).is_some_and(|&g| g.is_some_and(is_bracket)))?;
+        let bracket = line[bracket_x]?;
+        let start = Position { x: bracket_x, y };
+
+        let mut depth = 0;
+        if let Some(&(open, close)) = PAIRS.iter().find(|&&(open, _)| open == bracket) {
+            // 向后扫描
+            for (row_y, row) in rows.iter().enumerate().skip(y).take(BRACKET_SCAN_LINES) {
+                let graphemes = if row_y == y { line.clone() } else { code_graphemes(row) };
+                let skip = if row_y == y { bracket_x + 1 } else { 0 };
+                for (gx, g) in graphemes.into_iter().enumerate().skip(skip) {
+                    if g == Some(open) {
+                        depth += 1;
+                    } else if g == Some(close) {
+                        if depth == 0 {
+                            return Some((start, Position { x: gx, y: row_y }));
+                        }
+                        depth -= 1;
+                    }
+                }
+            }
+        } else {
+            let &(open, close) = PAIRS.iter().find(|&&(_, close)| close == bracket)?;
+            // 向前扫描
+            for row_y in (y.saturating_sub(BRACKET_SCAN_LINES - 1)..=y).rev() {
+                let graphemes = code_graphemes(&rows[row_y]);
+                let end = if row_y == y { bracket_x } else { graphemes.len() };
+                for gx in (0..end).rev() {
+                    if graphemes[gx] == Some(close) {
+                        depth += 1;
+                    } else if graphemes[gx] == Some(open) {
+                        if depth == 0 {
+                            return Some((start, Position { x: gx, y: row_y }));
+                        }
+                        depth -= 1;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// 刷新屏幕前更新光标旁的括号匹配
+    /// 
+    /// 文档和光标位置都没有变化时沿用上次的结果，避免每次刷新都跨行扫描
+    fn update_matching_bracket(&mut self) {
+        let key = (self.version, self.cursor_position);
+        if self.matching_bracket_key != Some(key) {
+            self.matching_bracket = self.find_matching_bracket();
+            self.matching_bracket_key = Some(key);
+        }
+    }
+
+    /// 将光标移动到与光标旁括号匹配的括号处
+    /// 
+    /// 光标不在括号旁或括号没有配对时保持光标不动，只在状态栏提示
+    fn jump_to_matching_bracket(&mut self) {
+        match self.find_matching_bracket() {
+            Some((_, target)) => {
+                self.clear_selection();
+                self.cursor_position = target;
+                // scroll() 会在刷新屏幕时让目标位置可见
+            }
+            None => {
+                self.status_message = StatusMessage::from("没有匹配的括号".to_string());
+            }
+        }
+    }
+
+    /// 切换自动换行
+    fn toggle_word_wrap(&mut self) {
+        self.word_wrap = !self.word_wrap;
+        self.offset.x = 0;
+        self.status_message = StatusMessage::from(if self.word_wrap {
+            "已开启自动换行".to_string()
+        } else {
+            "已关闭自动换行".to_string()
+        });
+    }
+
+    /// 切换空白字符显示
+    fn toggle_whitespace(&mut self) {
+        self.show_whitespace = !self.show_whitespace;
+        self.status_message = StatusMessage::from(if self.show_whitespace {
+            "已开启空白字符显示".to_string()
+        } else {
+            "已关闭空白字符显示".to_string()
+        });
+    }
+
+    /// 光标在折叠行上时展开，否则折叠
+    fn toggle_fold(&mut self) {
+        let y = self.cursor_position.y;
+        if self.rows.read().unwrap().get(y).is_some_and(|row| row.folded.is_some()) {
+            self.unfold();
+        } else {
+            self.fold();
+        }
+    }
+
+    /// 折叠选中的多行，没有选择时折叠光标所在的缩进块
+    /// 
+    /// 首行保持可见，其余的行被隐藏。范围内已有的折叠被整个包含在新的折叠中，
+    /// 展开新的折叠后恢复原样
+    fn fold(&mut self) {
+        let range = match self.selection {
+            Some(selection) if !selection.is_empty() => Some(self.selected_lines()),
+            _ => self.indent_block(self.cursor_position.y),
+        };
+        let mut rows = self.rows.write().unwrap();
+        let Some((header, mut last)) = range.filter(|&(header, last)| header < last && last < rows.len()) else {
+            drop(rows);
+            self.status_message = StatusMessage::from("没有可以折叠的行".to_string());
+            return;
+        };
+        // 被包含的折叠可能隐藏到范围之外的行，扩大范围使折叠保持嵌套
+        let mut y = header;
+        while y <= last {
+            if let Some(hidden) = rows[y].folded {
+                last = last.max(y + hidden).min(rows.len() - 1);
+            }
+            y += 1;
+        }
+        rows[header].folded = Some(last - header);
+        rows[header].invalidate_render();
+        drop(rows);
+        // 先把光标移到首行，否则下一次重绘会把光标所在的折叠重新展开
+        self.cursor_position = Position { x: 0, y: header };
+        self.clear_selection();
+        self.status_message = StatusMessage::from(format!("已折叠 {} 行", last - header));
+    }
+
+    /// 以第 `y` 行所在的缩进块作为折叠范围
+    /// 
+    /// 下一个非空行的缩进比第 `y` 行深时，块从第 `y` 行开始；否则从上方第一个缩进比它浅的行开始。
+    /// 块一直延伸到下一个缩进不比首行深的非空行之前，末尾的空行不计入
+    /// 
+    /// # 返回值
+    /// 首行和末行的行号（包含），找不到缩进块时返回 None
+    fn indent_block(&self, y: usize) -> Option<(usize, usize)> {
+        let rows = self.rows.read().unwrap();
+        let indent = |y: usize| -> Option<usize> {
+            let row = rows.get(y)?;
+            let blank = row.string.chars().all(char::is_whitespace);
+            (!blank).then(|| self.display_column(row, row.string.graphemes(true).take_while(|g| g.chars().all(char::is_whitespace)).count()))
+        };
+        let current = indent(y)?;
+        let next = (y + 1..rows.len()).find_map(indent);
+        let header = if next.is_some_and(|next| next > current) {
+            y
+        } else {
+            (0..y).rev().find(|&above| indent(above).is_some_and(|above| above < current))?
+        };
+        let header_indent = indent(header)?;
+        let mut last = header;
+        for below in header + 1..rows.len() {
+            match indent(below) {
+                Some(depth) if depth <= header_indent => break,
+                Some(_) => last = below,
+                None => (),
+            }
+        }
+        Some((header, last))
+    }
+
+    /// 展开光标所在行的折叠
+    fn unfold(&mut self) {
+        let y = self.cursor_position.y;
+        let mut rows = self.rows.write().unwrap();
+        match rows.get_mut(y).and_then(|row| row.folded.take().map(|hidden| (row, hidden))) {
+            Some((row, hidden)) => {
+                row.invalidate_render();
+                drop(rows);
+                self.status_message = StatusMessage::from(format!("已展开 {} 行", hidden));
+            }
+            None => {
+                drop(rows);
+                self.status_message = StatusMessage::from("光标所在行没有折叠".to_string());
+            }
+        }
+    }
+
+    /// 光标被折叠隐藏时（如搜索或跳转到隐藏的行）展开覆盖它的所有折叠
+    fn reveal_cursor(&mut self) {
+        let y = self.cursor_position.y;
+        let rows = self.rows.read().unwrap();
+        if visible_row(&rows, y) == y {
+            return;
+        }
+        drop(rows);
+        let mut rows = self.rows.write().unwrap();
+        for header in 0..y.min(rows.len()) {
+            if rows[header].folded.is_some_and(|hidden| header + hidden >= y) {
+                rows[header].folded = None;
+                rows[header].invalidate_render();
+            }
+        }
+    }
+
+    /// 切换缩进参考线
+    fn toggle_indent_guides(&mut self) {
+        self.show_indent_guides = !self.show_indent_guides;
+        self.status_message = StatusMessage::from(if self.show_indent_guides {
+            "已开启缩进参考线".to_string()
+        } else {
+            "已关闭缩进参考线".to_string()
+        });
+    }
+
+    /// 切换拼写检查，第一次开启时读取词表
+    fn toggle_spell_check(&mut self) {
+        if self.spell_check {
+            self.spell_check = false;
+            self.status_message = StatusMessage::from("已关闭拼写检查".to_string());
+            return;
+        }
+        if self.dictionary.is_none() {
+            match load_dictionary(&self.dictionary_path) {
+                Ok(dictionary) => self.dictionary = Some(dictionary),
+                Err(e) => {
+                    self.status_message = StatusMessage::error(format!("无法读取词表 {}: {}", self.dictionary_path.display(), e));
+                    return;
+                }
+            }
+        }
+        self.spell_check = true;
+        self.status_message = StatusMessage::from("已开启拼写检查".to_string());
+    }
+
+    /// 切换虚拟空间，关闭时把行尾之后的光标移回行尾
+    fn toggle_virtual_space(&mut self) {
+        self.virtual_space = !self.virtual_space;
+        if self.virtual_space {
+            self.status_message = StatusMessage::from("已开启虚拟空间".to_string());
+        } else {
+            let Position { x, y } = self.cursor_position;
+            let len = self.rows.read().unwrap().get(y).map_or(0, |row| row.len);
+            self.cursor_position.x = x.min(len);
+            self.status_message = StatusMessage::from("已关闭虚拟空间".to_string());
+        }
+    }
+
+    /// 光标当前是否可以移到行尾之后（自动换行时没有虚拟空间）
+    fn in_virtual_space(&self) -> bool {
+        self.virtual_space && !self.word_wrap
+    }
+
+    /// 切换行尾空白高亮
+    fn toggle_trailing_whitespace(&mut self) {
+        self.highlight_trailing_whitespace = !self.highlight_trailing_whitespace;
+        self.status_message = StatusMessage::from(if self.highlight_trailing_whitespace {
+            "已开启行尾空白高亮".to_string()
+        } else {
+            "已关闭行尾空白高亮".to_string()
+        });
+    }
+
+    /// 切换搜索结束后是否继续高亮匹配，关闭时同时清除当前的高亮
+    fn toggle_highlight_search(&mut self) {
+        self.highlight_search = !self.highlight_search;
+        self.status_message = StatusMessage::from(if self.highlight_search {
+            "搜索结束后继续高亮匹配".to_string()
+        } else {
+            self.current_search = None;
+            "搜索结束后不再高亮匹配".to_string()
+        });
+    }
+
+    /// 清除搜索结束后保留的匹配高亮
+    fn clear_search_highlight(&mut self) {
+        if self.current_search.take().is_some() {
+            self.status_message = StatusMessage::from("已清除搜索高亮".to_string());
+        }
+    }
+
+    /// 字素从显示列 `column` 开始时的显示宽度，制表符延伸到下一个制表位
+    /// 
+    /// 制表位从行首开始每隔 `tab_width` 列一个，所有计算显示列的地方都经过这里，
+    /// 保证渲染、光标定位和鼠标点击一致
+    fn grapheme_width(&self, grapheme: &str, column: usize) -> usize {
+        if grapheme == "\t" {
+            let tab_width = self.tab_width.max(1);
+            tab_width - column % tab_width
+        } else {
+            UnicodeWidthStr::width(grapheme)
+        }
+    }
+
+    /// 行中每个字素的显示宽度
+    fn grapheme_widths(&self, row: &Row) -> Vec<usize> {
+        let mut column = 0;
+        row.string[..]
+            .graphemes(true)
+            .map(|g| {
+                let width = self.grapheme_width(g, column);
+                column += width;
+                width
+            })
+            .collect()
+    }
+
+    /// 行中第 `x` 个字素开始的显示列（之前所有字素的显示宽度之和）
+    /// 
+    /// `x` 在虚拟空间中（超过行尾）时，行尾之后的每个位置占一列
+    fn display_column(&self, row: &Row, x: usize) -> usize {
+        row.string[..]
+            .graphemes(true)
+            .take(x)
+            .fold(0, |column, g| column + self.grapheme_width(g, column))
+            + x.saturating_sub(row.len)
+    }
+
+    /// 计算自动换行时一行被分成的各个显示段
+    /// 
+    /// 优先在空白字符之后折行，一个单词比整个屏幕还宽时在字素边界处强制折行
+    /// 
+    /// # 参数
+    /// * `row` - 要折行的行
+    /// * `width` - 可用于显示正文的列数
+    /// 
+    /// # 返回值
+    /// 每个显示段的起始字素位置，至少包含一个 0
+    fn wrap_segments(&self, row: &Row, width: usize) -> Vec<usize> {
+        let width = width.max(1);
+        let widths = self.grapheme_widths(row);
+        let mut starts = vec![0];
+        let mut line_width = 0;
+        let mut last_break = None;  // 当前段内最近一个可以折行的位置
+        for (i, grapheme) in row.string[..].graphemes(true).enumerate() {
+            let segment_start = *starts.last().unwrap_or(&0);
+            if line_width + widths[i] > width && i > segment_start {
+                let start = last_break.filter(|&b| b > segment_start).unwrap_or(i);
+                starts.push(start);
+                line_width = widths[start..i].iter().sum();
+                last_break = None;
+            }
+            line_width += widths[i];
+            if grapheme.trim().is_empty() {
+                last_break = Some(i + 1);
+            }
+        }
+        starts
+    }
+
+    /// 从第 `from` 行开头到 `to` 所在显示行（包括）共占用的屏幕行数
+    /// 
+    /// 未开启自动换行时每行占一个屏幕行
+    fn visual_rows_between(&self, rows: &[Row], from: usize, to: Position, width: usize) -> usize {
+        // 被折叠隐藏的行不占屏幕行
+        let mut count = 0;
+        let mut y = from;
+        while y < to.y {
+            count += match rows.get(y) {
+                Some(row) if self.word_wrap => self.wrap_segments(row, width).len(),
+                _ => 1,
+            };
+            y = next_visible_row(rows, y);
+        }
+        if !self.word_wrap {
+            return count + 1;
+        }
+        let segment = rows.get(to.y).map_or(0, |row| {
+            self.wrap_segments(row, width).iter().rposition(|&start| start <= to.x).unwrap_or(0)
+        });
+        count + segment + 1
+    }
+
+    /// 鼠标点击或拖动到的文档位置
+    /// 
+    /// 与 `screen_to_position` 相同，但屏幕行位于文档末尾之后时返回最后一行的行尾，
+    /// 点击和拖动都不会落在文档末尾之后的空行上
+    /// 
+    /// # 参数
+    /// * `column` - 屏幕列（包括行号栏）
+    /// * `screen_row` - 屏幕行
+    /// 
+    /// # 返回值
+    /// 文档为空时返回 None
+    fn mouse_position(&self, column: usize, screen_row: usize) -> Option<Position> {
+        self.screen_to_position(column, screen_row)
+            .or_else(|| self.clamp_to_document(Position { x: usize::MAX, y: usize::MAX }))
+    }
+
+    /// 将屏幕坐标转换为文档位置
+    /// 
+    /// 屏幕列按显示宽度（制表符、宽字符）换算成字素位置；点在多列字素的右半边时
+    /// 返回它之后的位置，与光标显示在字素左侧一致
+    /// 
+    /// # 参数
+    /// * `column` - 屏幕列（包括行号栏）
+    /// * `screen_row` - 屏幕行
+    /// 
+    /// # 返回值
+    /// 对应的文档位置，屏幕行位于文档末尾之后时返回 None
+    fn screen_to_position(&self, column: usize, screen_row: usize) -> Option<Position> {
+        let column = column.saturating_sub(self.gutter_width());
+        let width = self.text_cols();
+        let rows = self.rows.read().unwrap();
+        if !self.word_wrap {
+            let y = step_visible_rows(&rows, self.offset.y, screen_row, true);
+            let row = rows.get(y)?;
+            let column = column + self.offset.x;
+            let (x, start_column) = self.grapheme_at_column(row, column);
+            let x = match row.string[..].graphemes(true).nth(x) {
+                Some(grapheme) if (column - start_column) * 2 >= self.grapheme_width(grapheme, start_column) => x + 1,
+                _ if x == row.len => self.position_at_column(row, column),
+                _ => x,
+            };
+            return Some(Position { x, y });
+        }
+
+        let mut remaining = screen_row;
+        let mut y = self.offset.y;
+        while let Some(row) = rows.get(y) {
+            let starts = self.wrap_segments(row, width);
+            if remaining >= starts.len() {
+                remaining -= starts.len();
+                y = next_visible_row(&rows, y);
+                continue;
+            }
+            let start = starts[remaining];
+            // 点击非最后一段的末尾之后时停在该段的最后一个字素上
+            let end = starts.get(remaining + 1).map_or(row.len, |&next| next.saturating_sub(1).max(start));
+            let mut x = start;
+            let mut current_width = 0;
+            for width in self.grapheme_widths(row).into_iter().skip(start) {
+                if x >= end {
+                    break;
+                }
+                if current_width + width > column {
+                    if (column - current_width) * 2 >= width {
+                        x += 1;
+                    }
+                    break;
+                }
+                current_width += width;
+                x += 1;
+            }
+            return Some(Position { x: x.min(end), y });
+        }
+        None
+    }
+
+    /// 处理屏幕滚动
+    /// 
+    /// 根据光标位置自动调整视图：
+    /// - 确保光标始终可见
+    /// - 在光标上下（左右）保留 `scroll_off` 行（列）的上下文
+    /// - 处理水平和垂直滚动
+    /// - 支持 CJK 等宽字符
+    fn scroll(&mut self) {
+        self.reveal_cursor();
+        let y = self.cursor_position.y;
+        let width = self.text_cols();
+        let height = self.screen_rows;
+        // 屏幕太小时缩小边距，保证光标仍能定位
+        let margin_y = self.scroll_off.min(height.saturating_sub(1) / 2);
+        let margin_x = self.scroll_off.min(width.saturating_sub(1) / 2);
+
+        // 垂直方向按可见行计算，被折叠隐藏的行不计入
+        let rows = self.rows.read().unwrap();
+        let mut offset_y = visible_row(&rows, self.offset.y);
+        let top = step_visible_rows(&rows, y, margin_y, false);
+        if top < offset_y {
+            offset_y = top;
+        }
+
+        if self.word_wrap {
+            // 自动换行时没有水平滚动，垂直方向按显示行计算
+            self.offset.x = 0;
+            let limit = height.saturating_sub(margin_y);
+            while offset_y < y && self.visual_rows_between(&rows, offset_y, self.cursor_position, width) > limit {
+                offset_y = next_visible_row(&rows, offset_y);
+            }
+            drop(rows);
+            self.offset.y = offset_y;
+            return;
+        }
+
+        // 下方的边距不超过文档末尾，避免把视图滚动到文档之外
+        let bottom = step_visible_rows(&rows, y, margin_y, true).max(y);
+        let bottom_position = Position { x: 0, y: bottom };
+        if self.visual_rows_between(&rows, offset_y, bottom_position, width) > height {
+            offset_y = step_visible_rows(&rows, bottom, height.saturating_sub(1), false);
+        }
+        drop(rows);
+        self.offset.y = offset_y;
+
+        // offset.x 与光标位置都以显示宽度计算
+        let target_x = self.cursor_display_x();
+
+        let offset = &mut self.offset;
+        if target_x < offset.x.saturating_add(margin_x) {
+            offset.x = target_x.saturating_sub(margin_x);
+        } else if target_x.saturating_add(margin_x) >= offset.x.saturating_add(width) {
+            offset.x = target_x.saturating_add(margin_x).saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// 处理模式编辑普通模式下的按键
+    /// 
+    /// 不带修饰键的字符作为命令：移动命令换成对应的功能键返回，由
+    /// `process_keypress` 按非模式编辑的方式继续处理，其余命令在这里执行。
+    /// 功能键和带 Ctrl / Alt 的组合键原样返回
+    /// 
+    /// # 参数
+    /// * `code` - 按键
+    /// * `modifiers` - 修饰键
+    /// * `count` - 之前输入的重复次数
+    /// 
+    /// # 返回值
+    /// 需要继续处理的按键；按键已在这里处理完时返回 None
+    fn normal_mode_key(&mut self, code: KeyCode, modifiers: KeyModifiers, count: Option<usize>) -> io::Result<Option<(KeyCode, KeyModifiers)>> {
+        let operator = self.pending_operator.take();
+        let KeyCode::Char(c) = code else {
+            return Ok(Some((code, modifiers)));
+        };
+        if modifiers != KeyModifiers::NONE && modifiers != KeyModifiers::SHIFT {
+            return Ok(Some((code, modifiers)));
+        }
+        let repeat = count.map_or(1, |count| count.max(1));
+        if let Some(operator) = operator {
+            if operator == 'd' && c == 'd' {
+                self.delete_lines(repeat);
+            }
+            return Ok(None);
+        }
+
+        let key = |code| Ok(Some((code, KeyModifiers::NONE)));
+        match c {
+            // 已经输入重复次数时 0 是其中的一位数字，否则移到行首
+            '0' if count.is_none() => return key(KeyCode::Home),
+            '0'..='9' => {
+                let digit = c as usize - '0' as usize;
+                let count = count.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                self.pending_count = Some(count);
+            }
+            'h' => return key(KeyCode::Left),
+            'j' => return key(KeyCode::Down),
+            'k' => return key(KeyCode::Up),
+            'l' => return key(KeyCode::Right),
+            '$' => return key(KeyCode::End),
+            'w' => return Ok(Some((KeyCode::Right, KeyModifiers::CONTROL))),
+            'b' => return Ok(Some((KeyCode::Left, KeyModifiers::CONTROL))),
+            'i' => self.vi_mode = Some(ViMode::Insert),
+            'a' => {
+                let at_line_end = self.rows.read().unwrap()
+                    .get(self.cursor_position.y)
+                    .is_none_or(|row| self.cursor_position.x >= row.len);
+                if !at_line_end {
+                    self.cursor_position.x += 1;
+                }
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            'A' => {
+                self.move_cursor(KeyCode::End);
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            'o' => {
+                self.move_cursor(KeyCode::End);
+                self.insert_newline();
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            'x' => self.delete_under_cursor(repeat),
+            'd' => {
+                // 重复次数留给第二个 d 使用
+                self.pending_operator = Some('d');
+                self.pending_count = count;
+            }
+            'u' => self.undo(),
+            'n' => self.search_next(true),
+            'N' => self.search_next(false),
+            ':' => self.command_line()?,
+            _ => (),
+        }
+        self.clear_selection();
+        Ok(None)
+    }
+
+    /// 删除从光标处开始的 `count` 个字素，不超过行尾
+    fn delete_under_cursor(&mut self, count: usize) {
+        if !self.check_writable() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let Some(len) = self.rows.read().unwrap().get(y).map(|row| row.len) else {
+            return;
+        };
+        if x < len {
+            let end = Position { x: len.min(x.saturating_add(count)), y };
+            self.delete_range(self.cursor_position, end, EditKind::DeleteChar);
+        }
+    }
+
+    /// 删除从光标到行尾的文本
+    /// 
+    /// 光标已经在行尾时删除换行符，与下一行合并；在最后一行的行尾时不做任何操作
+    fn kill_line(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let rows = self.rows.read().unwrap();
+        let Some(len) = rows.get(y).map(|row| row.len) else {
+            return;
+        };
+        let end = if x < len {
+            Position { x: len, y }
+        } else if y + 1 < rows.len() {
+            Position { x: 0, y: y + 1 }
+        } else {
+            return;
+        };
+        drop(rows);
+        self.clear_selection();
+        // 光标在虚拟空间中时由 `delete_range` 补齐到光标所在的列再合并
+        self.kill_range(self.cursor_position, end, EditKind::KillLine, false);
+    }
+
+    /// 删除从 `start` 到 `end`（不含）之间的文本，并把删除的文本记入删除环
+    /// 
+    /// # 参数
+    /// * `start` - 删除范围的开始位置
+    /// * `end` - 删除范围的结束位置
+    /// * `kind` - 记录到撤销栈中的操作种类
+    /// * `backward` - 是否为向光标前的删除，与上一次删除合并时加在开头
+    fn kill_range(&mut self, start: Position, end: Position, kind: EditKind, backward: bool) {
+        let continues = self.continues_kill();
+        let text = self.text_range(start, end);
+        self.delete_range(start, end, kind);
+        self.record_kill(text, backward, continues);
+    }
+
+    /// 上一个修改是否就是在当前位置的删除（之后没有编辑也没有移动光标）
+    fn continues_kill(&self) -> bool {
+        self.last_kill == Some((self.version, self.cursor_position))
+    }
+
+    /// 把删除的文本记入删除环
+    /// 
+    /// # 参数
+    /// * `text` - 被删除的文本
+    /// * `backward` - 是否为向光标前的删除
+    /// * `continues` - 是否紧接着上一次删除，是时并入最近的一条记录
+    fn record_kill(&mut self, text: String, backward: bool, continues: bool) {
+        match self.kill_ring.front_mut() {
+            Some(last) if continues => {
+                if backward {
+                    last.insert_str(0, &text);
+                } else {
+                    last.push_str(&text);
+                }
+            }
+            _ => {
+                self.kill_ring.push_front(text);
+                self.kill_ring.truncate(KILL_RING_SIZE);
+            }
+        }
+        self.last_kill = Some((self.version, self.cursor_position));
+    }
+
+    /// 在光标处插入删除环中最近的一条记录，有选择时先删除选中的文本
+    fn yank(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        let Some(text) = self.kill_ring.front().cloned() else {
+            self.status_message = StatusMessage::from("删除环为空".to_string());
+            return;
+        };
+        self.begin_undo_group(EditKind::Paste);
+        if self.selection.is_some() {
+            self.delete_selection();
+        }
+        self.insert_text(&text);
+        self.end_undo_group();
+    }
+
+    /// 获取从 `start` 到 `end`（不含）之间的文本，跨行处以换行连接
+    fn text_range(&self, start: Position, end: Position) -> String {
+        let rows = self.rows.read().unwrap();
+        let lines: Vec<String> = (start.y..=end.y.min(rows.len().saturating_sub(1)))
+            .map(|y| {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { usize::MAX };
+                rows[y].string[..].graphemes(true).skip(from).take(to.saturating_sub(from)).collect()
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// 删除从光标所在行开始的 `count` 行，光标移到删除后同一行的行首
+    fn delete_lines(&mut self, count: usize) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor_before = self.cursor_position;
+        let y = cursor_before.y;
+        let len = self.rows.read().unwrap().len();
+        if y >= len {
+            return;
+        }
+        let end = len.min(y.saturating_add(count));
+        let continues = self.continues_kill();
+        let before = self.snapshot_rows(y, end - y);
+        // 每行带上换行符，插入回行首时恢复为完整的行
+        let text: String = before.iter().map(|line| format!("{}\n", line)).collect();
+        let mut rows = self.rows.write().unwrap();
+        rows.drain(y..end);
+        self.cursor_position = Position { x: 0, y: y.min(rows.len().saturating_sub(1)) };
+        drop(rows); // 释放写锁
+        self.push_undo(EditKind::DeleteLines, y, before, 0, cursor_before);
+        self.mark_dirty();
+        self.record_kill(text, false, continues);
+    }
+
+    /// 显示 `:` 命令行并执行输入的命令
+    fn command_line(&mut self) -> io::Result<()> {
+        let Some(command) = self.prompt::<fn(&mut Editor, &str, KeyCode) -> bool>(":", None)? else {
+            return Ok(());
+        };
+        self.run_command(command.trim())
+    }
+
+    /// 执行一条命令行命令
+    /// 
+    /// 命令分派给对应的编辑器方法，支持的命令见模块文档
+    /// 
+    /// # 参数
+    /// * `command` - 去掉首尾空白的命令文本
+    fn run_command(&mut self, command: &str) -> io::Result<()> {
+        if let Some(substitute) = command.strip_prefix("s/") {
+            self.substitute(substitute);
+            return Ok(());
+        }
+        let (name, argument) = command.split_once(' ').map_or((command, ""), |(name, argument)| (name, argument.trim()));
+        match name {
+            "" => (),
+            "e" | "edit" => {
+                if argument.is_empty() {
+                    self.status_message = StatusMessage::error("用法: e <文件>".to_string());
+                } else if self.maybe_confirm_discard()? {
+                    self.open_path(argument)?;
+                }
+            }
+            "goto" => self.jump_to_line(argument),
+            "noh" | "nohlsearch" => self.clear_search_highlight(),
+            "fold" => self.fold(),
+            "unfold" => self.unfold(),
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => self.jump_to_line(name),
+            "set" => self.set_option(argument),
+            "w" => self.save(false)?,
+            "q" => {
+                if self.any_dirty() {
+                    self.status_message = StatusMessage::error("有未保存的修改，使用 :q! 放弃修改并退出".to_string());
+                } else {
+                    self.should_quit = true;
+                }
+            }
+            "q!" => self.should_quit = true,
+            "wq" | "x" => {
+                self.save(false)?;
+                // 保存失败或被取消时不退出
+                if !self.is_dirty() {
+                    self.should_quit = true;
+                }
+            }
+            _ => {
+                self.status_message = StatusMessage::error(format!("未知的命令: {}", command));
+            }
+        }
+        Ok(())
+    }
+
+    /// 执行 `set 选项=值` 命令
+    fn set_option(&mut self, argument: &str) {
+        let Some((option, value)) = argument.split_once('=') else {
+            self.status_message = StatusMessage::error("用法: set 选项=值".to_string());
+            return;
+        };
+        match option.trim() {
+            "tabwidth" | "tab_width" => match value.trim().parse::<usize>() {
+                Ok(width) if width > 0 => {
+                    self.tab_width = width;
+                    self.status_message = StatusMessage::from(format!("制表符宽度: {}", width));
+                }
+                _ => {
+                    self.status_message = StatusMessage::error("tabwidth 的值应为正整数".to_string());
+                }
+            },
+            "filetype" | "ft" => self.apply_filetype(value),
+            option => {
+                self.status_message = StatusMessage::error(format!("未知的选项: {}", option));
+            }
+        }
+    }
+
+    /// 执行 `s/查找/替换/标志` 命令，`s/` 之后的部分由参数给出
+    /// 
+    /// 查找和替换文本中的 `\/` 表示斜杠本身；标志为 `g` 时替换全部匹配，
+    /// 否则替换光标之后的下一处匹配
+    fn substitute(&mut self, command: &str) {
+        if !self.check_writable() {
+            return;
+        }
+        // 按未转义的斜杠拆分
+        let mut parts = vec![String::new()];
+        let mut chars = command.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('/') => parts.last_mut().unwrap().push('/'),
+                    Some(c) => {
+                        let part = parts.last_mut().unwrap();
+                        part.push('\\');
+                        part.push(c);
+                    }
+                    None => parts.last_mut().unwrap().push('\\'),
+                },
+                '/' => parts.push(String::new()),
+                c => parts.last_mut().unwrap().push(c),
+            }
+        }
+        // 先检查命令的结构，多余的斜杠不应被当成标志报告
+        if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() {
+            self.status_message = StatusMessage::error("用法: s/查找/替换/[g]".to_string());
+            return;
+        }
+        let all = match parts.get(2).map(String::as_str) {
+            None | Some("") => false,
+            Some("g") => true,
+            Some(flags) => {
+                self.status_message = StatusMessage::error(format!("未知的替换标志: {}", flags));
+                return;
+            }
+        };
+
+        let replace_text = parts.swap_remove(1);
+        self.current_search = Some(parts.swap_remove(0));
+        self.search_state.replace_text = Some(replace_text);
+        if all {
+            self.replace_all_matches();
+        } else {
+            self.replace_current_match();
+        }
+        self.current_search = None;
+        self.search_state.replace_text = None;
+    }
+
+    /// 重复执行移动命令，光标不再移动（到达文档边界）时提前停止
+    /// 
+    /// # 参数
+    /// * `count` - 重复次数
+    /// * `step` - 单次移动
+    fn repeat_movement(&mut self, count: usize, mut step: impl FnMut(&mut Self)) {
+        for _ in 0..count {
+            let before = self.cursor_position;
+            step(self);
+            if self.cursor_position == before {
+                break;
+            }
+        }
+    }
+
+    /// 按单词移动光标
+    /// 
+    /// # 参数
+    /// * `forward` - true 移动到下一个单词的开头，false 移动到上一个单词的开头
+    fn move_word(&mut self, forward: bool) {
+        self.cursor_position = self.word_boundary(forward, false);
+    }
+
+    /// 删除光标前到上一个单词开头、或光标后到下一个单词结尾的文本
+    /// 
+    /// 光标在行首（行尾）时与上一行（下一行）合并，与 `delete_char` 一致。
+    /// 整个删除作为一个撤销单元
+    /// 
+    /// # 参数
+    /// * `forward` - true 向后删除（Ctrl-Delete），false 向前删除（Ctrl-Backspace）
+    fn delete_word(&mut self, forward: bool) {
+        if !self.check_writable() {
+            return;
+        }
+        let cursor = self.cursor_position;
+        let target = self.word_boundary(forward, true);
+        if target == cursor {
+            return;
+        }
+        let (start, end) = if forward { (cursor, target) } else { (target, cursor) };
+        self.clear_selection();
+        // 从文档末尾之后的空行向前删除时没有换行可删，只把光标移到最后一行的行尾
+        let Some((start, end)) = self.clamp_range_to_document(start, end) else {
+            return;
+        };
+        if start == end {
+            self.cursor_position = start;
+            return;
+        }
+        self.kill_range(start, end, EditKind::DeleteWord, !forward);
+    }
+
+    /// 计算从光标出发的单词边界
+    /// 
+    /// 单词字符与 `get_word_at` 一致（字母数字和 `_`），连续的标点也算作一个单词，
+    /// 空白被跳过。在行尾向后时到下一行行首，在行首向前时到上一行行尾
+    /// 
+    /// # 参数
+    /// * `forward` - 向后（true）还是向前（false）查找
+    /// * `word_end` - 向后查找时停在下一个单词的结尾，而不是下一个单词的开头
+    /// 
+    /// # 返回值
+    /// 边界位置，已经在文档开头或结尾时返回光标位置
+    fn word_boundary(&self, forward: bool, word_end: bool) -> Position {
+        let Position { mut x, mut y } = self.cursor_position;
+        let rows = self.rows.read().unwrap();
+        // 文档末尾之后的空行按没有内容的行处理，向前时到最后一行的行尾
+        let classes: Vec<WordClass> = rows
+            .get(y)
+            .map_or_else(Vec::new, |row| row.string[..].graphemes(true).map(WordClass::of).collect());
+        let skip_blanks = |x: &mut usize| {
+            while *x < classes.len() && classes[*x] == WordClass::Blank {
+                *x += 1;
+            }
+        };
+        let skip_run = |x: &mut usize| {
+            if let Some(&class) = classes.get(*x).filter(|&&class| class != WordClass::Blank) {
+                while *x < classes.len() && classes[*x] == class {
+                    *x += 1;
+                }
+            }
+        };
+
+        if forward {
+            if x >= classes.len() {
+                if y + 1 < rows.len() {
+                    y += 1;
+                    x = 0;
+                }
+            } else if word_end {
+                skip_blanks(&mut x);
+                skip_run(&mut x);
+            } else {
+                skip_run(&mut x);
+                skip_blanks(&mut x);
+            }
+        } else if x == 0 {
+            if y > 0 {
+                y -= 1;
+                x = rows[y].len;
+            }
+        } else {
+            x = x.min(classes.len());
+            while x > 0 && classes[x - 1] == WordClass::Blank {
+                x -= 1;
+            }
+            if x > 0 {
+                let class = classes[x - 1];
+                while x > 0 && classes[x - 1] == class {
+                    x -= 1;
+                }
+            }
+        }
+        Position { x, y }
+    }
+
+    /// 移动光标
+    /// 
+    /// 处理各种光标移动情况：
+    /// - 上下左右移动
+    /// - 处理行首行尾（Home 在第一个非空白字符和第 0 列之间切换）
+    /// - 处理页面上下翻页
+    /// - 支持 CJK 等宽字符
+    /// - 保持选择状态（如果有）
+    /// 
+    /// # 参数
+    /// * `key` - 移动方向对应的按键
+    fn move_cursor(&mut self, key: KeyCode) {
+        // 连续上下移动时保持最初的显示列，经过较短的行也不会丢失
+        let goal_column = match key {
+            KeyCode::Up | KeyCode::Down => Some(self.goal_column.unwrap_or_else(|| self.cursor_display_x())),
+            _ => None,
+        };
+        let Position { mut x, mut y } = self.cursor_position;
+        let rows = self.rows.read().unwrap();
+        let height = rows.len();
+
+        let current_row_len = rows.get(y).map_or(0, |row| row.len);
+
+        match key {
+            KeyCode::Up if y > 0 => {
+                y = visible_row(&rows, y - 1);
+                // 落在新行中最接近目标显示列的字素上
+                if let Some(row) = rows.get(y) {
+                    x = self.position_at_column(row, goal_column.unwrap_or(0));
+                }
+            }
+            KeyCode::Down if y < height => {
+                y = next_visible_row(&rows, y);
+                // 落在新行中最接近目标显示列的字素上
+                if let Some(row) = rows.get(y) {
+                    x = self.position_at_column(row, goal_column.unwrap_or(0));
+                }
+            }
+            KeyCode::Left => {
+                if x > 0 {
+                    x -= 1;
+                } else if y > 0 {
+                    y = visible_row(&rows, y - 1);
+                    if let Some(row) = rows.get(y) {
+                        x = row.len;
+                    } else {
+                        x = 0;
+                    }
+                }
+            }
+            KeyCode::Right => {
+                // 虚拟空间中在行尾继续向右移动，不换到下一行
+                if x < current_row_len || (self.in_virtual_space() && y < height) {
+                    x += 1;
+                } else if y < height {
+                    y = next_visible_row(&rows, y);
+                    x = 0;
+                }
+            }
+            // 翻页时视图与光标一起移动，光标在屏幕上的相对位置保持不变
+            KeyCode::PageUp => {
+                let page = self.page_size();
+                self.offset.y = step_visible_rows(&rows, self.offset.y, page, false);
+                y = step_visible_rows(&rows, y, page, false);
+            }
+            KeyCode::PageDown => {
+                let page = self.page_size();
+                let offset_y = step_visible_rows(&rows, self.offset.y, page, true);
+                self.offset.y = visible_row(&rows, offset_y.min(height.saturating_sub(1)));
+                y = step_visible_rows(&rows, y, page, true);
+            }
+            KeyCode::Home => {
+                // 智能行首：先跳到第一个非空白字符，已经在那里（或更靠左）时再跳到第 0 列
+                let first_non_blank = rows.get(y).map_or(0, |row| {
+                    row.string[..]
+                        .graphemes(true)
+                        .position(|g| !g.chars().all(char::is_whitespace))
+                        .unwrap_or(row.len)
+                });
+                x = if x <= first_non_blank { 0 } else { first_non_blank };
+            }
+            KeyCode::End => x = current_row_len,
+            _ => (),
+        }
+
+        // 确保 x 不超过当前行的长度（虚拟空间中可以停在行尾之后）
+        let width = if let Some(row) = rows.get(y) {
+            row.len
+        } else {
+            0
+        };
+        if x > width && !(self.in_virtual_space() && y < height) {
+            x = width;
+        }
+
+        drop(rows);
+        self.cursor_position = Position { x, y };
+        self.goal_column = goal_column;
+    }
+
+    /// 视图向下（或向上）滚动若干个可见行，被折叠隐藏的行不计入
+    fn scroll_view(&mut self, count: usize, down: bool) {
+        let rows = self.rows.read().unwrap();
+        let offset_y = step_visible_rows(&rows, self.offset.y, count, down);
+        drop(rows);
+        self.offset.y = offset_y;
+    }
+
+    /// 翻页时光标移动的行数（屏幕行数减去保留的上下文行数）
+    fn page_size(&self) -> usize {
+        self.screen_rows.saturating_sub(self.page_overlap).max(1)
+    }
+
+    /// 半页滚动
+    /// 
+    /// 光标和视图同时移动半个屏幕，光标在屏幕上的相对位置保持不变
+    /// 
+    /// # 参数
+    /// * `down` - 是否向下滚动
+    fn scroll_half_page(&mut self, down: bool) {
+        let half = (self.screen_rows / 2).max(1);
+        let rows = self.rows.read().unwrap();
+        let height = rows.len();
+        let Position { x, y } = self.cursor_position;
+        let offset_y = step_visible_rows(&rows, self.offset.y, half, down);
+        self.offset.y = if down { visible_row(&rows, offset_y.min(height.saturating_sub(1))) } else { offset_y };
+        let y = step_visible_rows(&rows, y, half, down);
+        let width = rows.get(y).map_or(0, |row| row.len);
+        drop(rows);
+        self.cursor_position = Position { x: x.min(width), y };
+    }
+
+    /// 刷新屏幕显示
+    fn refresh_screen(&mut self) -> io::Result<()> {
+        if self.hex_view.is_some() {
+            return self.refresh_hex_screen();
+        }
+        self.apply_syntax_job();
+        self.scroll();
+        // 在绘制之前完成跨行扫描，避免在持有绘制用的读锁时再次加锁
+        self.update_matching_bracket();
+        
+        queue!(
+            stdout(),
+            terminal::Clear(ClearType::All),
+            cursor::Hide,
+            cursor::MoveTo(0, 0)
+        )?;
+        
+        self.draw_rows()?;
+        self.draw_status_bar()?;
+        self.draw_message_bar()?;
+        
+        let Position { x, y } = self.cursor_position;
+        let Position { x: offset_x, y: offset_y } = self.offset;
+        
+        // 调整光标位置计算
+        let (cursor_x, cursor_y) = if self.word_wrap {
+            let width = self.text_cols();
+            let rows = self.rows.read().unwrap();
+            let cursor_y = self.visual_rows_between(&rows, offset_y, self.cursor_position, width) - 1;
+            let cursor_x = rows.get(y).map_or(0, |row| {
+                let starts = self.wrap_segments(row, width);
+                let start = starts.iter().rev().find(|&&start| start <= x).copied().unwrap_or(0);
+                self.grapheme_widths(row).iter().skip(start).take(x - start).sum()
+            });
+            (cursor_x, cursor_y)
+        } else {
+            let rows = self.rows.read().unwrap();
+            let cursor_y = self.visual_rows_between(&rows, offset_y, self.cursor_position, 0) - 1;
+            drop(rows);
+            (self.cursor_display_x().saturating_sub(offset_x), cursor_y)
+        };
+        let cursor_x = cursor_x + self.gutter_width();
+        
+        queue!(
+            stdout(),
+            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+            cursor::Show
+        )?;
+        
+        stdout().flush()
+    }
+
+    /// 在十六进制视图下刷新屏幕
+    fn refresh_hex_screen(&mut self) -> io::Result<()> {
+        let height = self.screen_rows;
+        let (cursor_x, cursor_y) = match self.hex_view.as_mut() {
+            Some(hex_view) => {
+                hex_view.scroll(height);
+                queue!(
+                    stdout(),
+                    terminal::Clear(ClearType::All),
+                    cursor::Hide,
+                    cursor::MoveTo(0, 0)
+                )?;
+                for terminal_row in 0..height {
+                    let line = terminal_row + hex_view.offset;
+                    if line < hex_view.line_count() {
+                        let mut text = hex_view.render_line(line);
+                        text.truncate(self.screen_cols);
+                        queue!(stdout(), Print(&text))?;
+                    } else {
+                        queue!(stdout(), Print("~"))?;
+                    }
+                    queue!(stdout(), terminal::Clear(ClearType::UntilNewLine))?;
+                    if terminal_row < height - 1 {
+                        queue!(stdout(), Print("\r\n"))?;
+                    }
+                }
+                hex_view.cursor_screen_position()
+            }
+            None => return Ok(()),
+        };
+
+        self.draw_status_bar()?;
+        self.draw_message_bar()?;
+
+        queue!(
+            stdout(),
+            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+            cursor::Show
+        )?;
+
+        stdout().flush()
+    }
+
+    /// 绘制状态栏
+    fn draw_status_bar(&mut self) -> io::Result<()> {
+        let width = self.screen_cols;
+        
+        let modified_indicator = if self.is_dirty() { "(modified)" } else { "" };
+        let mut file_name = "[No Name]".to_string();
+        if let Some(name) = &self.filename {
+            file_name = truncate_to_width(name, 20).to_string();
+        }
+        
+        // 打开了多个缓冲区时显示当前缓冲区的编号
+        if self.buffers.len() > 1 {
+            file_name = format!("[{}/{}] {}", self.current_buffer + 1, self.buffers.len(), file_name);
+        }
+
+        let mut status = format!(
+            "{} - {} lines {}",
+            file_name,
+            self.rows.read().unwrap().len(),
+            modified_indicator
+        );
+
+        // 添加覆盖模式指示
+        if !self.insert_mode {
+            status.push_str(" [OVR]");
+        }
+
+        // 添加只读指示
+        if self.read_only {
+            status.push_str(" [RO]");
+        }
+
+        // 添加模式编辑的当前模式
+        if let Some(mode) = self.vi_mode {
+            status.push_str(&format!(" [{}]", mode.name()));
+        }
+
+        // 添加搜索模式指示
+        if self.is_searching {
+            status.push_str(" | SEARCH MODE");
+            if let Some((index, total, capped)) = self.search_state.match_status {
+                let index = index.map_or("?".to_string(), |index| index.to_string());
+                let plus = if capped { "+" } else { "" };
+                status.push_str(&format!(" match {} of {}{}", index, total, plus));
+            }
+        }
+        
+        // 文件类型和换行符风格显示在位置指示之前
+        let file_info = if self.hex_view.is_some() {
+            String::new()
+        } else {
+            format!("{} | {} | ", self.syntax.name, self.line_ending.name())
+        };
+        let line_indicator = if let Some(hex_view) = &self.hex_view {
+            status.push_str(" | HEX");
+            format!("0x{:08x}/{}", hex_view.cursor, hex_view.bytes.len())
+        } else {
+            // 字素位置与显示列不同（制表符、宽字符）时同时显示显示列
+            let column = self.cursor_position.x.saturating_add(1);
+            let visual_column = self.cursor_display_x().saturating_add(1);
+            let column = if visual_column == column {
+                column.to_string()
+            } else {
+                format!("{} (vc {})", column, visual_column)
+            };
+            format!(
+                "{}:{}/{} {}",
+                self.cursor_position.y.saturating_add(1),
+                column,
+                self.rows.read().unwrap().len(),
+                self.scroll_position()
+            )
+        };
+        
+        let status = layout_status_bar(&status, &file_info, &line_indicator, width);
+        
+        queue!(
+            stdout(),
+            style::SetAttribute(style::Attribute::Reverse),
+            cursor::MoveTo(0, self.screen_rows as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            Print(status),
+            style::SetAttribute(style::Attribute::Reset)
+        )?;
+        
+        Ok(())
+    }
+
+    /// 视图在文档中的位置，与 `less` 相同
+    /// 
+    /// 按屏幕上显示的行计算：被折叠隐藏的行不计入，自动换行时一行按它占用的屏幕行数计入
+    /// 
+    /// # 返回值
+    /// 整个文档都可见时为 `All`，位于开头或末尾时为 `Top` / `Bot`，
+    /// 否则为已滚过的部分占可滚动范围的百分比，如 `45%`
+    fn scroll_position(&self) -> String {
+        let rows = self.rows.read().unwrap();
+        let width = self.text_cols();
+        // 计数包含终点所在的一行，减去后为终点之前的屏幕行数
+        let above = self.visual_rows_between(&rows, 0, Position { x: 0, y: self.offset.y }, width) - 1;
+        let total = self.visual_rows_between(&rows, 0, Position { x: 0, y: rows.len() }, width) - 1;
+        let scrollable = total.saturating_sub(self.screen_rows);
+        match above {
+            0 if scrollable == 0 => "All".to_string(),
+            0 => "Top".to_string(),
+            above if above >= scrollable => "Bot".to_string(),
+            above => format!("{}%", above * 100 / scrollable),
+        }
+    }
+
+    /// 绘制消息栏
+    fn draw_message_bar(&mut self) -> io::Result<()> {
+        queue!(
+            stdout(),
+            cursor::MoveTo(0, (self.screen_rows + 1) as u16),
+            terminal::Clear(ClearType::CurrentLine)
+        )?;
+            
+        // 常驻消息总是显示，普通消息超时后不再显示
+        if self.status_message.is_visible_at(Instant::now(), self.message_timeout, self.error_message_timeout) {
+            let text = truncate_to_width(&self.status_message.text, self.screen_cols);
+            queue!(stdout(), Print(text))?;
+        }
+        
+        Ok(())
+    }
+
+    /// 渲染单行文本
+    /// 
+    /// 处理行的渲染，包括：
+    /// - 语法高亮
+    /// - 搜索匹配高亮
+    /// - 选择区域高亮
+    /// - CJK 字符宽度处理
+    /// - 制表符展开
+    /// 
+    /// # 参数
+    /// * `row` - 要渲染的行
+    /// * `start` - 第一个渲染的字素位置
+    /// * `end` - 渲染到此字素位置之前为止（自动换行时为显示段的结束位置）
+    /// * `width` - 可用于显示正文的列数
+    /// * `selected` - 本行被选中的字素范围，由 `selection_span` 计算
+    /// * `cursor_at_end` - 光标是否位于本行行末，此时不高亮行尾空白，以免输入时闪烁
+    /// 
+    /// # 返回值
+    /// 返回包含 ANSI 转义序列的渲染后的字符串，参数和相关状态不变时直接返回缓存的结果
+    fn render_row(&self, row: &Row, start: usize, end: usize, width: usize, selected: Option<(usize, usize)>, cursor_at_end: bool) -> String {
+        let key = self.render_key(start, end, width, selected, cursor_at_end);
+        let mut cache = row.render_cache.lock().unwrap();
+        if let Some((_, rendered)) = cache.iter().find(|(cached, _)| *cached == key) {
+            return rendered.clone();
+        }
+        let rendered = self.render_row_uncached(row, start, end, width, selected, cursor_at_end);
+        // 同一显示段的旧结果已经过期
+        cache.retain(|(cached, _)| (cached.start, cached.end) != (start, end));
+        if cache.len() >= RENDER_CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.push((key, rendered.clone()));
+        rendered
+    }
+
+    /// 计算当前行（`cursor_position.y`，由 `draw_rows` 临时设置）的渲染缓存键
+    fn render_key(&self, start: usize, end: usize, width: usize, selection: Option<(usize, usize)>, cursor_at_end: bool) -> RenderKey {
+        let y = self.cursor_position.y;
+        let bracket_x = |position: Position| (position.y == y).then_some(position.x);
+        let brackets = self
+            .matching_bracket
+            .map_or([None, None], |(a, b)| [bracket_x(a), bracket_x(b)]);
+        RenderKey {
+            start,
+            end,
+            width,
+            search: self.current_search.clone(),
+            selection,
+            brackets,
+            theme: self.theme.name,
+            tab_width: self.tab_width,
+            show_whitespace: self.show_whitespace,
+            show_indent_guides: self.show_indent_guides,
+            trailing_whitespace: self.highlight_trailing_whitespace && !cursor_at_end,
+            spell_check: self.spell_check,
+        }
+    }
+
+    /// 渲染单行文本，不经过缓存，由 `render_row` 调用
+    fn render_row_uncached(&self, row: &Row, start: usize, end: usize, width: usize, selected: Option<(usize, usize)>, cursor_at_end: bool) -> String {
+        let mut result = String::new();
+        let mut current_highlighting = HighlightType::Normal;
+        let mut is_in_selection = false;
+        let mut is_in_search_highlight = false;
+        let mut is_in_trailing = false;
+        let mut is_in_misspelling = false;
+        let highlight_trailing = self.highlight_trailing_whitespace && !cursor_at_end;
+        let mut current_display_width = 0;
+        let mut rendered_chars = 0;
+
+        // 行尾空白从这个字素位置开始，显示空白字符时用 `·` 标出，开启高亮时以红色背景显示
+        let row_len = row.len;
+        let trailing_start = row_len - row.string
+            .graphemes(true)
+            .rev()
+            .take_while(|g| *g == " " || *g == "\t")
+            .count();
+        // 行首缩进的字素个数，缩进参考线只画在这个范围内
+        let indent_end = row.string
+            .graphemes(true)
+            .take_while(|g| *g == " " || *g == "\t")
+            .count();
+        let tab_width = self.tab_width.max(1);
+
+        // 获取搜索高亮范围，字节偏移换算成字素位置
+        let mut search_highlights = Vec::new();
+        if let Some(query) = self.current_search.as_deref().filter(|query| !query.is_empty()) {
+            let boundaries: Vec<usize> = row.string.grapheme_indices(true).map(|(i, _)| i).collect();
+            let grapheme_at = |byte: usize| boundaries.partition_point(|&b| b < byte);
+            for (byte, matched) in row.string.match_indices(query) {
+                search_highlights.push((grapheme_at(byte), grapheme_at(byte + matched.len())));
+            }
+        }
+
+        let misspellings = self.misspelled_words(row);
+
+        // 遍历并渲染每个字符，制表符的宽度取决于它在整行中的显示列
+        let mut column = 0;
+        for (index, grapheme) in row.string[..].graphemes(true).enumerate() {
+            let char_width = self.grapheme_width(grapheme, column);
+            // 缩进中位于制表位上的空白字符显示为参考线
+            let is_guide = self.show_indent_guides && index < indent_end && column % tab_width == 0;
+            column += char_width;
+            
+            // 跳过起始位置之前的字符
+            if index < start {
+                continue;
+            }
+            if index >= end {
+                break;
+            }
+
+            // 检查是否超出屏幕宽度
+            if current_display_width + char_width > width {
+                break;
+            }
+
+            // 检查是否在搜索高亮范围内
+            let in_search = search_highlights.iter()
+                .any(|&(start, end)| index >= start && index < end);
+
+            // 检查是否在选择范围内
+            // 选择状态只在实际渲染的字符上切换，被跳过的字符不改变状态，
+            // 因此第一个可见字符位于选择内时总会输出反转显示，
+            // 从视图左侧之外开始的选择也能正确显示
+            let in_selection = selected.is_some_and(|(from, to)| index >= from && index < to);
+            if in_selection != is_in_selection {
+                is_in_selection = in_selection;
+                if in_selection {
+                    result.push_str("\x1b[7m"); // 反转显示（背景色和前景色交换）
+                } else {
+                    result.push_str("\x1b[27m"); // 取消反转
+                    // 恢复选择区域内未输出的语法高亮颜色
+                    if !in_search {
+                        let color = self.theme.color(current_highlighting);
+                        result.push_str(&format!("\x1b[38;5;{}m", color));
+                    }
+                }
+            }
+
+            // 获取语法高亮类型
+            if let Some(&highlighting_type) = row.highlighting.get(index) {
+                if highlighting_type != current_highlighting {
+                    current_highlighting = highlighting_type;
+                    if !in_search && !is_in_selection {
+                        let color = self.theme.color(current_highlighting);
+                        result.push_str(&format!("\x1b[38;5;{}m", color));
+                    }
+                }
+            }
+
+            // 处理搜索高亮
+            if in_search != is_in_search_highlight {
+                is_in_search_highlight = in_search;
+                if in_search {
+                    result.push_str("\x1b[43m"); // 黄色背景
+                } else {
+                    result.push_str("\x1b[49m"); // 恢复默认背景
+                    // 恢复当前语法高亮的前景色
+                    if !is_in_selection {
+                        let color = self.theme.color(current_highlighting);
+                        result.push_str(&format!("\x1b[38;5;{}m", color));
+                    }
+                }
+            }
+
+            // 行尾空白以红色背景显示，搜索高亮优先
+            let in_trailing = highlight_trailing && index >= trailing_start && !in_search;
+            if in_trailing != is_in_trailing {
+                is_in_trailing = in_trailing;
+                if in_trailing {
+                    result.push_str("\x1b[41m");
+                } else if !in_search {
+                    result.push_str("\x1b[49m");
+                }
+            }
+
+            // 拼写错误的单词加下划线
+            let in_misspelling = misspellings.iter().any(|&(start, end)| index >= start && index < end);
+            if in_misspelling != is_in_misspelling {
+                is_in_misspelling = in_misspelling;
+                result.push_str(if in_misspelling { "\x1b[4m" } else { "\x1b[24m" });
+            }
+
+            // 匹配的括号以品红色背景显示
+            let is_bracket = self.matching_bracket.is_some_and(|(a, b)| {
+                let current_pos = Position { x: index, y: self.cursor_position.y };
+                current_pos == a || current_pos == b
+            });
+            if is_bracket {
+                result.push_str("\x1b[45m");
+            }
+
+            // 渲染字符，显示空白字符或缩进参考线时以暗淡的符号代替第一列，显示宽度不变。
+            // 空白字符的符号优先于参考线
+            if grapheme == "\t" {
+                if self.show_whitespace {
+                    result.push_str("\x1b[2m→\x1b[22m");
+                    result.push_str(&" ".repeat(char_width - 1));
+                } else if is_guide {
+                    result.push_str("\x1b[2m│\x1b[22m");
+                    result.push_str(&" ".repeat(char_width - 1));
+                } else {
+                    result.push_str(&" ".repeat(char_width));
+                }
+            } else if self.show_whitespace && grapheme == " " && index >= trailing_start {
+                result.push_str("\x1b[2m·\x1b[22m");
+            } else if is_guide {
+                result.push_str("\x1b[2m│\x1b[22m");
+            } else {
+                result.push_str(grapheme);
+            }
+            current_display_width += char_width;
+            if is_bracket {
+                // 恢复搜索高亮或默认背景
+                result.push_str(if is_in_search_highlight { "\x1b[43m" } else { "\x1b[49m" });
+            }
+            rendered_chars += 1;
+        }
+
+        // 这一段渲染到了行末并且还有空间时，显示行末标记；选择包含行末的换行符时
+        // （包括选中的空行）这一格反转显示，表示换行符也被选中
+        if self.shows_line_end_cell(selected) && start + rendered_chars >= row_len && current_display_width < width {
+            result.push_str("\x1b[0m");
+            if Self::selects_newline(selected) {
+                result.push_str("\x1b[7m");
+            }
+            result.push_str(if self.show_whitespace { "\x1b[2m¬" } else { " " });
+        }
+
+        result.push_str("\x1b[0m");
+        result
+    }
+
+    /// 本行被选中的范围是否包含行末的换行符（选择延续到下一行）
+    fn selects_newline(selected: Option<(usize, usize)>) -> bool {
+        selected.is_some_and(|(_, to)| to == usize::MAX)
+    }
+
+    /// 行末之后是否占用一格显示行末标记或被选中的换行符
+    fn shows_line_end_cell(&self, selected: Option<(usize, usize)>) -> bool {
+        self.show_whitespace || Self::selects_newline(selected)
+    }
+
+    /// 查找一行中拼写错误的单词，由 `render_row` 调用，因此只检查屏幕上显示的行
+    /// 
+    /// 只检查注释和字符串中的单词，文件类型没有注释和字符串规则（如纯文本）时检查整行。
+    /// 单词是连续的字母（中间可以有撇号），只检查长度超过一个字母的纯英文单词，
+    /// 原样或转换为小写后在词表中即视为正确
+    /// 
+    /// # 返回值
+    /// 拼写错误的单词的字素范围 `[start, end)`；没有开启拼写检查时返回空列表
+    fn misspelled_words(&self, row: &Row) -> Vec<(usize, usize)> {
+        let Some(dictionary) = self.dictionary.as_ref().filter(|_| self.spell_check) else {
+            return Vec::new();
+        };
+        let prose = self.syntax.is_prose();
+        let graphemes: Vec<&str> = row.string.graphemes(true).collect();
+        let is_letter = |index: usize| graphemes.get(index).is_some_and(|g| g.chars().next().is_some_and(char::is_alphabetic));
+        let mut misspellings = Vec::new();
+        let mut index = 0;
+        while index < graphemes.len() {
+            if !is_letter(index) {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while is_letter(index) || (graphemes.get(index) == Some(&"'") && is_letter(index + 1) && index > start) {
+                index += 1;
+            }
+            let checked = prose
+                || matches!(row.highlighting.get(start), Some(HighlightType::Comment | HighlightType::String));
+            let word = graphemes[start..index].concat();
+            if checked
+                && index - start > 1
+                && word.chars().all(|c| c.is_ascii_alphabetic() || c == '\'')
+                && !dictionary.contains(&word)
+                && !dictionary.contains(&word.to_lowercase())
+            {
+                misspellings.push((start, index));
+            }
+        }
+        misspellings
+    }
+
+    /// 计算每个屏幕行显示的内容
+    /// 
+    /// 未开启自动换行时一个文档行占一个屏幕行，从水平偏移处开始显示
+    /// 
+    /// # 参数
+    /// * `rows` - 文档的所有行
+    /// * `height` - 文本区域的高度
+    /// * `text_cols` - 文本区域的宽度（不含行号）
+    /// 
+    /// # 返回值
+    /// 每个屏幕行对应的文档行、字素范围和行首的填充宽度
+    fn screen_lines(&self, rows: &[Row], height: usize, text_cols: usize) -> Vec<(usize, usize, usize, usize)> {
+        let mut lines = Vec::new();
+        let mut file_row = self.offset.y;
+        while let Some(row) = rows.get(file_row) {
+            if lines.len() >= height {
+                break;
+            }
+            if self.word_wrap {
+                let starts = self.wrap_segments(row, text_cols);
+                for (i, &start) in starts.iter().enumerate() {
+                    let end = starts.get(i + 1).copied().unwrap_or(row.len);
+                    lines.push((file_row, start, end, 0));
+                }
+            } else {
+                // 从第一个完整位于水平偏移之后的字素开始显示，
+                // 被截断一半的宽字符用空格代替，使后面的字符对齐
+                let (index, column) = self.grapheme_at_column(row, self.offset.x);
+                if column < self.offset.x {
+                    let width = row.string[..].graphemes(true).nth(index).map_or(0, |g| self.grapheme_width(g, column));
+                    lines.push((file_row, index + 1, row.len, column + width - self.offset.x));
+                } else {
+                    lines.push((file_row, index, row.len, 0));
+                }
+            }
+            file_row = next_visible_row(rows, file_row);
+        }
+        lines
+    }
+
+    /// 绘制所有行
+    /// 
+    /// 渲染编辑器的主要内容区域：
+    /// - 显示文件内容
+    /// - 显示行号栏（开启时）
+    /// - 处理视图偏移
+    /// - 显示欢迎信息（空文件时）
+    /// - 处理行末和屏幕边界
+    /// 
+    /// # 错误
+    /// 如果发生 I/O 错误，将返回该错误
+    fn draw_rows(&mut self) -> io::Result<()> {
+        let height = self.screen_rows;
+        let gutter_width = self.gutter_width();
+        let text_cols = self.screen_cols.saturating_sub(gutter_width);
+        let rows = self.rows.read().unwrap();
+        let lines = self.screen_lines(&rows, height, text_cols);
+
+        for terminal_row in 0..height {
+            if let Some(&(file_row, start, end, padding)) = lines.get(terminal_row) {
+                let row = &rows[file_row];
+                if gutter_width > 0 && start == 0 {
+                    // 行号右对齐并以暗灰色显示
+                    queue!(
+                        stdout(),
+                        Print(format!("\x1b[38;5;242m{:>width$}\x1b[0m ", file_row + 1, width = gutter_width - 1))
+                    )?;
+                } else if gutter_width > 0 {
+                    // 折行后的后续显示段不重复显示行号
+                    queue!(stdout(), Print(" ".repeat(gutter_width)))?;
+                }
+                queue!(stdout(), Print(" ".repeat(padding)))?;
+                // 临时保存当前光标位置的 y 坐标
+                let saved_y = self.cursor_position.y;
+                // 设置当前渲染行的 y 坐标
+                self.cursor_position.y = file_row;
+                let selected = self.selection_span(&rows, file_row);
+                let cursor_at_end = file_row == saved_y && self.cursor_position.x >= row.len;
+                let rendered_row = self.render_row(row, start, end, text_cols.saturating_sub(padding), selected, cursor_at_end);
+                // 恢复光标位置的 y 坐标
+                self.cursor_position.y = saved_y;
+                queue!(stdout(), Print(&rendered_row))?;
+                // 折叠行在最后一个显示段之后标出隐藏的行数
+                if let Some(hidden) = row.folded.filter(|_| end == row.len) {
+                    let widths = self.grapheme_widths(row);
+                    let used = padding
+                        + widths[start.min(widths.len())..].iter().sum::<usize>()
+                        + usize::from(self.shows_line_end_cell(selected));
+                    let marker = format!(" {{...}} {} lines", hidden);
+                    let marker = truncate_to_width(&marker, text_cols.saturating_sub(used));
+                    queue!(stdout(), Print(format!("\x1b[2m{}\x1b[22m", marker)))?;
+                }
+            } else if rows.is_empty() && terminal_row == height / 3 {
+                let mut welcome = format!("Hecto editor -- version {}", VERSION);
+                // 终端比欢迎信息窄时截断（欢迎信息只含 ASCII 字符），保留行首的 `~`
+                welcome.truncate(self.screen_cols.saturating_sub(1));
+                let padding = self.screen_cols.saturating_sub(welcome.len()) / 2;
+                queue!(stdout(), Print("~"))?;
+                for _ in 1..padding {
+                    queue!(stdout(), Print(" "))?;
+                }
+                queue!(stdout(), Print(&welcome))?;
+            } else {
+                queue!(stdout(), Print("~"))?;
+            }
+            queue!(
+                stdout(),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+            if terminal_row < height - 1 {
+                queue!(stdout(), Print("\r\n"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 运行编辑器
+    /// 
+    /// # 参数
+    /// * `filenames` - 启动时要打开的文件，每个文件一个缓冲区（在终端初始化后打开，以便显示确认提示）
+    /// * `read_only` - 是否以只读模式打开这些文件
+    pub fn run(&mut self, filenames: &[String], read_only: bool) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        
+        // 设置终端
+        crossterm::execute!(
+            stdout(),
+            terminal::EnterAlternateScreen,
+            event::EnableMouseCapture,
+            terminal::SetTitle("Hecto Editor"),
+            terminal::DisableLineWrap
+        )?;
+
+        // 禁用快速编辑模式（Windows特定）
+        #[cfg(windows)]
+        {
+            use crossterm::event::EnableFocusChange;
+            crossterm::execute!(stdout(), EnableFocusChange)?;
+        }
+
+        let result = self.open_all(filenames, read_only).and_then(|()| self.run_loop());
+
+        // 恢复终端设置
+        crossterm::execute!(
+            stdout(),
+            event::DisableMouseCapture,
+            terminal::EnableLineWrap,
+            terminal::LeaveAlternateScreen
+        )?;
+        terminal::disable_raw_mode()?;
+        
+        result
+    }
+
+    /// 把每个文件打开到各自的缓冲区，完成后回到第一个缓冲区
+    /// 
+    /// 文件名可以带 `:行` 或 `:行:列` 后缀（见 `parse_file_location`），打开后跳转到该位置
+    fn open_all(&mut self, filenames: &[String], read_only: bool) -> io::Result<()> {
+        self.read_only = read_only;
+        for (i, name) in filenames.iter().enumerate() {
+            if i > 0 {
+                self.new_buffer();
+                self.read_only = read_only;
+            }
+            let (name, location) = parse_file_location(name);
+            self.open(name)?;
+            if let Some((line, column)) = location {
+                self.jump_to(line, Some(column.unwrap_or(1)));
+            }
+        }
+        self.switch_buffer(0);
+        if filenames.len() > 1 {
+            self.status_message = StatusMessage::from(format!("已打开 {} 个文件，按 Alt-N 切换", filenames.len()));
+        }
+        Ok(())
+    }
+
+    /// 主循环
+    fn run_loop(&mut self) -> io::Result<()> {
+        loop {
+            if let Err(error) = self.refresh_screen() {
+                die(&error);
+            }
+            if self.should_quit {
+                break;
+            }
+            if let Err(error) = self.process_keypress() {
+                die(&error);
+            }
+        }
+        // 记录所有已打开文件的光标位置
+        self.remember_position();
+        for buffer in self.buffers.iter().flatten() {
+            if let Some(filename) = &buffer.filename {
+                self.remember_position_of(filename, buffer.cursor_position);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 拆分命令行参数中的 `路径:行` 或 `路径:行:列`，如编译器报告的 `src/main.rs:120:5`
+/// 
+/// 参数本身是已存在的路径时不拆分，以便打开名称中带冒号的文件。
+/// 冒号之前只剩一个字母时视为 Windows 盘符（如 `C:\src`、`C:12`），不作为行号
+/// 
+/// # 返回值
+/// 路径，以及行号和可选的列号（从 1 开始）；没有位置后缀时位置为 None
+fn parse_file_location(arg: &str) -> (&str, Option<(usize, Option<usize>)>) {
+    if arg.is_empty() || std::path::Path::new(arg).exists() {
+        return (arg, None);
+    }
+    // 从右向左取出最多两个数字后缀
+    let mut path = arg;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        let Some((rest, suffix)) = path.rsplit_once(':') else {
+            break;
+        };
+        let is_drive = rest.len() == 1 && rest.starts_with(|c: char| c.is_ascii_alphabetic());
+        match suffix.parse::<usize>() {
+            Ok(number) if !rest.is_empty() && !is_drive && suffix.bytes().all(|b| b.is_ascii_digit()) => {
+                numbers.push(number);
+                path = rest;
+            }
+            _ => break,
+        }
+    }
+    match numbers[..] {
+        [line] => (path, Some((line, None))),
+        [column, line] => (path, Some((line, Some(column)))),
+        _ => (arg, None),
+    }
+}
+
+/// 判断内容是否像二进制数据
+/// 
+/// 检查开头的 `BINARY_SNIFF_LEN` 个字节：包含 NUL 字节，
+/// 或不可打印的控制字符超过三成时视为二进制
+/// 
+/// # 参数
+/// * `bytes` - 文件内容
+fn looks_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    if sample.is_empty() {
+        return false;
+    }
+    if sample.contains(&0) {
+        return true;
+    }
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    control * 10 > sample.len() * 3
+}
+
+/// 获取字符的 Unicode 名称
+/// 
+/// 只收录 ASCII 字符以及常见的易混淆或不可见字符（如不换行空格、
+/// 智能引号、零宽字符），其余字符返回 None
+/// 
+/// # 参数
+/// * `c` - 要查询的字符
+fn unicode_name(c: char) -> Option<String> {
+    const CONTROL_NAMES: [&str; 32] = [
+        "NULL", "START OF HEADING", "START OF TEXT", "END OF TEXT", "END OF TRANSMISSION",
+        "ENQUIRY", "ACKNOWLEDGE", "BELL", "BACKSPACE", "CHARACTER TABULATION", "LINE FEED",
+        "LINE TABULATION", "FORM FEED", "CARRIAGE RETURN", "SHIFT OUT", "SHIFT IN",
+        "DATA LINK ESCAPE", "DEVICE CONTROL ONE", "DEVICE CONTROL TWO", "DEVICE CONTROL THREE",
+        "DEVICE CONTROL FOUR", "NEGATIVE ACKNOWLEDGE", "SYNCHRONOUS IDLE",
+        "END OF TRANSMISSION BLOCK", "CANCEL", "END OF MEDIUM", "SUBSTITUTE", "ESCAPE",
+        "INFORMATION SEPARATOR FOUR", "INFORMATION SEPARATOR THREE",
+        "INFORMATION SEPARATOR TWO", "INFORMATION SEPARATOR ONE",
+    ];
+    const DIGIT_NAMES: [&str; 10] = [
+        "ZERO", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE",
+    ];
+
+    let name = match c {
+        '\u{0}'..='\u{1f}' => CONTROL_NAMES[c as usize],
+        'A'..='Z' => return Some(format!("LATIN CAPITAL LETTER {}", c)),
+        'a'..='z' => return Some(format!("LATIN SMALL LETTER {}", c.to_ascii_uppercase())),
+        '0'..='9' => return Some(format!("DIGIT {}", DIGIT_NAMES[c as usize - '0' as usize])),
+        ' ' => "SPACE",
+        '!' => "EXCLAMATION MARK",
+        '"' => "QUOTATION MARK",
+        '#' => "NUMBER SIGN",
+        '$' => "DOLLAR SIGN",
+        '%' => "PERCENT SIGN",
+        '&' => "AMPERSAND",
+        '\'' => "APOSTROPHE",
+        '(' => "LEFT PARENTHESIS",
+        ')' => "RIGHT PARENTHESIS",
+        '*' => "ASTERISK",
+        '+' => "PLUS SIGN",
+        ',' => "COMMA",
+        '-' => "HYPHEN-MINUS",
+        '.' => "FULL STOP",
+        '/' => "SOLIDUS",
+        ':' => "COLON",
+        ';' => "SEMICOLON",
+        '<' => "LESS-THAN SIGN",
+        '=' => "EQUALS SIGN",
+        '>' => "GREATER-THAN SIGN",
+        '?' => "QUESTION MARK",
+        '@' => "COMMERCIAL AT",
+        '[' => "LEFT SQUARE BRACKET",
+        '\\' => "REVERSE SOLIDUS",
+        ']' => "RIGHT SQUARE BRACKET",
+        '^' => "CIRCUMFLEX ACCENT",
+        '_' => "LOW LINE",
+        '`' => "GRAVE ACCENT",
+        '{' => "LEFT CURLY BRACKET",
+        '|' => "VERTICAL LINE",
+        '}' => "RIGHT CURLY BRACKET",
+        '~' => "TILDE",
+        '\u{7f}' => "DELETE",
+        '\u{a0}' => "NO-BREAK SPACE",
+        '\u{ad}' => "SOFT HYPHEN",
+        '\u{2002}' => "EN SPACE",
+        '\u{2003}' => "EM SPACE",
+        '\u{2009}' => "THIN SPACE",
+        '\u{200b}' => "ZERO WIDTH SPACE",
+        '\u{200c}' => "ZERO WIDTH NON-JOINER",
+        '\u{200d}' => "ZERO WIDTH JOINER",
+        '\u{200e}' => "LEFT-TO-RIGHT MARK",
+        '\u{200f}' => "RIGHT-TO-LEFT MARK",
+        '\u{2010}' => "HYPHEN",
+        '\u{2011}' => "NON-BREAKING HYPHEN",
+        '\u{2013}' => "EN DASH",
+        '\u{2014}' => "EM DASH",
+        '\u{2018}' => "LEFT SINGLE QUOTATION MARK",
+        '\u{2019}' => "RIGHT SINGLE QUOTATION MARK",
+        '\u{201c}' => "LEFT DOUBLE QUOTATION MARK",
+        '\u{201d}' => "RIGHT DOUBLE QUOTATION MARK",
+        '\u{2026}' => "HORIZONTAL ELLIPSIS",
+        '\u{2028}' => "LINE SEPARATOR",
+        '\u{2029}' => "PARAGRAPH SEPARATOR",
+        '\u{202f}' => "NARROW NO-BREAK SPACE",
+        '\u{2060}' => "WORD JOINER",
+        '\u{3000}' => "IDEOGRAPHIC SPACE",
+        '\u{feff}' => "ZERO WIDTH NO-BREAK SPACE",
+        '\u{fffd}' => "REPLACEMENT CHARACTER",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// 按显示宽度排版状态栏：文件状态靠左，文件信息和位置指示靠右
+/// 
+/// 空间不足时先省略文件信息，再截断左侧，保证右侧的位置指示可见
+/// 
+/// # 参数
+/// * `status` - 左侧的文件状态
+/// * `file_info` - 位置指示之前的文件信息（文件类型、换行符风格）
+/// * `indicator` - 右侧的位置指示
+/// * `width` - 状态栏的显示宽度
+/// 
+/// # 返回值
+/// 恰好占满 `width` 列（宽字符被截断时可能略少）的状态栏文本
+fn layout_status_bar(status: &str, file_info: &str, indicator: &str, width: usize) -> String {
+    let status_width = UnicodeWidthStr::width(status);
+    let info_width = UnicodeWidthStr::width(file_info);
+    let indicator_width = UnicodeWidthStr::width(indicator);
+    let right = if status_width + info_width + indicator_width <= width {
+        format!("{}{}", file_info, indicator)
+    } else {
+        indicator.to_string()
+    };
+    let right_width = UnicodeWidthStr::width(right.as_str());
+    let mut line = truncate_to_width(status, width.saturating_sub(right_width)).to_string();
+    let len = UnicodeWidthStr::width(line.as_str()) + right_width;
+    line.push_str(&" ".repeat(width.saturating_sub(len)));
+    line.push_str(&right);
+    truncate_to_width(&line, width).to_string()
+}
+
+/// 按字素列出一行的内容，字符串、字符字面量和注释中的字素为 None
+/// 
+/// 查找匹配括号时使用，高亮按字符记录，因此按每个字素的第一个字符判断
+fn code_graphemes(row: &Row) -> Vec<Option<&str>> {
+    let mut char_index = 0;
+    row.string[..]
+        .graphemes(true)
+        .map(|g| {
+            let highlight = row.highlighting.get(char_index);
+            char_index += g.chars().count();
+            let literal = matches!(highlight, Some(HighlightType::String | HighlightType::CharLiteral | HighlightType::Comment));
+            (!literal).then_some(g)
+        })
+        .collect()
+}
+
+/// 按显示宽度截断字符串，只在字素边界处截断
+/// 
+/// 宽字符放不下时整个舍去，因此结果的显示宽度可能略小于 `width`
+/// 
+/// # 参数
+/// * `text` - 要截断的字符串
+/// * `width` - 允许的最大显示宽度（列数）
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        used += UnicodeWidthStr::width(grapheme);
+        if used > width {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// 获取配置目录
+/// 
+/// 按以下顺序确定：
+/// 1. `HECTO_CONFIG_DIR` 环境变量
+/// 2. Windows 上的 `%APPDATA%\hecto`
+/// 3. `$XDG_CONFIG_HOME/hecto`
+/// 4. `~/.config/hecto`
+/// 
+/// # 返回值
+/// 无法确定主目录时返回 None
+fn config_dir() -> Option<PathBuf> {
+    resolve_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// 获取数据目录（历史记录、最近文件、光标位置等状态文件）
+/// 
+/// 按以下顺序确定：
+/// 1. `HECTO_CONFIG_DIR` 环境变量
+/// 2. Windows 上的 `%APPDATA%\hecto`
+/// 3. `$XDG_DATA_HOME/hecto`
+/// 4. `~/.local/share/hecto`
+/// 
+/// # 返回值
+/// 无法确定主目录时返回 None
+fn data_dir() -> Option<PathBuf> {
+    resolve_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// 按环境变量解析目录，`config_dir` 和 `data_dir` 共用
+/// 
+/// # 参数
+/// * `xdg_var` - 对应的 XDG 环境变量名
+/// * `home_fallback` - 未设置 XDG 变量时相对于主目录的路径
+fn resolve_dir(xdg_var: &str, home_fallback: &str) -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    if let Some(dir) = non_empty("HECTO_CONFIG_DIR") {
+        return Some(dir);
+    }
+    if cfg!(windows) {
+        return non_empty("APPDATA").map(|dir| dir.join("hecto"));
+    }
+    if let Some(dir) = non_empty(xdg_var) {
+        return Some(dir.join("hecto"));
+    }
+    non_empty("HOME").map(|home| home.join(home_fallback).join("hecto"))
+}
+
+/// 读取光标位置记录
+/// 
+/// 记录文件每行为 `行号\t列号\t路径`（位置从 0 开始，路径为绝对路径），最近使用的在前。
+/// 文件不存在时返回空列表，格式错误的行被忽略
+fn read_positions(store: &Path) -> Vec<(PathBuf, Position)> {
+    let text = fs::read_to_string(store).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let y = fields.next()?.parse().ok()?;
+            let x = fields.next()?.parse().ok()?;
+            Some((PathBuf::from(fields.next()?), Position { x, y }))
+        })
+        .collect()
+}
+
+/// 查找文件上次记录的光标位置
+/// 
+/// # 参数
+/// * `store` - 记录文件的路径
+/// * `file` - 要查找的文件，按绝对路径比较
+fn recall_position(store: &Path, file: &Path) -> Option<Position> {
+    let file = fs::canonicalize(file).ok()?;
+    read_positions(store).into_iter().find(|(path, _)| *path == file).map(|(_, position)| position)
+}
+
+/// 记录文件的光标位置
+/// 
+/// 这个文件的记录移到最前面，超过 `POSITION_HISTORY_SIZE` 条时丢弃最久没有使用的记录
+/// 
+/// # 参数
+/// * `store` - 记录文件的路径，所在目录不存在时创建
+/// * `file` - 光标所在的文件，不存在（如尚未保存的新文件）时不记录
+/// * `position` - 光标位置
+fn remember_position(store: &Path, file: &Path, position: Position) -> io::Result<()> {
+    let file = fs::canonicalize(file)?;
+    let Some(name) = file.to_str().filter(|name| !name.contains('\n')) else {
+        return Ok(());
+    };
+    let mut positions = read_positions(store);
+    positions.retain(|(path, _)| *path != file);
+    positions.truncate(POSITION_HISTORY_SIZE - 1);
+    let mut text = format!("{}\t{}\t{}\n", position.y, position.x, name);
+    for (path, position) in &positions {
+        text.push_str(&format!("{}\t{}\t{}\n", position.y, position.x, path.display()));
+    }
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(store, text)
+}
+
+/// 读取拼写检查的词表，每行一个单词，统一转换为小写
+fn load_dictionary(path: &Path) -> io::Result<HashSet<String>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.lines().map(|word| word.trim().to_lowercase()).filter(|word| !word.is_empty()).collect())
+}
+
+/// 处理致命错误
+fn die(e: &io::Error) {
+    terminal::disable_raw_mode().unwrap();
+    eprintln!("Error: {}", e);
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROL_SHIFT: KeyModifiers = KeyModifiers::CONTROL.union(KeyModifiers::SHIFT);
+
+    fn row(text: &str) -> Row {
+        Row::new(text.to_string(), &PLAIN_SYNTAX)
+    }
+
+    /// 检查增量维护的长度与行内容一致
+    fn assert_row(row: &Row, expected: &str) {
+        assert_eq!(row.as_str(), expected);
+        assert_eq!(row.len, expected.graphemes(true).count());
+        assert_eq!(row.display_len, UnicodeWidthStr::width(expected));
+    }
+
+    #[test]
+    fn insert_ascii_cjk_and_emoji() {
+        let mut ascii = row("ac");
+        ascii.insert(1, 'b');
+        ascii.insert(3, 'd');
+        ascii.insert(10, 'e');
+        assert_row(&ascii, "abcde");
+
+        let mut cjk = row("中文");
+        cjk.insert(1, '的');
+        cjk.insert(0, 'x');
+        assert_row(&cjk, "x中的文");
+
+        let mut emoji = row("a👍🏽b");
+        emoji.insert(2, '🎉');
+        assert_row(&emoji, "a👍🏽🎉b");
+        assert_eq!(emoji.len(), 4);
+    }
+
+    #[test]
+    fn insert_combining_mark_merges_with_previous_grapheme() {
+        let mut end = row("e");
+        end.insert(1, '\u{301}');
+        assert_row(&end, "e\u{301}");
+        assert_eq!(end.len(), 1);
+
+        let mut middle = row("ex");
+        middle.insert(1, '\u{301}');
+        assert_row(&middle, "e\u{301}x");
+        assert_eq!(middle.len(), 2);
+    }
+
+    #[test]
+    fn delete_removes_whole_graphemes() {
+        let mut ascii = row("abc");
+        ascii.delete(1);
+        ascii.delete(5);
+        assert_row(&ascii, "ac");
+
+        let mut cjk = row("中文字");
+        cjk.delete(1);
+        assert_row(&cjk, "中字");
+
+        let mut emoji = row("a👍🏽e\u{301}");
+        emoji.delete(1);
+        assert_row(&emoji, "ae\u{301}");
+        emoji.delete(1);
+        assert_row(&emoji, "a");
+    }
+
+    #[test]
+    fn split_and_append_round_trip() {
+        for (text, at) in [("hello world", 5), ("中文字符", 2), ("a👍🏽b", 1), ("abc", 3), ("abc", 0)] {
+            let mut head = row(text);
+            let tail = head.split(at, &PLAIN_SYNTAX);
+            let expected_head: String = text.graphemes(true).take(at).collect();
+            let expected_tail: String = text.graphemes(true).skip(at).collect();
+            assert_row(&head, &expected_head);
+            assert_row(&tail, &expected_tail);
+
+            head.append(&tail, &PLAIN_SYNTAX);
+            assert_row(&head, text);
+        }
+    }
+
+    #[test]
+    fn append_merges_combining_mark_across_rows() {
+        let mut head = row("e");
+        head.append(&row("\u{301}x"), &PLAIN_SYNTAX);
+        assert_row(&head, "e\u{301}x");
+        assert_eq!(head.len(), 2);
+    }
+
+    #[test]
+    fn search_returns_grapheme_positions() {
+        let ascii = row("abcabc");
+        assert_eq!(ascii.search("bc", 0), Some(1));
+        assert_eq!(ascii.search("bc", 2), Some(4));
+        assert_eq!(ascii.search("bc", 5), None);
+        assert_eq!(ascii.search("a", 7), None);
+
+        let cjk = row("中文中文");
+        assert_eq!(cjk.search("文", 2), Some(3));
+
+        let emoji = row("👍🏽x👍🏽x");
+        assert_eq!(emoji.search("x", 0), Some(1));
+        assert_eq!(emoji.search("x", 2), Some(3));
+    }
+
+    #[test]
+    fn rsearch_finds_nearest_match_before_position() {
+        let ascii = row("abcabc");
+        assert_eq!(ascii.rsearch("bc", 6), Some(4));
+        assert_eq!(ascii.rsearch("bc", 4), Some(1));
+        assert_eq!(ascii.rsearch("bc", 1), None);
+
+        let mixed = row("中x👍🏽x");
+        assert_eq!(mixed.rsearch("x", 4), Some(3));
+        assert_eq!(mixed.rsearch("x", 3), Some(1));
+    }
+
+    fn editor_with_lines(count: usize) -> Editor {
+        // 不读取开发者自己的配置文件，测试结果与环境无关
+        let editor = Editor::with_config(Config::default(), None);
+        *editor.rows.write().unwrap() = (0..count).map(|i| row(&format!("line {}", i))).collect();
+        editor
+    }
+
+    #[test]
+    fn resize_keeps_cursor_on_screen() {
+        let mut editor = editor_with_lines(100);
+        editor.resize(80, 42);
+        editor.cursor_position = Position { x: 0, y: 80 };
+        editor.offset.y = 50;
+
+        editor.resize(40, 12);
+        assert_eq!(editor.screen_rows, 10);
+        assert_eq!(editor.screen_cols, 40);
+        let Position { y, .. } = editor.cursor_position;
+        assert!(editor.offset.y <= y && y < editor.offset.y + editor.screen_rows);
+        assert_eq!(editor.offset.y, 71);
+
+        // 放大后光标停在缩小时被限制到的屏幕行上
+        editor.resize(80, 200);
+        assert_eq!(editor.offset.y, 71);
+        assert_eq!(editor.offset.x, 0);
+    }
+
+    /// 光标在屏幕上的位置（显示列和屏幕行）
+    fn cursor_screen_position(editor: &Editor) -> (usize, usize) {
+        let x = editor.cursor_display_x() - editor.offset.x;
+        let y = editor.cursor_position.y - editor.offset.y;
+        (x, y)
+    }
+
+    #[test]
+    fn resize_preserves_cursor_screen_position_near_each_edge() {
+        // 每个用例：光标位置、初始偏移、缩小到 40x10 后光标在屏幕上的位置
+        let cases = [
+            // 左上角，缩小后仍然放得下
+            (Position { x: 100, y: 30 }, Position { x: 100, y: 30 }, (0, 0)),
+            // 屏幕中间，缩小后仍然放得下
+            (Position { x: 120, y: 35 }, Position { x: 100, y: 30 }, (20, 5)),
+            // 最后一个屏幕行，限制到新的最后一行
+            (Position { x: 100, y: 69 }, Position { x: 100, y: 30 }, (0, 9)),
+            // 长行上的最后一列，限制到新的最后一列和最后一行
+            (Position { x: 179, y: 50 }, Position { x: 100, y: 30 }, (39, 9)),
+        ];
+        for (cursor, offset, shrunk) in cases {
+            let mut editor = editor_with_lines(100);
+            for y in [30, 35, 50, 69] {
+                editor.rows.write().unwrap()[y] = row(&"x".repeat(200));
+            }
+            editor.resize(80, 42);
+            editor.cursor_position = cursor;
+            editor.offset = offset;
+
+            editor.resize(40, 12);
+            assert_eq!((editor.screen_cols, editor.screen_rows), (40, 10));
+            assert_eq!(cursor_screen_position(&editor), shrunk, "shrinking with the cursor at {:?}", cursor);
+
+            // 放大时光标仍然放得下，停在原来的屏幕位置
+            editor.resize(120, 62);
+            assert_eq!(cursor_screen_position(&editor), shrunk, "growing with the cursor at {:?}", cursor);
+            assert_eq!(editor.cursor_position, cursor);
+        }
+    }
+
+    #[test]
+    fn resize_clamps_each_dimension_separately() {
+        let mut editor = editor_with_lines(100);
+        editor.rows.write().unwrap()[69] = row(&"x".repeat(200));
+        editor.resize(80, 42);
+        editor.cursor_position = Position { x: 179, y: 69 };
+        editor.offset = Position { x: 100, y: 30 };
+        assert_eq!(cursor_screen_position(&editor), (79, 39));
+
+        // 只缩小列数时行不受影响
+        editor.resize(40, 42);
+        assert_eq!(cursor_screen_position(&editor), (39, 39));
+        // 只缩小行数时列不受影响
+        editor.resize(40, 12);
+        assert_eq!(cursor_screen_position(&editor), (39, 9));
+        editor.resize(80, 42);
+        assert_eq!(cursor_screen_position(&editor), (39, 9));
+    }
+
+    /// 测试用的临时目录，离开作用域时删除，断言失败时也不会留下文件
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// 创建以 `hecto-<name>-<进程号>` 命名的目录
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("hecto-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// 读写目录中文件的编辑器，不记住光标位置，以免写入开发者自己的状态目录
+        fn editor(&self, lines: usize) -> Editor {
+            let mut editor = editor_with_lines(lines);
+            editor.remember_positions = false;
+            editor
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn lines(editor: &Editor) -> Vec<String> {
+        editor.rows.read().unwrap().iter().map(|row| row.as_str().to_string()).collect()
+    }
+
+    #[test]
+    fn insert_text_splits_lines_around_cursor() {
+        let mut editor = editor_with_lines(2);
+        editor.cursor_position = Position { x: 2, y: 0 };
+        editor.insert_text("AB\n中文\nC");
+        assert_eq!(lines(&editor), ["liAB", "中文", "Cne 0", "line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 2 });
+
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn insert_text_after_last_line() {
+        let mut editor = editor_with_lines(1);
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.insert_text("a\nb\n");
+        assert_eq!(lines(&editor), ["line 0", "a", "b", ""]);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 3 });
+    }
+
+    #[test]
+    fn insert_text_pastes_large_block_at_once() {
+        let mut editor = editor_with_lines(1);
+        let text: Vec<String> = (0..1000).map(|i| format!("fn f{}() {{ /* {} */ }}", i, i)).collect();
+        editor.insert_text(&text.join("\n"));
+        let rows = lines(&editor);
+        assert_eq!(rows.len(), 1000);
+        assert_eq!(rows[0], text[0]);
+        assert_eq!(rows[999], format!("{}line 0", text[999]));
+        assert_eq!(editor.cursor_position, Position { x: text[999].len(), y: 999 });
+    }
+
+    fn highlighting(editor: &Editor, y: usize) -> Vec<HighlightType> {
+        editor.rows.read().unwrap()[y].highlighting.clone()
+    }
+
+    #[test]
+    fn async_highlighting_applies_to_unchanged_document() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = ["/* a", "b */ x"].iter().map(|line| row(line)).collect();
+        editor.syntax = &RUST_SYNTAX;
+        editor.update_syntax_async();
+        editor.finish_syntax_job();
+        assert!(editor.syntax_job.is_none());
+        assert_eq!(highlighting(&editor, 0), vec![HighlightType::Comment; 4]);
+        assert_eq!(highlighting(&editor, 1)[..4], [HighlightType::Comment; 4]);
+        assert_eq!(highlighting(&editor, 1)[5], HighlightType::Normal);
+    }
+
+    #[test]
+    fn async_highlighting_restarts_after_edit() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = ["/* a", "b */"].iter().map(|line| row(line)).collect();
+        editor.syntax = &RUST_SYNTAX;
+        editor.update_syntax_async();
+        editor.cursor_position = Position { x: 4, y: 1 };
+        editor.insert_char('y');
+        editor.finish_syntax_job();
+        assert_eq!(lines(&editor), ["/* a", "b */y"]);
+        assert_eq!(highlighting(&editor, 1)[..4], [HighlightType::Comment; 4]);
+    }
+
+    fn rust_editor(text: &[&str]) -> Editor {
+        let mut editor = editor_with_lines(0);
+        editor.syntax = &RUST_SYNTAX;
+        *editor.rows.write().unwrap() = text.iter().map(|line| Row::new(line.to_string(), &RUST_SYNTAX)).collect();
+        let total_rows = text.len();
+        update_syntax_from(&mut editor.rows.write().unwrap(), 0, total_rows, &RUST_SYNTAX);
+        editor
+    }
+
+    #[test]
+    fn matching_bracket_skips_strings_and_comments() {
+        let mut editor = rust_editor(&["f(\"(\", ')', x) // )", "{ /* } */", "}"]);
+        editor.cursor_position = Position { x: 1, y: 0 };
+        assert_eq!(editor.find_matching_bracket(), Some((Position { x: 1, y: 0 }, Position { x: 13, y: 0 })));
+        editor.cursor_position = Position { x: 1, y: 2 };
+        assert_eq!(editor.find_matching_bracket(), Some((Position { x: 0, y: 2 }, Position { x: 0, y: 1 })));
+        // 字符串中的括号本身没有匹配
+        editor.cursor_position = Position { x: 3, y: 0 };
+        assert_eq!(editor.find_matching_bracket(), None);
+    }
+
+    #[test]
+    fn matching_bracket_scan_is_limited_and_cached() {
+        let mut text = vec!["("];
+        text.extend(std::iter::repeat_n("x", BRACKET_SCAN_LINES - 1));
+        text.push(")");
+        // 包括光标所在行在内最多扫描 `BRACKET_SCAN_LINES` 行
+        let mut editor = rust_editor(&text);
+        assert_eq!(editor.find_matching_bracket(), None);
+        editor.rows.write().unwrap().remove(1);
+        assert_eq!(editor.find_matching_bracket().map(|(_, end)| end), Some(Position { x: 0, y: BRACKET_SCAN_LINES - 1 }));
+        editor.cursor_position = Position { x: 1, y: BRACKET_SCAN_LINES - 1 };
+        assert_eq!(editor.find_matching_bracket().map(|(_, end)| end), Some(Position { x: 0, y: 0 }));
+        editor.cursor_position = Position { x: 0, y: 0 };
+
+        // 版本号和光标都没变时沿用上次的结果
+        editor.update_matching_bracket();
+        assert!(editor.matching_bracket.is_some());
+        editor.rows.write().unwrap()[0] = row("x");
+        editor.update_matching_bracket();
+        assert!(editor.matching_bracket.is_some());
+        editor.mark_dirty();
+        editor.update_matching_bracket();
+        assert_eq!(editor.matching_bracket, None);
+    }
+
+    #[test]
+    fn joining_comment_lines_keeps_comment_highlighting() {
+        let mut editor = rust_editor(&["// a", "// b"]);
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.delete_char();
+        assert_eq!(lines(&editor), ["// a// b"]);
+        assert_eq!(highlighting(&editor, 0), vec![HighlightType::Comment; 8]);
+    }
+
+    #[test]
+    fn joining_lines_updates_block_comment_state_below() {
+        // 合并后 "*" 和 "/" 组成注释结束标记，之后的行不再在注释中
+        let mut editor = rust_editor(&["/* a *", "/ b", "c"]);
+        assert_eq!(highlighting(&editor, 2), [HighlightType::Comment]);
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.delete_char();
+        assert_eq!(lines(&editor), ["/* a */ b", "c"]);
+        assert_eq!(highlighting(&editor, 0)[..7], [HighlightType::Comment; 7]);
+        assert_eq!(highlighting(&editor, 0)[8], HighlightType::Normal);
+        assert_eq!(highlighting(&editor, 1), [HighlightType::Normal]);
+    }
+
+    #[test]
+    fn dirty_buffer_quits_on_third_ctrl_q() {
+        let mut editor = editor_with_lines(1);
+        editor.mark_dirty();
+        editor.request_quit();
+        assert!(editor.status_message.text.contains("Press Ctrl-Q 2 more times"));
+        editor.request_quit();
+        assert!(editor.status_message.text.contains("Press Ctrl-Q 1 more time "));
+        assert!(!editor.should_quit);
+        editor.request_quit();
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn clean_buffer_quits_immediately() {
+        let mut editor = editor_with_lines(1);
+        editor.request_quit();
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn status_bar_drops_file_info_before_truncating() {
+        let wide = layout_status_bar("main.rs - 3 lines", "rust | LF | ", "1:1/3 All", 40);
+        assert_eq!(wide, format!("main.rs - 3 lines{}rust | LF | 1:1/3 All", " ".repeat(2)));
+
+        let narrow = layout_status_bar("main.rs - 3 lines", "rust | LF | ", "1:1/3 All", 30);
+        assert_eq!(narrow, format!("main.rs - 3 lines{}1:1/3 All", " ".repeat(4)));
+
+        let tiny = layout_status_bar("中文.txt - 3 lines", "text | CRLF | ", "1:1/3 All", 12);
+        assert_eq!(tiny, "中 1:1/3 All");
+        assert_eq!(UnicodeWidthStr::width(tiny.as_str()), 12);
+    }
+
+    #[test]
+    fn status_bar_truncates_chinese_file_name_in_search_mode() {
+        assert_eq!(truncate_to_width("测试文件.rs", 20), "测试文件.rs");
+        // 放不下的宽字符整个舍去
+        assert_eq!(truncate_to_width("测试文件.rs", 5), "测试");
+        let long = truncate_to_width("测试目录/测试文件测试文件.rs", 20);
+        assert_eq!(long, "测试目录/测试文件测");
+        assert!(UnicodeWidthStr::width(long) <= 20);
+
+        let name = truncate_to_width("测试文件.rs", 20);
+        let status = format!("{} - 3 lines (modified) | SEARCH MODE match 1 of 2", name);
+        for width in 0..=80 {
+            let line = layout_status_bar(&status, "rust | LF | ", "1:1/3 All", width);
+            assert!(UnicodeWidthStr::width(line.as_str()) <= width, "width {}: {:?}", width, line);
+        }
+        // 截断处的宽字符放不下时用空格补齐，位置指示保持右对齐
+        let narrow = layout_status_bar(&status, "rust | LF | ", "1:1/3 All", 14);
+        assert_eq!(narrow, "测试 1:1/3 All");
+    }
+
+    #[test]
+    fn kill_line_deletes_to_end_then_joins() {
+        let mut editor = editor_with_lines(2);
+        editor.cursor_position = Position { x: 4, y: 0 };
+        editor.kill_line();
+        assert_eq!(lines(&editor), ["line", "line 1"]);
+        editor.kill_line();
+        assert_eq!(lines(&editor), ["lineline 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 0 });
+
+        editor.cursor_position = Position { x: 10, y: 0 };
+        editor.kill_line();
+        assert_eq!(lines(&editor), ["lineline 1"]);
+
+        editor.undo();
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+    }
+
+    #[test]
+    fn kill_line_handles_wide_graphemes() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("中文👍🏽e\u{301}")];
+        editor.cursor_position = Position { x: 1, y: 0 };
+        editor.kill_line();
+        let rows = editor.rows.read().unwrap();
+        assert_row(&rows[0], "中");
+    }
+
+    #[test]
+    fn delete_lines_at_end_of_document() {
+        let mut editor = editor_with_lines(3);
+        editor.cursor_position = Position { x: 3, y: 2 };
+        editor.delete_lines(1);
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 1 });
+
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.delete_lines(5);
+        assert!(lines(&editor).is_empty());
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn consecutive_kills_are_yanked_together() {
+        let mut editor = editor_with_lines(3);
+        editor.kill_line();
+        editor.kill_line();
+        editor.kill_line();
+        assert_eq!(lines(&editor), ["", "line 2"]);
+        assert_eq!(editor.kill_ring, ["line 0\nline 1"]);
+
+        editor.yank();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2"]);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 1 });
+    }
+
+    #[test]
+    fn backward_word_kills_prepend_and_moves_start_new_entry() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("one two three")];
+        editor.cursor_position = Position { x: 13, y: 0 };
+        editor.delete_word(false);
+        editor.delete_word(false);
+        assert_eq!(editor.kill_ring, ["two three"]);
+
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.delete_word(true);
+        assert_eq!(editor.kill_ring, ["one", "two three"]);
+        assert_eq!(lines(&editor), [" "]);
+    }
+
+    #[test]
+    fn deleted_lines_keep_their_newlines_and_ring_is_bounded() {
+        let mut editor = editor_with_lines(KILL_RING_SIZE + 2);
+        editor.delete_lines(2);
+        assert_eq!(editor.kill_ring, ["line 0\nline 1\n"]);
+        editor.yank();
+        assert_eq!(lines(&editor)[..3], ["line 0", "line 1", "line 2"]);
+
+        for _ in 0..KILL_RING_SIZE + 1 {
+            editor.kill_line();
+            editor.cursor_position.y += 1;
+        }
+        assert_eq!(editor.kill_ring.len(), KILL_RING_SIZE);
+    }
+
+    /// 去掉渲染结果中的 ANSI 控制序列
+    fn strip_ansi(rendered: &str) -> String {
+        let mut text = String::new();
+        let mut chars = rendered.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn indent_guides_mark_each_level_in_leading_whitespace() {
+        let mut editor = editor_with_lines(0);
+        editor.tab_width = 4;
+        editor.show_indent_guides = true;
+        let render = |editor: &Editor, text: &str| strip_ansi(&editor.render_row_uncached(&row(text), 0, usize::MAX, 80, None, false));
+        assert_eq!(render(&editor, "        x  y"), "│   │   x  y");
+        assert_eq!(render(&editor, "\t  \tx"), "│   │   x");
+        assert_eq!(render(&editor, "  x"), "│ x");
+        assert_eq!(render(&editor, "x   y"), "x   y");
+
+        editor.show_whitespace = true;
+        assert_eq!(render(&editor, "\tx"), "→   x¬");
+
+        editor.show_indent_guides = false;
+        editor.show_whitespace = false;
+        assert_eq!(render(&editor, "        x"), "        x");
+    }
+
+    /// 按 `draw_rows` 的方式经过缓存渲染每一行，并检查结果与不经过缓存时一致
+    ///
+    /// 返回渲染前缓存中已有当前结果的行号
+    fn render_all_rows(editor: &mut Editor) -> Vec<usize> {
+        let saved_y = editor.cursor_position.y;
+        let rows = editor.rows.read().unwrap();
+        let mut cached = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            editor.cursor_position.y = y;
+            let selected = editor.selection_span(&rows, y);
+            let cursor_at_end = y == saved_y && editor.cursor_position.x >= row.len;
+            let key = editor.render_key(0, row.len, 80, selected, cursor_at_end);
+            if row.render_cache.lock().unwrap().iter().any(|(cached_key, _)| *cached_key == key) {
+                cached.push(y);
+            }
+            let rendered = editor.render_row(row, 0, row.len, 80, selected, cursor_at_end);
+            assert_eq!(rendered, editor.render_row_uncached(row, 0, row.len, 80, selected, cursor_at_end));
+        }
+        drop(rows);
+        editor.cursor_position.y = saved_y;
+        cached
+    }
+
+    #[test]
+    fn typing_only_re_renders_the_edited_row() {
+        let mut editor = editor_with_lines(50);
+        editor.cursor_position = Position { x: 6, y: 10 };
+        assert!(render_all_rows(&mut editor).is_empty());
+        assert_eq!(render_all_rows(&mut editor), (0..50).collect::<Vec<_>>());
+
+        editor.insert_char('x');
+        assert!(editor.rows.read().unwrap()[10].render_cache.lock().unwrap().is_empty());
+        let untouched: Vec<usize> = (0..50).filter(|&y| y != 10).collect();
+        assert_eq!(render_all_rows(&mut editor), untouched);
+
+        // 选择只使落在选择范围内的行重新渲染
+        let mut selection = Selection::new(Position { x: 2, y: 20 });
+        selection.end = Position { x: 3, y: 22 };
+        editor.selection = Some(selection);
+        let outside: Vec<usize> = (0..50).filter(|y| !(20..=22).contains(y)).collect();
+        assert_eq!(render_all_rows(&mut editor), outside);
+        editor.selection = None;
+        assert_eq!(render_all_rows(&mut editor), outside);
+
+        // 搜索文本改变时每一行都可能有新的匹配
+        editor.current_search = Some("line 3".to_string());
+        assert!(render_all_rows(&mut editor).is_empty());
+        assert_eq!(render_all_rows(&mut editor).len(), 50);
+        editor.current_search = None;
+        assert!(render_all_rows(&mut editor).is_empty());
+    }
+
+    fn select_lines(editor: &mut Editor, first: usize, last: usize) {
+        let start = Position { x: 0, y: first };
+        let mut selection = Selection::new(start);
+        selection.end = Position { x: 1, y: last };
+        editor.selection = Some(selection);
+    }
+
+    #[test]
+    fn folded_lines_are_skipped_as_a_unit() {
+        let mut editor = editor_with_lines(6);
+        select_lines(&mut editor, 1, 3);
+        editor.fold();
+        assert_eq!(editor.rows.read().unwrap()[1].folded, Some(2));
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 1 });
+
+        editor.move_cursor(KeyCode::Down);
+        assert_eq!(editor.cursor_position.y, 4);
+        editor.move_cursor(KeyCode::Up);
+        assert_eq!(editor.cursor_position.y, 1);
+        editor.move_cursor(KeyCode::End);
+        editor.move_cursor(KeyCode::Right);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 4 });
+
+        let rows = editor.rows.read().unwrap();
+        assert_eq!(editor.visual_rows_between(&rows, 0, Position { x: 0, y: 4 }, 80), 3);
+        drop(rows);
+        editor.offset = Position::default();
+        assert_eq!(editor.screen_to_position(0, 2), Some(Position { x: 0, y: 4 }));
+
+        editor.cursor_position.y = 1;
+        editor.toggle_fold();
+        assert_eq!(editor.rows.read().unwrap()[1].folded, None);
+        editor.move_cursor(KeyCode::Down);
+        assert_eq!(editor.cursor_position.y, 2);
+    }
+
+    #[test]
+    fn fold_uses_indentation_block_and_nests() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = ["fn f() {", "    if x {", "        y", "    }", "", "    z", "}"]
+            .iter()
+            .map(|line| row(line))
+            .collect();
+        assert_eq!(editor.indent_block(0), Some((0, 5)));
+        assert_eq!(editor.indent_block(2), Some((1, 2)));
+        assert_eq!(editor.indent_block(6), None);
+
+        editor.cursor_position = Position { x: 0, y: 2 };
+        editor.fold();
+        assert_eq!(editor.rows.read().unwrap()[1].folded, Some(1));
+        select_lines(&mut editor, 0, 1);
+        editor.fold();
+        assert_eq!(editor.rows.read().unwrap()[0].folded, Some(2));
+
+        // 跳到隐藏的行时展开覆盖它的所有折叠
+        editor.cursor_position = Position { x: 0, y: 2 };
+        editor.scroll();
+        let rows = editor.rows.read().unwrap();
+        assert_eq!((rows[0].folded, rows[1].folded), (None, None));
+    }
+
+    #[test]
+    fn trailing_whitespace_is_highlighted_unless_cursor_is_at_line_end() {
+        let mut editor = editor_with_lines(0);
+        let render = |editor: &Editor, text: &str, cursor_at_end: bool| {
+            editor.render_row_uncached(&row(text), 0, usize::MAX, 80, None, cursor_at_end)
+        };
+        assert!(!render(&editor, "x  ", false).contains("\x1b[41m"));
+
+        editor.highlight_trailing_whitespace = true;
+        let rendered = render(&editor, "a b \t", false);
+        assert!(rendered.contains("b\x1b[41m "), "{:?}", rendered);
+        assert_eq!(rendered.matches("\x1b[41m").count(), 1);
+        assert_eq!(strip_ansi(&rendered), "a b     ");
+        assert!(!render(&editor, "a b", false).contains("\x1b[41m"));
+        assert!(!render(&editor, "a b ", true).contains("\x1b[41m"));
+
+        // 搜索匹配覆盖的行尾空白显示为搜索高亮
+        editor.current_search = Some(" ".to_string());
+        let rendered = render(&editor, "a  ", false);
+        assert!(!rendered.contains("\x1b[41m"), "{:?}", rendered);
+    }
+
+    #[test]
+    fn get_word_at_requires_boundaries_on_both_sides() {
+        let word_at = |text: &str, start: usize| {
+            let chars: Vec<char> = text.chars().collect();
+            row("").get_word_at(start, &chars)
+        };
+        assert_eq!(word_at("if x", 0).as_deref(), Some("if"));
+        assert_eq!(word_at("(x_1)", 1).as_deref(), Some("x_1"));
+        assert_eq!(word_at("for", 0).as_deref(), Some("for"));
+        assert_eq!(word_at("_if", 1), None);
+        assert_eq!(word_at("a1", 1), None);
+        assert_eq!(word_at("1a", 0), None);
+        assert_eq!(word_at(" x", 0), None);
+        assert_eq!(word_at("x", 1), None);
+    }
+
+    #[test]
+    fn parse_key_accepts_modifiers_and_named_keys() {
+        assert_eq!(parse_key("ctrl-h"), Some((KeyCode::Char('h'), KeyModifiers::CONTROL)));
+        assert_eq!(parse_key("Ctrl-Shift-K"), Some((KeyCode::Char('k'), CONTROL_SHIFT)));
+        assert_eq!(parse_key("alt-up"), Some((KeyCode::Up, KeyModifiers::ALT)));
+        assert_eq!(parse_key("shift-f3"), Some((KeyCode::F(3), KeyModifiers::SHIFT)));
+        assert_eq!(parse_key("ctrl--"), Some((KeyCode::Char('-'), KeyModifiers::CONTROL)));
+        assert_eq!(parse_key("pagedown"), Some((KeyCode::PageDown, KeyModifiers::NONE)));
+        assert_eq!(parse_key("ctrl-f13"), None);
+        assert_eq!(parse_key("meta-x"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn config_bindings_override_the_default_keymap() {
+        let config = Config::parse(
+            "bind.ctrl-h = \"replace\"\nbind.alt-r = \"replace\"\nbind.\"ctrl-k\" = \"none\"\n",
+        )
+        .unwrap();
+        let action = |name: &str| config.keymap.get(&parse_key(name).unwrap()).copied();
+        assert_eq!(action("ctrl-h"), Some(Action::Replace));
+        assert_eq!(action("alt-r"), Some(Action::Replace));
+        assert_eq!(action("ctrl-k"), None);
+        assert_eq!(action("ctrl-alt-e"), Some(Action::ReplaceAll));
+        assert_eq!(Config::default().keymap.get(&parse_key("ctrl-h").unwrap()), Some(&Action::DeleteBackward));
+
+        assert!(Config::parse("bind.ctrl-h = \"fly\"").err().is_some_and(|e| e.contains("未知的命令 fly")));
+        assert!(Config::parse("bind.hyper-h = \"undo\"").err().is_some_and(|e| e.contains("无法识别的按键")));
+        assert!(Config::parse("bind.ctrl-h = undo").is_err());
+    }
+
+    #[test]
+    fn every_action_has_a_name_and_defaults_are_unique() {
+        let mut seen = Vec::new();
+        for &(code, modifiers, _) in DEFAULT_KEYMAP.iter() {
+            assert!(!seen.contains(&(code, modifiers)), "{:?} {:?} 重复绑定", code, modifiers);
+            seen.push((code, modifiers));
+        }
+        for &(_, _, action) in DEFAULT_KEYMAP.iter() {
+            assert!(ACTION_NAMES.iter().any(|&(_, named)| named == action), "{:?} 没有名称", action);
+        }
+    }
+
+    #[test]
+    fn backspace_ctrl_h_and_delete_remove_characters() {
+        let mut editor = editor_with_lines(1);
+        editor.cursor_position = Position { x: 6, y: 0 };
+        // 发送 0x7F 的终端
+        editor.process_key(KeyCode::Backspace, KeyModifiers::NONE).unwrap();
+        assert_eq!(lines(&editor), ["line "]);
+        // 发送 0x08 的终端，crossterm 报告为 Ctrl-H
+        editor.process_key(KeyCode::Char('h'), KeyModifiers::CONTROL).unwrap();
+        assert_eq!(lines(&editor), ["line"]);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 0 });
+
+        // Delete 删除光标处的字符，在行尾时与下一行合并
+        let mut editor = editor_with_lines(2);
+        editor.process_key(KeyCode::Delete, KeyModifiers::NONE).unwrap();
+        assert_eq!(lines(&editor), ["ine 0", "line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 0 });
+        editor.cursor_position.x = 5;
+        editor.process_key(KeyCode::Delete, KeyModifiers::NONE).unwrap();
+        assert_eq!(lines(&editor), ["ine 0line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 5, y: 0 });
+        editor.move_cursor(KeyCode::End);
+        editor.process_key(KeyCode::Delete, KeyModifiers::NONE).unwrap();
+        assert_eq!(lines(&editor), ["ine 0line 1"]);
+        editor.undo();
+        assert_eq!(lines(&editor), ["ine 0", "line 1"]);
+        assert_eq!(Config::default().keymap.get(&parse_key("delete").unwrap()), Some(&Action::DeleteForward));
+        assert_eq!(editor.keymap.get(&(KeyCode::Char('e'), KeyModifiers::CONTROL)), Some(&Action::Replace));
+    }
+
+    #[test]
+    fn keys_dispatch_through_the_keymap() {
+        let mut editor = editor_with_lines(3);
+        editor.cursor_position = Position { x: 2, y: 0 };
+        editor.keymap.insert((KeyCode::Char('j'), KeyModifiers::ALT), Action::DeleteBackward);
+        editor.process_key(KeyCode::Char('j'), KeyModifiers::ALT).unwrap();
+        assert_eq!(lines(&editor), ["lne 0", "line 1", "line 2"]);
+
+        // 终端报告的大写字母与小写的绑定一致
+        editor.keymap.insert((KeyCode::Char('k'), CONTROL_SHIFT), Action::DeleteLines);
+        editor.process_key(KeyCode::Char('K'), CONTROL_SHIFT).unwrap();
+        assert_eq!(lines(&editor), ["line 1", "line 2"]);
+
+        editor.keymap.remove(&(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        editor.process_key(KeyCode::Char('z'), KeyModifiers::CONTROL).unwrap();
+        assert_eq!(lines(&editor), ["line 1", "line 2"]);
+    }
+
+    #[test]
+    fn parse_file_location_splits_line_and_column() {
+        assert_eq!(parse_file_location("src/nothing.rs:120"), ("src/nothing.rs", Some((120, None))));
+        assert_eq!(parse_file_location("src/nothing.rs:120:5"), ("src/nothing.rs", Some((120, Some(5)))));
+        assert_eq!(parse_file_location("a:b:1:2:3"), ("a:b:1", Some((2, Some(3)))));
+        assert_eq!(parse_file_location("notes.txt"), ("notes.txt", None));
+        assert_eq!(parse_file_location("notes.txt:"), ("notes.txt:", None));
+        assert_eq!(parse_file_location("notes.txt:+3"), ("notes.txt:+3", None));
+        assert_eq!(parse_file_location(":12"), (":12", None));
+        // Windows 盘符不是行号
+        assert_eq!(parse_file_location("C:\\src\\main.rs"), ("C:\\src\\main.rs", None));
+        assert_eq!(parse_file_location("C:\\src\\main.rs:7"), ("C:\\src\\main.rs", Some((7, None))));
+        assert_eq!(parse_file_location("C:12"), ("C:12", None));
+        // 已存在的路径原样打开
+        assert_eq!(parse_file_location("Cargo.toml"), ("Cargo.toml", None));
+    }
+
+    #[test]
+    fn jump_to_clamps_line_and_column() {
+        let mut editor = editor_with_lines(100);
+        editor.jump_to(50, Some(3));
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 49 });
+        assert!(editor.offset.y <= 49 && 49 < editor.offset.y + editor.screen_rows);
+        editor.jump_to(500, Some(99));
+        assert_eq!(editor.cursor_position, Position { x: 7, y: 99 });
+        editor.jump_to(0, Some(0));
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 0 });
+        editor.cursor_position.x = 4;
+        editor.jump_to(10, None);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 9 });
+    }
+
+    #[test]
+    fn positions_are_remembered_most_recent_first() {
+        let dir = TempDir::new("positions");
+        let store = dir.join("state").join("positions");
+        let files: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("file{}.txt", i))).collect();
+        for file in &files {
+            fs::write(file, "text\n").unwrap();
+        }
+
+        assert_eq!(recall_position(&store, &files[0]), None);
+        remember_position(&store, &files[0], Position { x: 1, y: 2 }).unwrap();
+        remember_position(&store, &files[1], Position { x: 3, y: 4 }).unwrap();
+        remember_position(&store, &files[0], Position { x: 5, y: 6 }).unwrap();
+        assert_eq!(recall_position(&store, &files[0]), Some(Position { x: 5, y: 6 }));
+        assert_eq!(recall_position(&store, &files[1]), Some(Position { x: 3, y: 4 }));
+        assert_eq!(recall_position(&store, &files[2]), None);
+        // 相对路径和绝对路径指向同一个文件
+        let relative = dir.join(".").join("file1.txt");
+        assert_eq!(recall_position(&store, &relative), Some(Position { x: 3, y: 4 }));
+
+        let paths: Vec<PathBuf> = read_positions(&store).into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, [fs::canonicalize(&files[0]).unwrap(), fs::canonicalize(&files[1]).unwrap()]);
+
+        // 不存在的文件不记录
+        assert!(remember_position(&store, &dir.join("missing"), Position::default()).is_err());
+        assert_eq!(read_positions(&store).len(), 2);
+    }
+
+    #[test]
+    fn position_history_is_bounded() {
+        let dir = TempDir::new("position-limit");
+        let store = dir.join("positions");
+        let file = dir.join("file.txt");
+        fs::write(&file, "").unwrap();
+        let old: String = (0..POSITION_HISTORY_SIZE + 10).map(|i| format!("{}\t0\t/nonexistent/{}\n", i, i)).collect();
+        fs::write(&store, old + "garbage line\n").unwrap();
+
+        remember_position(&store, &file, Position { x: 0, y: 7 }).unwrap();
+        let positions = read_positions(&store);
+        assert_eq!(positions.len(), POSITION_HISTORY_SIZE);
+        assert_eq!(positions[0], (fs::canonicalize(&file).unwrap(), Position { x: 0, y: 7 }));
+        assert_eq!(positions[1].0, PathBuf::from("/nonexistent/0"));
+    }
+
+    #[test]
+    fn spell_check_underlines_unknown_words_in_comments_and_strings() {
+        let mut editor = rust_editor(&["let helo = \"wrld peace\"; // helo world", "/* it's fine, isn't it */"]);
+        editor.dictionary = Some(["world", "peace", "it's", "fine", "it", "let"].iter().map(|w| w.to_string()).collect());
+        let misspelled = |editor: &Editor, y: usize| misspelled_in(editor, &editor.rows.read().unwrap()[y]);
+        assert!(misspelled(&editor, 0).is_empty());
+
+        editor.spell_check = true;
+        // 代码中的标识符 `helo` 不检查
+        assert_eq!(misspelled(&editor, 0), ["wrld", "helo"]);
+        assert_eq!(misspelled(&editor, 1), ["isn't"]);
+
+        let rows = editor.rows.read().unwrap();
+        let rendered = editor.render_row_uncached(&rows[0], 0, usize::MAX, 80, None, false);
+        assert!(rendered.contains("\x1b[4mwrld\x1b[24m"), "{:?}", rendered);
+        drop(rows);
+
+        // 纯文本文件检查全部内容
+        editor.syntax = &PLAIN_SYNTAX;
+        assert_eq!(misspelled_in(&editor, &row("Helo World, I think")), ["Helo", "think"]);
+    }
+
+    /// 一行中被拼写检查标出的单词
+    fn misspelled_in(editor: &Editor, row: &Row) -> Vec<String> {
+        let graphemes: Vec<&str> = row.string.graphemes(true).collect();
+        editor.misspelled_words(row).iter().map(|&(start, end)| graphemes[start..end].concat()).collect()
+    }
+
+    #[test]
+    fn first_save_backs_up_the_original_file() {
+        let dir = TempDir::new("backup");
+        let file = dir.join("notes.txt");
+        let name = file.to_str().unwrap().to_string();
+        let backup = dir.join("notes.txt.bak");
+        fs::write(&file, "original\n").unwrap();
+
+        let mut editor = dir.editor(1);
+        editor.filename = Some(name.clone());
+        editor.save(true).unwrap();
+        assert!(!backup.exists());
+
+        editor.backup_suffix = Some(".bak".to_string());
+        *editor.rows.write().unwrap() = vec![row("first edit")];
+        editor.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "line 0\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "first edit\n");
+
+        // 之后的保存不覆盖备份
+        *editor.rows.write().unwrap() = vec![row("second edit")];
+        editor.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "line 0\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "second edit\n");
+
+        let config = Config::parse("backup = true\nbackup_suffix = \"~\"").unwrap();
+        assert!(config.backup);
+        assert_eq!(config.backup_suffix, "~");
+        assert!(Config::parse("backup_suffix = \"\"").is_err());
+        assert!(Config::parse("backup_suffix = \"/x\"").is_err());
+    }
+
+    #[test]
+    fn selected_newlines_show_a_highlighted_cell() {
+        // 选中两个空行：从第 1 行行首到第 3 行行首，两个空行各显示一格反转的换行符，
+        // 选择结束的第 3 行没有被选中的字符
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("x"), row(""), row(""), row("y")];
+        let mut selection = Selection::new(Position { x: 0, y: 1 });
+        selection.end = Position { x: 0, y: 3 };
+        editor.selection = Some(selection);
+        let rendered: Vec<String> = {
+            let rows = editor.rows.read().unwrap();
+            (0..4)
+                .map(|y| editor.render_row_uncached(&rows[y], 0, usize::MAX, 80, editor.selection_span(&rows, y), false))
+                .collect()
+        };
+        assert!(!rendered[0].contains("\x1b[7m"));
+        assert!(rendered[1].contains("\x1b[7m "), "{:?}", rendered[1]);
+        assert!(rendered[2].contains("\x1b[7m "), "{:?}", rendered[2]);
+        assert!(!rendered[3].contains("\x1b[7m"));
+        assert_eq!(strip_ansi(&rendered[1]), " ");
+
+        // 选择延续到下一行时，行尾之后的换行符也显示为选中
+        let rendered = editor.render_row_uncached(&row("ab"), 0, usize::MAX, 80, Some((1, usize::MAX)), false);
+        assert!(rendered.ends_with("\x1b[0m\x1b[7m \x1b[0m"), "{:?}", rendered);
+        assert_eq!(strip_ansi(&rendered), "ab ");
+        let rendered = editor.render_row_uncached(&row("ab"), 0, usize::MAX, 80, Some((0, 1)), false);
+        assert_eq!(strip_ansi(&rendered), "ab");
+
+        // 显示空白字符时行末标记本身反转显示
+        editor.show_whitespace = true;
+        let rendered = editor.render_row_uncached(&row(""), 0, usize::MAX, 80, Some((0, usize::MAX)), false);
+        assert!(rendered.contains("\x1b[7m\x1b[2m¬"), "{:?}", rendered);
+    }
+
+    #[test]
+    fn virtual_space_moves_past_line_end_and_pads_when_typing() {
+        let mut editor = editor_with_lines(2);
+        editor.cursor_position = Position { x: 6, y: 0 };
+        editor.move_cursor(KeyCode::Right);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 1 });
+
+        editor.toggle_virtual_space();
+        editor.cursor_position = Position { x: 6, y: 0 };
+        for _ in 0..3 {
+            editor.move_cursor(KeyCode::Right);
+        }
+        assert_eq!(editor.cursor_position, Position { x: 9, y: 0 });
+        assert_eq!(editor.cursor_display_x(), 9);
+        editor.move_cursor(KeyCode::Down);
+        assert_eq!(editor.cursor_position, Position { x: 9, y: 1 });
+
+        // 退格在虚拟空间中只移动光标
+        editor.delete_char();
+        assert_eq!(editor.cursor_position, Position { x: 8, y: 1 });
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+
+        editor.insert_char('x');
+        assert_eq!(lines(&editor), ["line 0", "line 1  x"]);
+        assert_eq!(editor.cursor_position, Position { x: 9, y: 1 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+
+        editor.cursor_position = Position { x: 9, y: 0 };
+        editor.toggle_virtual_space();
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 0 });
+    }
+
+    #[test]
+    fn joining_lines_from_virtual_space_pads_to_the_cursor_column() {
+        let mut editor = editor_with_lines(3);
+        editor.toggle_virtual_space();
+        editor.cursor_position = Position { x: 8, y: 0 };
+        editor.delete_char_forward();
+        assert_eq!(lines(&editor), ["line 0  line 1", "line 2"]);
+        assert_eq!(editor.cursor_position, Position { x: 8, y: 0 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2"]);
+
+        editor.cursor_position = Position { x: 7, y: 1 };
+        editor.kill_line();
+        assert_eq!(lines(&editor), ["line 0", "line 1 line 2"]);
+        assert_eq!(editor.cursor_position, Position { x: 7, y: 1 });
+    }
+
+    #[test]
+    fn deleting_a_word_or_selection_from_virtual_space_pads_to_the_start_column() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("ab"), row("cd")];
+        editor.toggle_virtual_space();
+        editor.cursor_position = Position { x: 5, y: 0 };
+        editor.process_key(KeyCode::Delete, KeyModifiers::CONTROL).unwrap();
+        assert_eq!(lines(&editor), ["ab   cd"]);
+        assert_eq!(editor.cursor_position, Position { x: 5, y: 0 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["ab", "cd"]);
+
+        let mut selection = Selection::new(Position { x: 4, y: 0 });
+        selection.end = Position { x: 1, y: 1 };
+        editor.selection = Some(selection);
+        editor.delete_selection();
+        assert_eq!(lines(&editor), ["ab  d"]);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 0 });
+
+        // 选择两端都在同一行的虚拟空间中时没有可删除的内容
+        let mut selection = Selection::new(Position { x: 6, y: 0 });
+        selection.end = Position { x: 8, y: 0 };
+        editor.selection = Some(selection);
+        editor.delete_selection();
+        assert_eq!(lines(&editor), ["ab  d"]);
+    }
+
+    #[test]
+    fn phantom_line_selection_copies_and_deletes_up_to_last_line() {
+        let mut editor = editor_with_lines(3);
+        editor.cursor_position = Position { x: 2, y: 2 };
+        editor.move_cursor(KeyCode::Down);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 3 });
+
+        editor.process_key(KeyCode::Up, KeyModifiers::SHIFT).unwrap();
+        editor.process_key(KeyCode::Up, KeyModifiers::SHIFT).unwrap();
+        // 上移时回到原来的显示列，空行上的端点收回到最后一行的行尾
+        assert_eq!(editor.selected_text().as_deref(), Some("ne 1\nline 2"));
+        editor.run_action(Action::Cut, 1).unwrap();
+        assert_eq!(lines(&editor), ["line 0", "li"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn deleting_on_phantom_line_only_moves_to_last_line_end() {
+        for action in [Action::DeleteBackward, Action::DeleteWordBackward] {
+            let mut editor = editor_with_lines(2);
+            editor.cursor_position = Position { x: 0, y: 2 };
+            editor.run_action(action, 1).unwrap();
+            assert_eq!(lines(&editor), ["line 0", "line 1"]);
+            assert_eq!(editor.cursor_position, Position { x: 6, y: 1 });
+            assert!(editor.undo_stack.undo.is_empty());
+            assert!(editor.kill_ring.is_empty());
+        }
+
+        let mut editor = editor_with_lines(2);
+        editor.cursor_position = Position { x: 0, y: 2 };
+        editor.move_word(false);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 1 });
+        editor.cursor_position = Position { x: 0, y: 2 };
+        editor.run_action(Action::DeleteWordForward, 1).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 2 });
+    }
+
+    #[test]
+    fn mouse_below_document_lands_on_last_line_end() {
+        let editor = editor_with_lines(3);
+        assert_eq!(editor.mouse_position(2, 1), Some(Position { x: 2, y: 1 }));
+        assert_eq!(editor.mouse_position(2, 10), Some(Position { x: 6, y: 2 }));
+        assert_eq!(Editor::with_config(Config::default(), None).mouse_position(0, 0), None);
+    }
+
+    #[test]
+    fn read_only_buffer_is_not_saved() {
+        let dir = TempDir::new("read-only");
+        let file = dir.join("latin1.txt");
+        let name = file.to_str().unwrap().to_string();
+        fs::write(&file, b"caf\xe9\n").unwrap();
+
+        let mut editor = dir.editor(0);
+        editor.open(&name).unwrap();
+        assert!(editor.read_only);
+        editor.save(true).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"caf\xe9\n");
+
+        // 取消只读后按用户的选择改写
+        editor.toggle_read_only();
+        editor.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn hex_view_shows_file_bytes_until_buffer_is_modified() {
+        let dir = TempDir::new("hex");
+        let file = dir.join("crlf.txt");
+        fs::write(&file, b"a\r\n\xff\r\n").unwrap();
+
+        let mut editor = dir.editor(0);
+        editor.open(file.to_str().unwrap()).unwrap();
+        editor.toggle_hex_view();
+        assert_eq!(editor.hex_view.as_ref().unwrap().bytes, b"a\r\n\xff\r\n");
+
+        // 修改后显示按原换行符重新组装的缓冲区内容
+        editor.toggle_hex_view();
+        editor.mark_dirty();
+        editor.toggle_hex_view();
+        assert_eq!(editor.hex_view.as_ref().unwrap().bytes, "a\r\n\u{fffd}\r\n".as_bytes());
+        assert!(editor.status_message.text.contains("缓冲区内容"));
+    }
+
+    #[test]
+    fn hex_view_keys_follow_the_keymap() {
+        assert_eq!(key_name((KeyCode::Char('b'), KeyModifiers::CONTROL)), "Ctrl-B");
+        assert_eq!(key_name((KeyCode::PageUp, CONTROL_SHIFT)), "Ctrl-Shift-PageUp");
+        assert_eq!(key_name((KeyCode::F(3), KeyModifiers::NONE)), "F3");
+
+        let mut editor = editor_with_lines(1);
+        editor.toggle_hex_view();
+        assert!(editor.status_message.text.contains("按 Ctrl-B 返回"), "{}", editor.status_message.text);
+        editor.toggle_hex_view();
+
+        editor.keymap.remove(&(KeyCode::Char('b'), KeyModifiers::CONTROL));
+        editor.keymap.insert((KeyCode::Char('h'), KeyModifiers::ALT), Action::HexView);
+        editor.process_key(KeyCode::Char('h'), KeyModifiers::ALT).unwrap();
+        assert!(editor.status_message.text.contains("按 Alt-H 返回"), "{}", editor.status_message.text);
+        editor.process_hex_keypress(KeyCode::Char('b'), KeyModifiers::CONTROL);
+        assert!(editor.hex_view.is_some());
+        editor.process_hex_keypress(KeyCode::Char('h'), KeyModifiers::ALT);
+        assert!(editor.hex_view.is_none());
+
+        // 退出经过正常的退出确认，第一次按下计入确认次数
+        editor.mark_dirty();
+        editor.toggle_hex_view();
+        editor.process_hex_keypress(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert!(editor.hex_view.is_none());
+        assert!(!editor.should_quit);
+        assert!(editor.status_message.text.contains("Press Ctrl-Q 2 more times"));
+        editor.process_key(KeyCode::Char('q'), KeyModifiers::CONTROL).unwrap();
+        editor.process_key(KeyCode::Char('q'), KeyModifiers::CONTROL).unwrap();
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn switching_buffers_recounts_matches() {
+        let mut editor = editor_with_lines(3);
+        editor.search_state.last_match = Some(Position { x: 0, y: 0 });
+        editor.update_match_status("line");
+        assert_eq!(editor.search_state.match_status, Some((Some(1), 3, false)));
+
+        // 新缓冲区的版本号同样为 0，不能用上一个文档的统计结果
+        editor.goal_column = Some(4);
+        editor.swap_buffer(Buffer::new());
+        assert_eq!((editor.search_state.last_match, editor.goal_column), (None, None));
+        *editor.rows.write().unwrap() = vec![row("line x")];
+        editor.search_state.last_match = Some(Position { x: 0, y: 0 });
+        editor.update_match_status("line");
+        assert_eq!(editor.search_state.match_status, Some((Some(1), 1, false)));
+    }
+
+    #[test]
+    fn match_status_counts_overlapping_matches() {
+        assert_eq!(row("aaaa").match_positions("aa").collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(row("中aa中aa").match_positions("aa").collect::<Vec<_>>(), [1, 4]);
+
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("aaaa"), row("xaa")];
+        // 每次从上一个匹配的下一个字素继续查找，序号依次加一
+        let mut from = Position { x: 0, y: 0 };
+        for index in 1..=4 {
+            assert_eq!(editor.move_to_match("aa", from, 1), Some(false));
+            editor.update_match_status("aa");
+            assert_eq!(editor.search_state.match_status, Some((Some(index), 4, false)));
+            let last = editor.search_state.last_match.unwrap();
+            from = Position { x: last.x + 1, ..last };
+        }
+        assert_eq!(editor.search_state.last_match, Some(Position { x: 1, y: 1 }));
+    }
+
+    #[test]
+    fn save_trims_trailing_whitespace_when_enabled() {
+        let dir = TempDir::new("trim");
+        let file = dir.join("trim.txt");
+
+        let mut editor = dir.editor(0);
+        editor.filename = Some(file.to_str().unwrap().to_string());
+        *editor.rows.write().unwrap() = vec![row("a  "), row("\tb\t"), row("c")];
+        editor.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "a  \n\tb\t\nc\n");
+
+        editor.trim_trailing_whitespace = true;
+        editor.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "a\n\tb\nc\n");
+        assert!(!editor.is_dirty());
+        // 删除的空白可以撤销
+        editor.undo();
+        assert_eq!(lines(&editor), ["a  ", "\tb\t", "c"]);
+
+        let config = Config::parse("trim_trailing_whitespace = true  # 保存时删除").unwrap();
+        assert!(config.trim_trailing_whitespace);
+        let config = Config::parse("dictionary = \"/a#b\"\nbackup_suffix = \"#\" # 注释").unwrap();
+        assert_eq!(config.dictionary, PathBuf::from("/a#b"));
+        assert_eq!(config.backup_suffix, "#");
+    }
+
+    #[test]
+    fn trimming_save_keeps_cursor_and_view_in_place() {
+        let dir = TempDir::new("trim-cursor");
+        let file = dir.join("trim.txt");
+
+        let mut editor = dir.editor(100);
+        editor.trim_trailing_whitespace = true;
+        editor.filename = Some(file.to_str().unwrap().to_string());
+        for row in editor.rows.write().unwrap().iter_mut() {
+            let text = format!("{}   ", row.string);
+            row.set_string(text);
+        }
+        editor.cursor_position = Position { x: 3, y: 60 };
+        editor.offset = Position { x: 2, y: 40 };
+        editor.save(true).unwrap();
+        assert_eq!(editor.rows.read().unwrap()[60].string, "line 60");
+        assert_eq!(editor.cursor_position, Position { x: 3, y: 60 });
+        assert_eq!(editor.offset, Position { x: 2, y: 40 });
+
+        // 光标所在的空白被删除时收回到新的行尾
+        *editor.rows.write().unwrap() = vec![row("a   ")];
+        editor.cursor_position = Position { x: 4, y: 0 };
+        editor.save(true).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 0 });
+    }
+
+    #[test]
+    fn page_overlap_is_read_from_config() {
+        let config = Config::parse("page_overlap = 5").unwrap();
+        let mut editor = Editor::with_config(config, None);
+        editor.resize(80, 22);
+        assert_eq!(editor.page_size(), 15);
+        assert_eq!(Config::default().page_overlap, PAGE_OVERLAP);
+        assert!(Config::parse("page_overlap = -1").is_err());
+    }
+
+    #[test]
+    fn status_messages_expire_after_their_timeout() {
+        let visible = |message: &StatusMessage, after: Duration| {
+            message.is_visible_at(message.time + after, MESSAGE_TIMEOUT, ERROR_MESSAGE_TIMEOUT)
+        };
+        let just_before = MESSAGE_TIMEOUT - Duration::from_millis(1);
+
+        let normal = StatusMessage::from("saved".to_string());
+        assert!(visible(&normal, just_before));
+        assert!(!visible(&normal, MESSAGE_TIMEOUT));
+        assert!(!visible(&normal, ERROR_MESSAGE_TIMEOUT));
+
+        let error = StatusMessage::error("failed".to_string());
+        assert!(visible(&error, just_before));
+        assert!(visible(&error, MESSAGE_TIMEOUT));
+        assert!(!visible(&error, ERROR_MESSAGE_TIMEOUT));
+
+        let persistent = StatusMessage::persistent("Search: ".to_string());
+        assert!(visible(&persistent, just_before));
+        assert!(visible(&persistent, MESSAGE_TIMEOUT));
+        assert!(visible(&persistent, ERROR_MESSAGE_TIMEOUT));
+        assert!(visible(&persistent, Duration::from_secs(24 * 60 * 60)));
+    }
+
+    #[test]
+    fn message_timeouts_are_read_from_config() {
+        let config = Config::parse("message_timeout = 2\nerror_message_timeout = 0").unwrap();
+        let editor = Editor::with_config(config, None);
+        assert_eq!(editor.message_timeout, Duration::from_secs(2));
+        assert_eq!(editor.error_message_timeout, Duration::ZERO);
+
+        let error = StatusMessage::error("failed".to_string());
+        assert!(!error.is_visible_at(error.time, editor.message_timeout, editor.error_message_timeout));
+        let normal = StatusMessage::from("saved".to_string());
+        assert!(normal.is_visible_at(normal.time + Duration::from_secs(1), editor.message_timeout, editor.error_message_timeout));
+
+        assert_eq!(Config::default().message_timeout, MESSAGE_TIMEOUT);
+        assert_eq!(Config::default().error_message_timeout, ERROR_MESSAGE_TIMEOUT);
+        assert!(Config::parse("message_timeout = -1").is_err());
+        assert!(Config::parse("error_message_timeout = soon").is_err());
+    }
+
+    #[test]
+    fn filetype_can_be_set_by_command_and_filename_pattern() {
+        assert!(glob_match("*.rs.in", "src/lib.rs.in"));
+        assert!(glob_match("Makefile", "/home/me/Makefile"));
+        assert!(glob_match("build/*.?", "build/out.c"));
+        assert!(!glob_match("*.rs.in", "lib.rs"));
+
+        let config = Config::parse("filetype.\"*.rs.in\" = \"rust\"\nfiletype.*.conf = \"py\"").unwrap();
+        assert_eq!(syntax_for_filename("lib.rs.in", &config.filetypes).name, "rust");
+        assert_eq!(syntax_for_filename("app.conf", &config.filetypes).name, "python");
+        assert_eq!(syntax_for_filename("main.c", &config.filetypes).name, "c");
+        assert!(Config::parse("filetype.*.x = \"cobol\"").is_err());
+
+        let mut editor = editor_with_lines(1);
+        editor.set_option("filetype=rust");
+        assert_eq!(editor.syntax.name, "rust");
+        editor.set_option("ft = js");
+        assert_eq!(editor.syntax.name, "javascript");
+        editor.set_option("filetype=cobol");
+        assert_eq!(editor.syntax.name, "javascript");
+    }
+
+    #[test]
+    fn backward_search_visits_matches_in_reverse_order() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("ab ab"), row(""), row("x ab ab ab")];
+        editor.cursor_position = Position { x: 8, y: 2 };
+
+        let mut visited = Vec::new();
+        for _ in 0..5 {
+            let wrapped = editor.move_to_match("ab", editor.cursor_position, -1).unwrap();
+            visited.push((editor.cursor_position.x, editor.cursor_position.y, wrapped));
+        }
+        assert_eq!(visited, [(5, 2, false), (2, 2, false), (3, 0, false), (0, 0, false), (8, 2, true)]);
+    }
+
+    #[test]
+    fn find_match_backward_handles_rows_wide_characters_and_wrapping() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("foo bar foo"), row("中文foo中foo"), row("bar")];
+
+        // 同一行中更早的匹配，匹配必须在起点之前开始
+        assert_eq!(editor.find_match("foo", Position { x: 8, y: 0 }, -1), Some(Position { x: 0, y: 0 }));
+        // 起始行没有更早的匹配时查找上一行
+        assert_eq!(editor.find_match("foo", Position { x: 1, y: 2 }, -1), Some(Position { x: 6, y: 1 }));
+        // 宽字符之后的位置按字素计算
+        assert_eq!(editor.find_match("foo", Position { x: 6, y: 1 }, -1), Some(Position { x: 2, y: 1 }));
+        assert_eq!(editor.find_match("中f", Position { x: 10, y: 1 }, -1), Some(Position { x: 5, y: 1 }));
+
+        // 从开头向前查找时从文档末尾继续
+        editor.cursor_position = Position { x: 0, y: 0 };
+        assert_eq!(editor.move_to_match("foo", Position { x: 0, y: 0 }, -1), Some(true));
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 1 });
+        editor.search_state.last_query = Some("bar".to_string());
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.search_next(false);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 2 });
+        assert!(editor.status_message.text.contains("从末尾继续"));
+        editor.search_next(false);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 0 });
+        assert_eq!(editor.status_message.text, "找到 \"bar\"");
+    }
+
+    #[test]
+    fn replace_after_cjk_text_uses_grapheme_positions() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("中文 word 中 word")];
+        editor.current_search = Some("word".to_string());
+        editor.search_state.replace_text = Some("词语".to_string());
+
+        editor.replace_current_match();
+        assert_eq!(lines(&editor), ["中文 词语 中 word"]);
+        editor.cursor_position = Position { x: 6, y: 0 };
+        editor.replace_current_match();
+        assert_eq!(lines(&editor), ["中文 词语 中 词语"]);
+        editor.undo();
+        assert_eq!(lines(&editor), ["中文 词语 中 word"]);
+    }
+
+    #[test]
+    fn crlf_file_round_trips_unchanged() {
+        let dir = TempDir::new("crlf");
+        let file = dir.join("dos.txt");
+        let name = file.to_str().unwrap().to_string();
+
+        for original in ["a\r\nb\r\n\r\nc\r\n", "a\r\nb"] {
+            fs::write(&file, original).unwrap();
+            let mut editor = dir.editor(0);
+            editor.open(&name).unwrap();
+            assert!(editor.line_ending == LineEnding::CrLf);
+            editor.save(true).unwrap();
+            assert_eq!(fs::read(&file).unwrap(), original.as_bytes());
+        }
+    }
+
+    #[test]
+    fn saving_twice_keeps_file_bytes_stable() {
+        let dir = TempDir::new("stable");
+        let file = dir.join("stable.txt");
+        let name = file.to_str().unwrap().to_string();
+
+        for original in ["a\nb", "", "\n", "x\n\n"] {
+            fs::write(&file, original).unwrap();
+            let mut editor = dir.editor(0);
+            editor.open(&name).unwrap();
+            editor.save(true).unwrap();
+            assert_eq!(fs::read_to_string(&file).unwrap(), original, "第一次保存 {:?}", original);
+            editor.save(true).unwrap();
+            assert_eq!(fs::read_to_string(&file).unwrap(), original, "第二次保存 {:?}", original);
+        }
+    }
+
+    #[test]
+    fn selected_text_keeps_combining_characters_together() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("e\u{301}x"), row("e\u{301}")];
+        let select = |editor: &mut Editor, start: Position, end: Position| {
+            editor.selection = Some(Selection { start, end, block: false });
+            editor.selected_text()
+        };
+        assert_eq!(select(&mut editor, Position { x: 0, y: 0 }, Position { x: 1, y: 0 }).as_deref(), Some("e\u{301}"));
+        assert_eq!(select(&mut editor, Position { x: 1, y: 0 }, Position { x: 2, y: 0 }).as_deref(), Some("x"));
+        assert_eq!(
+            select(&mut editor, Position { x: 1, y: 0 }, Position { x: 1, y: 1 }).as_deref(),
+            Some("x\ne\u{301}")
+        );
+    }
+
+    #[test]
+    fn clicks_map_tabs_and_wide_characters_to_graphemes() {
+        for word_wrap in [false, true] {
+            let mut editor = editor_with_lines(0);
+            *editor.rows.write().unwrap() = vec![row("\tfoo"), row("中文x")];
+            editor.word_wrap = word_wrap;
+            // 制表符占 4 列，点在右半边时落在它之后
+            let tab_line: Vec<usize> = (0..7).map(|column| editor.mouse_position(column, 0).unwrap().x).collect();
+            assert_eq!(tab_line, [0, 0, 1, 1, 1, 2, 3], "word_wrap = {}", word_wrap);
+            let wide_line: Vec<usize> = (0..7).map(|column| editor.mouse_position(column, 1).unwrap().x).collect();
+            assert_eq!(wide_line, [0, 1, 1, 2, 2, 3, 3], "word_wrap = {}", word_wrap);
+            assert_eq!(editor.screen_to_position(0, 2), None);
+            assert_eq!(editor.mouse_position(0, 2), Some(Position { x: 3, y: 1 }));
+        }
+
+        // 折行后第二段的列从该段的开头算起
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("中文x")];
+        editor.word_wrap = true;
+        editor.screen_cols = 4;
+        assert_eq!(editor.screen_to_position(3, 0), Some(Position { x: 1, y: 0 }));
+        assert_eq!(editor.screen_to_position(0, 1), Some(Position { x: 2, y: 0 }));
+        assert_eq!(editor.screen_to_position(1, 1), Some(Position { x: 3, y: 0 }));
+    }
+
+    #[test]
+    fn horizontal_offset_pads_half_cut_wide_characters() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("ab中文cd"), row("abcdefgh")];
+        editor.resize(80, 12);
+
+        // 偏移落在“中”的右半边：从“文”开始显示，前面补一个空格
+        editor.offset.x = 3;
+        let lines = editor.screen_lines(&editor.rows.read().unwrap(), 10, 80);
+        assert_eq!(lines, [(0, 3, 6, 1), (1, 3, 8, 0)]);
+        let rendered = {
+            let rows = editor.rows.read().unwrap();
+            editor.render_row_uncached(&rows[0], 3, 6, 79, None, false)
+        };
+        assert_eq!(format!("{}{}", " ".repeat(lines[0].3), strip_ansi(&rendered)), " 文cd");
+
+        // 偏移正好落在宽字符开头时不需要填充
+        editor.offset.x = 4;
+        let lines = editor.screen_lines(&editor.rows.read().unwrap(), 10, 80);
+        assert_eq!(lines, [(0, 3, 6, 0), (1, 4, 8, 0)]);
+
+        // 滚动按显示宽度计算偏移，光标所在的宽字符完整显示
+        editor.scroll_off = 0;
+        editor.offset.x = 0;
+        editor.resize(4, 12);
+        editor.cursor_position = Position { x: 4, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.offset.x, 3);
+        editor.cursor_position = Position { x: 3, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.offset.x, 3);
+        editor.cursor_position = Position { x: 2, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.offset.x, 2);
+    }
+
+    #[test]
+    fn long_chinese_message_is_truncated_at_character_boundaries() {
+        let message = "文件已保存到磁盘，共写入 128 字节";
+        // 宽字符放不下时整个舍去，结果不超过终端宽度
+        assert_eq!(truncate_to_width(message, 10), "文件已保存");
+        assert_eq!(truncate_to_width(message, 9), "文件已保");
+        assert_eq!(truncate_to_width(message, 1), "");
+        assert_eq!(truncate_to_width(message, 0), "");
+        assert_eq!(truncate_to_width("ab中文", 3), "ab");
+        assert_eq!(truncate_to_width("ab中文", 4), "ab中");
+        // 组合字符与基字符一起保留或舍去
+        assert_eq!(truncate_to_width("e\u{301}x", 1), "e\u{301}");
+        assert_eq!(truncate_to_width(message, 200), message);
+        for width in 0..40 {
+            assert!(UnicodeWidthStr::width(truncate_to_width(message, width)) <= width);
+        }
+    }
+
+    #[test]
+    fn default_bindings_can_be_sent_by_legacy_terminals() {
+        // 传统终端把 Ctrl-Shift-字母报告为 Ctrl-字母，把 Ctrl-Tab 报告为 Tab
+        for &(code, modifiers, action) in DEFAULT_KEYMAP.iter() {
+            let name = ACTION_NAMES.iter().find(|&&(_, a)| a == action).map_or("?", |&(name, _)| name);
+            assert!(
+                !(matches!(code, KeyCode::Char(_)) && modifiers == CONTROL_SHIFT),
+                "{} 绑定到 Ctrl-Shift", name
+            );
+            assert!(!(code == KeyCode::Tab && modifiers.contains(KeyModifiers::CONTROL)), "{} 绑定到 Ctrl-Tab", name);
+        }
+
+        let keymap = default_keymap();
+        assert!(keymap.get(&(KeyCode::Char('e'), CONTROL_ALT)) == Some(&Action::ReplaceAll));
+        assert!(keymap.get(&(KeyCode::Char('k'), CONTROL_ALT)) == Some(&Action::DeleteLines));
+        assert!(keymap.get(&(KeyCode::Char('a'), KeyModifiers::ALT)) == Some(&Action::SaveAs));
+        assert!(keymap.get(&(KeyCode::Char('n'), KeyModifiers::ALT)) == Some(&Action::NextBuffer));
+
+        let mut editor = editor_with_lines(3);
+        editor.process_key(KeyCode::Char('k'), CONTROL_ALT).unwrap();
+        assert_eq!(lines(&editor), ["line 1", "line 2"]);
+    }
+
+    #[test]
+    fn config_parse_handles_comments_quotes_and_errors() {
+        let config = Config::parse("# 整行注释\n\ntab_width = 2  # 行尾注释\nline_numbers=true\n").unwrap();
+        assert_eq!(config.tab_width, 2);
+        assert!(config.line_numbers);
+        // 双引号内的 `#` 属于值本身
+        assert_eq!(strip_comment("a = \"#1\" # 注释"), "a = \"#1\" ");
+        assert_eq!(strip_comment("a = 1 # \"注释\""), "a = 1 ");
+
+        assert_eq!(Config::parse("tab_width = 4\ncolour = 1").err().unwrap(), "第 2 行: 未知的配置项 colour");
+        assert!(Config::parse("tab_width = 0").is_err());
+        assert!(Config::parse("tab_width = four").is_err());
+        assert!(Config::parse("word_wrap = yes").is_err());
+        assert!(Config::parse("line_numbers").is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_file_opens_read_only_with_warning() {
+        let dir = TempDir::new("lossy");
+        let file = dir.join("latin1.txt");
+        fs::write(&file, b"caf\xe9\nok\n").unwrap();
+
+        let mut editor = dir.editor(0);
+        editor.open(file.to_str().unwrap()).unwrap();
+        assert!(editor.read_only);
+        assert!(!editor.is_dirty());
+        assert_eq!(lines(&editor), ["caf\u{fffd}", "ok"]);
+        assert!(editor.status_message.text.contains("不是有效的 UTF-8"));
+
+        // 有效的 UTF-8 文件照常以可写方式打开
+        let valid = dir.join("utf8.txt");
+        fs::write(&valid, "café\n").unwrap();
+        let mut editor = dir.editor(0);
+        editor.open(valid.to_str().unwrap()).unwrap();
+        assert!(!editor.read_only);
+        assert_eq!(lines(&editor), ["café"]);
+    }
+
+    #[test]
+    fn failed_write_keeps_buffer_dirty() {
+        let dir = TempDir::new("missing");
+        let name = dir.join("no-such-dir").join("notes.txt").to_str().unwrap().to_string();
+
+        let mut editor = dir.editor(1);
+        editor.filename = Some(name.clone());
+        editor.mark_dirty();
+        let saved_version = editor.saved_version;
+        editor.save(false).unwrap();
+        assert_eq!(editor.saved_version, saved_version);
+        assert!(editor.is_dirty());
+        assert!(editor.status_message.text.starts_with(&format!("无法保存 {}: ", name)));
+    }
+
+    #[test]
+    fn save_as_recognizes_the_current_file_and_keeps_the_name_on_failure() {
+        let dir = TempDir::new("save-as");
+        let current = dir.join("notes.txt");
+        let other = dir.join("other.txt");
+        fs::write(&current, "line 0\n").unwrap();
+        fs::write(&other, "other\n").unwrap();
+
+        let mut editor = dir.editor(1);
+        editor.filename = Some(current.to_str().unwrap().to_string());
+        // 写回当前文件本身不需要确认覆盖
+        assert!(editor.is_current_file(dir.join(".").join("notes.txt").to_str().unwrap()));
+        assert!(!editor.is_current_file(other.to_str().unwrap()));
+        assert!(!editor.is_current_file(dir.join("new.txt").to_str().unwrap()));
+
+        // 写入失败时仍然对应原来的文件
+        let missing = dir.join("no-such-dir").join("notes.rs").to_str().unwrap().to_string();
+        assert!(!editor.save_to(missing).unwrap());
+        assert_eq!(editor.filename.as_deref(), current.to_str());
+        assert_eq!(editor.syntax.name, PLAIN_SYNTAX.name);
+
+        let renamed = dir.join("notes.rs").to_str().unwrap().to_string();
+        assert!(editor.save_to(renamed.clone()).unwrap());
+        assert_eq!(editor.filename, Some(renamed.clone()));
+        assert_eq!(editor.syntax.name, "rust");
+        assert_eq!(fs::read_to_string(&renamed).unwrap(), "line 0\n");
+    }
+
+    fn folds(editor: &Editor) -> Vec<Option<usize>> {
+        editor.rows.read().unwrap().iter().map(|row| row.folded).collect()
+    }
+
+    #[test]
+    fn editing_a_folded_line_keeps_the_fold() {
+        let mut editor = editor_with_lines(6);
+        select_lines(&mut editor, 1, 3);
+        editor.fold();
+        editor.toggle_comment();
+        assert_eq!(lines(&editor)[1], "// line 1");
+        assert_eq!(folds(&editor)[1], Some(2));
+        editor.indent_lines(true);
+        assert_eq!(lines(&editor)[1], "\t// line 1");
+        assert_eq!(folds(&editor)[1], Some(2));
+
+        // 撤销和重做不改变行数，折叠保留
+        editor.undo();
+        editor.undo();
+        assert_eq!(lines(&editor)[1], "line 1");
+        assert_eq!(folds(&editor)[1], Some(2));
+        editor.redo();
+        assert_eq!(lines(&editor)[1], "// line 1");
+        assert_eq!(folds(&editor)[1], Some(2));
+    }
+
+    #[test]
+    fn block_edits_keep_folds() {
+        let mut editor = editor_with_lines(4);
+        select_lines(&mut editor, 0, 2);
+        editor.fold();
+        let mut selection = Selection::new(Position { x: 0, y: 0 });
+        selection.end = Position { x: 2, y: 0 };
+        selection.block = true;
+        editor.selection = Some(selection);
+        editor.delete_block();
+        assert_eq!(lines(&editor)[0], "ne 0");
+        assert_eq!(folds(&editor)[0], Some(2));
+
+        editor.paste_block("ab");
+        assert_eq!(lines(&editor)[0], "abne 0");
+        assert_eq!(folds(&editor)[0], Some(2));
+    }
+
+    #[test]
+    fn joining_hidden_lines_into_a_fold_unfolds_it() {
+        let mut editor = editor_with_lines(4);
+        select_lines(&mut editor, 0, 2);
+        editor.fold();
+        // 行内删除保留折叠
+        editor.delete_range(Position { x: 0, y: 0 }, Position { x: 1, y: 0 }, EditKind::DeleteSelection);
+        assert_eq!(folds(&editor)[0], Some(2));
+
+        editor.delete_range(Position { x: 5, y: 0 }, Position { x: 0, y: 1 }, EditKind::DeleteSelection);
+        assert_eq!(lines(&editor), ["ine 0line 1", "line 2", "line 3"]);
+        assert_eq!(folds(&editor), [None, None, None]);
+        assert_eq!(editor.status_message.text, "已展开第 1 行的折叠");
+
+        // 撤销合并时行数改变，不恢复折叠
+        editor.undo();
+        assert_eq!(lines(&editor), ["ine 0", "line 1", "line 2", "line 3"]);
+        assert_eq!(folds(&editor), [None, None, None, None]);
+    }
+
+    /// 写入总是失败的目标，模拟已经关闭的终端
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+    }
+
+    #[test]
+    fn progress_draws_at_intervals_and_ignores_write_errors() {
+        let mut progress = Progress::new("正在替换");
+        let mut out = Vec::new();
+        progress.update(&mut out, 1, 2, 5);
+        assert!(out.is_empty());
+
+        progress.last_draw -= PROGRESS_INTERVAL;
+        progress.update(&mut out, 1, 2, 5);
+        assert!(String::from_utf8(out).unwrap().ends_with("正在替换… 50%"));
+
+        progress.last_draw -= PROGRESS_INTERVAL;
+        progress.update(&mut BrokenPipe, 2, 2, 5);
+        assert!(progress.last_draw.elapsed() < PROGRESS_INTERVAL);
+    }
+
+    #[test]
+    fn replace_all_is_undone_in_one_step() {
+        let mut editor = editor_with_lines(3);
+        editor.current_search = Some("line".to_string());
+        editor.search_state.replace_text = Some("row".to_string());
+        editor.replace_all_matches();
+        assert_eq!(lines(&editor), ["row 0", "row 1", "row 2"]);
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2"]);
+    }
+
+    #[test]
+    fn undo_stack_drops_the_oldest_group_past_the_limit() {
+        let mut stack = UndoStack::default();
+        let origin = Position { x: 0, y: 0 };
+        for y in 0..=UNDO_LIMIT {
+            let edit = Edit { y, before: Vec::new(), after: Vec::new() };
+            stack.record(EditKind::DeleteChar, edit, origin, origin, 0, true);
+        }
+        assert_eq!(stack.undo.len(), UNDO_LIMIT);
+        assert_eq!(stack.undo.front().map(|group| group.edits[0].y), Some(1));
+        assert_eq!(stack.undo.back().map(|group| group.edits[0].y), Some(UNDO_LIMIT));
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip_a_line_join() {
+        let mut editor = editor_with_lines(2);
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.delete_char();
+        assert_eq!(lines(&editor), ["line 0line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 0 });
+
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 1 });
+        editor.redo();
+        assert_eq!(lines(&editor), ["line 0line 1"]);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 0 });
+    }
+
+    #[test]
+    fn typed_characters_are_undone_by_word() {
+        let mut editor = editor_with_lines(0);
+        for c in "ab cd".chars() {
+            editor.process_key(KeyCode::Char(c), KeyModifiers::NONE).unwrap();
+        }
+        assert_eq!(lines(&editor), ["ab cd"]);
+        // 空白开始新的一组，之后的字符并入这一组
+        editor.undo();
+        assert_eq!(lines(&editor), ["ab"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 0 });
+        // 第一个字符是在空文档中新建的行，撤销后文档重新为空
+        editor.undo();
+        assert!(lines(&editor).is_empty());
+        editor.redo();
+        editor.redo();
+        assert_eq!(lines(&editor), ["ab cd"]);
+
+        // 光标移开后输入的字符不与之前的合并
+        editor.cursor_position.x = 0;
+        editor.process_key(KeyCode::Char('x'), KeyModifiers::NONE).unwrap();
+        editor.undo();
+        assert_eq!(lines(&editor), ["ab cd"]);
+    }
+
+    #[test]
+    fn undo_back_to_the_saved_version_clears_dirty() {
+        let mut editor = editor_with_lines(1);
+        editor.cursor_position = Position { x: 6, y: 0 };
+        editor.insert_char('!');
+        editor.saved_version = editor.version;
+        assert!(!editor.is_dirty());
+
+        editor.insert_char(' ');
+        assert!(editor.is_dirty());
+        editor.undo();
+        assert!(!editor.is_dirty());
+        editor.redo();
+        assert!(editor.is_dirty());
+        editor.undo();
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0"]);
+        assert!(editor.is_dirty());
+    }
+
+    #[test]
+    fn block_delete_handles_short_rows_and_wide_characters() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("abcdef"), row("ab"), row("中文字xy")];
+        // 显示列 1..4：第二行比左边界之后的部分短，第三行的 `中` 跨过左边界
+        let mut selection = Selection::new(Position { x: 1, y: 0 });
+        selection.end = Position { x: 2, y: 2 };
+        selection.block = true;
+        editor.selection = Some(selection);
+        editor.delete_block();
+        assert_eq!(lines(&editor), ["aef", "a", "字xy"]);
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 0 });
+        assert!(editor.selection.is_none());
+
+        editor.undo();
+        assert_eq!(lines(&editor), ["abcdef", "ab", "中文字xy"]);
+    }
+
+    #[test]
+    fn block_paste_pads_short_rows_and_extends_the_document() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("abcdef"), row("ab")];
+        editor.cursor_position = Position { x: 4, y: 0 };
+        editor.paste_block("X\nY\nZ");
+        // 短行用空格补齐到光标所在的列，超出文档末尾时追加新行
+        assert_eq!(lines(&editor), ["abcdXef", "ab  Y", "    Z"]);
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 0 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["abcdef", "ab"]);
+
+        // 列落在宽字符中间时插入到这个字符之前
+        *editor.rows.write().unwrap() = vec![row("abc"), row("中文")];
+        editor.cursor_position = Position { x: 1, y: 0 };
+        editor.paste_block("1\n2");
+        assert_eq!(lines(&editor), ["a1bc", "2中文"]);
+    }
+
+    #[test]
+    fn substitute_handles_flags_empty_pattern_and_multibyte_text() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("中文 a/b 中文"), row("中文")];
+
+        // 不带标志时只替换光标之后的下一处匹配，多字节字符按字素计算
+        editor.cursor_position = Position { x: 1, y: 0 };
+        editor.run_command("s/中文/汉字").unwrap();
+        assert_eq!(lines(&editor), ["中文 a/b 汉字", "中文"]);
+        editor.undo();
+
+        // g 替换全部匹配，`\/` 表示斜杠本身
+        editor.run_command("s/中文/字/g").unwrap();
+        assert_eq!(lines(&editor), ["字 a/b 字", "字"]);
+        assert_eq!(editor.status_message.text, "已替换 3 处匹配项");
+        editor.run_command("s/a\\/b/x/g").unwrap();
+        assert_eq!(lines(&editor), ["字 x 字", "字"]);
+        assert!(editor.current_search.is_none());
+        assert!(editor.search_state.replace_text.is_none());
+
+        // 未知的标志和空的查找文本只显示错误，不修改文档
+        editor.run_command("s/字/x/i").unwrap();
+        assert_eq!(editor.status_message.text, "未知的替换标志: i");
+        editor.run_command("s//x/g").unwrap();
+        assert_eq!(editor.status_message.text, "用法: s/查找/替换/[g]");
+        editor.run_command("s/字").unwrap();
+        assert_eq!(editor.status_message.text, "用法: s/查找/替换/[g]");
+        // 多出的部分是用法错误，不是未知的标志
+        editor.run_command("s/x/y/z/w").unwrap();
+        assert_eq!(editor.status_message.text, "用法: s/查找/替换/[g]");
+        assert_eq!(lines(&editor), ["字 x 字", "字"]);
+    }
+
+    #[test]
+    fn count_prefix_repeats_the_next_movement() {
+        let mut editor = editor_with_lines(20);
+        editor.process_key(KeyCode::Char('1'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Char('2'), KeyModifiers::ALT).unwrap();
+        assert_eq!(editor.pending_count, Some(12));
+        assert_eq!(editor.status_message.text, "重复次数: 12");
+        editor.process_key(KeyCode::Down, KeyModifiers::NONE).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 12 });
+        // 重复次数只作用于紧接着的一个按键
+        assert_eq!(editor.pending_count, None);
+        editor.process_key(KeyCode::Down, KeyModifiers::NONE).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 13 });
+
+        // 带 Shift 时扩展选区，到达文档边界时提前停止
+        editor.process_key(KeyCode::Char('9'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Right, KeyModifiers::SHIFT).unwrap();
+        let selection = editor.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (Position { x: 0, y: 13 }, Position { x: 1, y: 14 }));
+        editor.process_key(KeyCode::Char('9'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Char('9'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Down, KeyModifiers::NONE).unwrap();
+        assert!(editor.selection.is_none());
+        assert_eq!(editor.cursor_position.y, 20);
+
+        // 按单词移动同样可以重复，重复次数为 0 时按 1 次处理
+        *editor.rows.write().unwrap() = vec![row("one two three four")];
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.process_key(KeyCode::Char('3'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Right, KeyModifiers::CONTROL).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 14, y: 0 });
+        editor.process_key(KeyCode::Char('0'), KeyModifiers::ALT).unwrap();
+        editor.process_key(KeyCode::Left, KeyModifiers::NONE).unwrap();
+        assert_eq!(editor.cursor_position, Position { x: 13, y: 0 });
+    }
+
+    #[test]
+    fn vi_normal_mode_counts_apply_to_motions_and_deletes() {
+        let mut editor = editor_with_lines(10);
+        editor.vi_mode = Some(ViMode::Normal);
+        let keys = |editor: &mut Editor, keys: &str| {
+            for c in keys.chars() {
+                editor.process_key(KeyCode::Char(c), KeyModifiers::NONE).unwrap();
+            }
+        };
+        keys(&mut editor, "3j4l");
+        assert_eq!(editor.cursor_position, Position { x: 4, y: 3 });
+        // 已经输入重复次数时 0 是其中的一位数字
+        keys(&mut editor, "0");
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 3 });
+        keys(&mut editor, "10k");
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 0 });
+        keys(&mut editor, "3j");
+
+        // 重复次数写在 dd 之前，删除多行作为一次撤销
+        keys(&mut editor, "2dd");
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2", "line 5", "line 6", "line 7", "line 8", "line 9"]);
+        assert_eq!(editor.cursor_position, Position { x: 0, y: 3 });
+        keys(&mut editor, "3x");
+        assert_eq!(lines(&editor)[3], "e 5");
+        keys(&mut editor, "uu");
+        assert_eq!(lines(&editor).len(), 10);
+        assert!(editor.vi_mode == Some(ViMode::Normal));
+    }
+
+    #[test]
+    fn toggle_comment_comments_and_uncomments_selected_lines() {
+        let mut editor = editor_with_lines(0);
+        *editor.rows.write().unwrap() = vec![row("fn a() {"), row("    x();"), row(""), row("    // y();")];
+        select_lines(&mut editor, 0, 3);
+        editor.cursor_position = Position { x: 6, y: 1 };
+        // 有未注释的行时为所有非空行添加注释，空行保持不变
+        editor.toggle_comment();
+        assert_eq!(lines(&editor), ["// fn a() {", "    // x();", "", "    // // y();"]);
+        assert_eq!(editor.cursor_position, Position { x: 9, y: 1 });
+        let selection = editor.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (Position { x: 3, y: 0 }, Position { x: 1, y: 3 }));
+
+        // 全部已注释时取消注释，光标回到原来的文本上
+        editor.toggle_comment();
+        assert_eq!(lines(&editor), ["fn a() {", "    x();", "", "    // y();"]);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 1 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["// fn a() {", "    // x();", "", "    // // y();"]);
+        editor.undo();
+        assert_eq!(lines(&editor), ["fn a() {", "    x();", "", "    // y();"]);
+
+        // 注释标记后没有空格时只删除标记，只有空行时不做修改
+        *editor.rows.write().unwrap() = vec![row("  //x"), row("// y"), row("  ")];
+        select_lines(&mut editor, 0, 2);
+        editor.toggle_comment();
+        assert_eq!(lines(&editor), ["  x", "y", "  "]);
+        editor.clear_selection();
+        editor.cursor_position = Position { x: 0, y: 2 };
+        editor.toggle_comment();
+        assert_eq!(lines(&editor), ["  x", "y", "  "]);
+    }
+
+    #[test]
+    fn indent_and_dedent_move_the_cursor_and_selection_with_the_text() {
+        let mut editor = editor_with_lines(0);
+        editor.tab_width = 4;
+        editor.insert_spaces = false;
+        *editor.rows.write().unwrap() = vec![row("a"), row(""), row("\tb"), row("      c")];
+        select_lines(&mut editor, 0, 3);
+        editor.cursor_position = Position { x: 1, y: 0 };
+        // 空行不缩进
+        editor.indent_lines(true);
+        assert_eq!(lines(&editor), ["\ta", "", "\t\tb", "\t      c"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 0 });
+        let selection = editor.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (Position { x: 1, y: 0 }, Position { x: 2, y: 3 }));
+        editor.indent_lines(false);
+        assert_eq!(lines(&editor), ["a", "", "\tb", "      c"]);
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 0 });
+
+        // 取消缩进删除一个制表符或最多 tab_width 个空格，没有缩进的行不变
+        editor.indent_lines(false);
+        assert_eq!(lines(&editor), ["a", "", "b", "  c"]);
+        assert_eq!(editor.selection.unwrap().end, Position { x: 0, y: 3 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["a", "", "\tb", "      c"]);
+
+        // 所有行都没有缩进时不记录修改
+        *editor.rows.write().unwrap() = vec![row("x")];
+        editor.clear_selection();
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.saved_version = editor.version;
+        editor.indent_lines(false);
+        assert!(!editor.is_dirty());
+
+        // 开启软制表符时插入 tab_width 个空格
+        editor.insert_spaces = true;
+        editor.tab_width = 2;
+        editor.indent_lines(true);
+        assert_eq!(lines(&editor), ["  x"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn duplicate_lines_copies_the_cursor_line_or_the_selection() {
+        let mut editor = editor_with_lines(4);
+        editor.cursor_position = Position { x: 3, y: 1 };
+        editor.duplicate_lines();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 1", "line 2", "line 3"]);
+        assert_eq!(editor.cursor_position, Position { x: 3, y: 2 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2", "line 3"]);
+        assert_eq!(editor.cursor_position, Position { x: 3, y: 1 });
+
+        // 选择随之移动到副本上
+        select_lines(&mut editor, 1, 2);
+        editor.cursor_position = Position { x: 1, y: 2 };
+        editor.duplicate_lines();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2", "line 1", "line 2", "line 3"]);
+        let selection = editor.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (Position { x: 0, y: 3 }, Position { x: 1, y: 4 }));
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 4 });
+
+        // 光标位于文档末尾之后的空行时不做修改
+        editor.clear_selection();
+        editor.cursor_position = Position { x: 0, y: 6 };
+        editor.duplicate_lines();
+        assert_eq!(lines(&editor).len(), 6);
+    }
+
+    #[test]
+    fn alt_arrows_move_lines_with_the_cursor_and_selection() {
+        let mut editor = editor_with_lines(4);
+        editor.cursor_position = Position { x: 2, y: 1 };
+        editor.process_key(KeyCode::Down, KeyModifiers::ALT).unwrap();
+        assert_eq!(lines(&editor), ["line 0", "line 2", "line 1", "line 3"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 2 });
+        editor.undo();
+        assert_eq!(lines(&editor), ["line 0", "line 1", "line 2", "line 3"]);
+        assert_eq!(editor.cursor_position, Position { x: 2, y: 1 });
+
+        // 选中的多行一起移动，选择保持覆盖相同的文本
+        select_lines(&mut editor, 1, 2);
+        editor.cursor_position = Position { x: 1, y: 2 };
+        editor.move_lines(false);
+        assert_eq!(lines(&editor), ["line 1", "line 2", "line 0", "line 3"]);
+        let selection = editor.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (Position { x: 0, y: 0 }, Position { x: 1, y: 1 }));
+        assert_eq!(editor.cursor_position, Position { x: 1, y: 1 });
+
+        // 已经在文档开头或末尾时不做修改
+        editor.move_lines(false);
+        assert_eq!(lines(&editor), ["line 1", "line 2", "line 0", "line 3"]);
+        editor.clear_selection();
+        editor.cursor_position = Position { x: 0, y: 3 };
+        editor.saved_version = editor.version;
+        editor.move_lines(true);
+        assert_eq!(lines(&editor), ["line 1", "line 2", "line 0", "line 3"]);
+        assert!(!editor.is_dirty());
+    }
+
+    #[test]
+    fn looks_binary_detects_nul_bytes_and_control_characters() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary("普通文本\tline\r\n\x1b[0m\x0c".as_bytes()));
+        assert!(looks_binary(b"text\0more text"));
+        // 控制字符超过三成时视为二进制，恰好三成时不算
+        assert!(!looks_binary(b"\x01\x02\x03abcdefg"));
+        assert!(looks_binary(b"\x01\x02\x03\x04abcdef"));
+        // 只检查开头的 BINARY_SNIFF_LEN 个字节
+        let mut bytes = vec![b'a'; BINARY_SNIFF_LEN];
+        bytes.push(0);
+        assert!(!looks_binary(&bytes));
+    }
+
+    #[test]
+    fn scroll_position_counts_displayed_lines() {
+        let mut editor = editor_with_lines(30);
+        editor.resize(80, 12);
+        assert_eq!(editor.screen_rows, 10);
+        assert_eq!(editor.scroll_position(), "Top");
+        editor.offset.y = 10;
+        assert_eq!(editor.scroll_position(), "50%");
+        editor.offset.y = 20;
+        assert_eq!(editor.scroll_position(), "Bot");
+
+        // 折叠隐藏的行不计入：第 1 行之下隐藏 9 行后共 21 个可见行
+        select_lines(&mut editor, 1, 10);
+        editor.fold();
+        editor.offset.y = 11;
+        assert_eq!(editor.scroll_position(), "18%");
+        select_lines(&mut editor, 1, 1);
+        editor.unfold();
+
+        // 自动换行时每行按占用的屏幕行数计入
+        for row in editor.rows.write().unwrap().iter_mut() {
+            row.set_string("x".repeat(100));
+        }
+        editor.word_wrap = true;
+        editor.offset.y = 10;
+        assert_eq!(editor.scroll_position(), "40%");
+        editor.offset.y = 25;
+        assert_eq!(editor.scroll_position(), "Bot");
+
+        *editor.rows.write().unwrap() = vec![row("short")];
+        editor.offset.y = 0;
+        assert_eq!(editor.scroll_position(), "All");
+    }
+}